@@ -0,0 +1,68 @@
+use anyhow::{Context, Result};
+use clap::Parser;
+use std::{
+    fs::{create_dir_all, File},
+    path::{Path, PathBuf},
+};
+
+use crate::utils;
+use compare::{Comparison, Scenario};
+
+// Entry point for `compare` CLI command.
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+pub struct CompareCommand {
+    /// Output directories to compare, the first is used as the baseline
+    #[arg(required = true, num_args = 2..)]
+    dirs: Vec<PathBuf>,
+    /// Output directory path for comparison csvs
+    #[clap(short, long, default_value = "comparison")]
+    outpath: PathBuf,
+}
+
+impl CompareCommand {
+    pub fn run(&self) -> Result<()> {
+        let spinner = utils::default_spinner();
+        spinner.set_message(format!("[1/2] Loading {} scenarios...", self.dirs.len()));
+        let scenarios = self
+            .dirs
+            .iter()
+            .map(|dir| Scenario::load(dir).context(format!("failed to load '{}'", dir.display())))
+            .collect::<Result<Vec<Scenario>>>()?;
+        spinner.finish_with_message(format!(
+            "[1/2] Completed loading {} scenarios",
+            scenarios.len()
+        ));
+
+        let spinner = utils::default_spinner();
+        spinner.set_message(format!(
+            "[2/2] Writing comparison to '{}'...",
+            self.outpath.display()
+        ));
+        create_dir_all(&self.outpath)?;
+        let comparison = Comparison::new(&scenarios)?;
+        let mut deltas_wtr = writer(&self.outpath.join("comparison.csv"))?;
+        for record in comparison.deltas() {
+            deltas_wtr.serialize(record)?;
+        }
+        deltas_wtr.flush()?;
+        let mut agents_wtr = writer(&self.outpath.join("agents.csv"))?;
+        for record in comparison.agent_changes() {
+            agents_wtr.serialize(record)?;
+        }
+        agents_wtr.flush()?;
+        spinner.finish_with_message(format!(
+            "[2/2] Completed writing comparison to '{}'",
+            self.outpath.display()
+        ));
+
+        println!("{}", comparison);
+        Ok(())
+    }
+}
+
+fn writer(path: &Path) -> Result<csv::Writer<File>> {
+    let file =
+        File::create(path).context(format!("unable to create out file '{}'", path.display()))?;
+    Ok(csv::Writer::from_writer(file))
+}
@@ -0,0 +1,40 @@
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+use configuration::config::Config;
+
+// Entry point for `config` CLI command.
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+pub struct ConfigCommand {
+    #[command(subcommand)]
+    command: ConfigSubcommand,
+}
+
+#[derive(Subcommand)]
+enum ConfigSubcommand {
+    /// Print the fully resolved config (including defaults, base configs and overrides)
+    Show {
+        /// Config path
+        #[clap(short, long)]
+        config: Option<PathBuf>,
+        /// Override config values using 'key.path=value' (can be used multiple times)
+        #[arg(long, value_name = "KEY=VALUE")]
+        set: Vec<String>,
+    },
+}
+
+impl ConfigCommand {
+    pub fn run(&self) -> Result<()> {
+        match &self.command {
+            ConfigSubcommand::Show { config, set } => {
+                let config = Config::build(config.as_deref(), set)?;
+                config.valid()?;
+                let yaml = serde_yaml::to_string(&config).context("failed to write config")?;
+                print!("{}", yaml);
+                Ok(())
+            }
+        }
+    }
+}
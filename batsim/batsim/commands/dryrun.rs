@@ -3,7 +3,7 @@ use clap::Parser;
 use indicatif::HumanCount;
 use std::{fs::File, io::BufReader, path::PathBuf};
 
-use configuration::{config::Config, handler::AgentConfig, sampler};
+use configuration::{assignments::Assignments, config::Config, handler::AgentConfig, sampler};
 use tracer::Population;
 
 use crate::utils;
@@ -15,9 +15,18 @@ pub struct DryrunCommand {
     /// Config path
     #[clap(short, long)]
     config: Option<PathBuf>,
+    /// Override config values using 'key.path=value' (can be used multiple times)
+    #[arg(long, value_name = "KEY=VALUE")]
+    set: Vec<String>,
     /// Path to traces file
     #[clap(short, long, default_value = "traces.trc")]
     trace_path: PathBuf,
+    /// Optional specs csv (as written by a previous run) used to fix agent spec assignments
+    #[arg(long, value_name = "SPECS")]
+    assignments: Option<PathBuf>,
+    /// Merge the traces of persons sharing a vehicle, given by a person attribute (such as a household id)
+    #[arg(long, value_name = "ATTRIBUTE")]
+    vehicles: Option<String>,
     /// Output file path
     #[clap(short, long, default_value = "config.csv")]
     output: PathBuf,
@@ -29,10 +38,7 @@ pub struct DryrunCommand {
 impl DryrunCommand {
     pub fn run(&self) -> Result<()> {
         // Load config
-        let config = match &self.config {
-            Some(path) => Config::load(path),
-            None => Ok(Config::default()),
-        }?;
+        let config = Config::build(self.config.as_deref(), &self.set)?;
         config.valid()?;
 
         // Prepare input files
@@ -48,8 +54,11 @@ impl DryrunCommand {
         // Prepare output files
         let mut specs_writer = csv::Writer::from_writer(out_file);
 
-        //Rng
-        let mut rng = sampler::new(config.seed);
+        // Fixed assignments
+        let assignments = match &self.assignments {
+            Some(path) => Some(Assignments::load(path, &config)?),
+            None => None,
+        };
 
         // Load population
         let spinner = utils::default_spinner();
@@ -63,6 +72,13 @@ impl DryrunCommand {
             HumanCount(population.len() as u64)
         ));
 
+        // Merge shared vehicles
+        let (population, _) = utils::vehicles(population, self.vehicles.as_deref())?;
+        utils::unassigned(assignments.as_ref(), &population);
+
+        // Sampler (selecting agents for any spec quotas)
+        let sampler = sampler::new(config.seed).with_quotas(&config, &population);
+
         // Write Agent Configurations
         let progress_bar = utils::default_progress_bar(population.len() as u64);
         progress_bar.set_message(format!(
@@ -71,7 +87,8 @@ impl DryrunCommand {
         ));
         for (pid, person) in population.into_iter() {
             progress_bar.inc(1);
-            let agent_config = AgentConfig::build(&config, pid, person, &mut rng);
+            let agent_config =
+                AgentConfig::assign(&config, pid, person, &sampler, assignments.as_ref())?;
             specs_writer
                 .serialize(agent_config.to_record())
                 .context(format!("failed to write specs for pid: '{}'", pid))?;
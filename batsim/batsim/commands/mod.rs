@@ -1,11 +1,17 @@
+pub mod compare;
+pub mod config;
 pub mod dryrun;
 pub mod optimise;
 pub mod peek;
 pub mod run;
+pub mod sweep;
 pub mod trace;
 
+pub use crate::CompareCommand;
+pub use crate::ConfigCommand;
 pub use crate::DryrunCommand;
 pub use crate::OptimiseCommand;
 pub use crate::PeekCommand;
 pub use crate::RunCommand;
+pub use crate::SweepCommand;
 pub use crate::TracerCommand;
@@ -7,14 +7,12 @@ use std::{
     path::PathBuf,
 };
 
+use crate::outputs::{Manifest, OutputArgs, OutputHandler, RunSummary, Timer};
 use crate::utils;
-use configuration::{config::Config, handler::AgentConfig, sampler};
-use optimise::handler::OptimiseHandler;
-use simulate::{
-    record::{AgentSimulationRecord, EventsRecord},
-    results::SummaryHandler,
-};
-use tracer::Population;
+use configuration::{assignments::Assignments, config::Config, handler::AgentConfig, sampler};
+use optimise::{handler::OptimiseHandler, shared};
+use simulate::record::AgentSimulationRecord;
+use tracer::{Network, Population};
 
 // Entry point for `optmimise` CLI command.
 #[derive(Parser)]
@@ -23,24 +21,38 @@ pub struct OptimiseCommand {
     /// Config path
     #[clap(short, long)]
     config: Option<PathBuf>,
+    /// Override config values using 'key.path=value' (can be used multiple times)
+    #[arg(long, value_name = "KEY=VALUE")]
+    set: Vec<String>,
     /// Path to traces file
     #[clap(short, long, default_value = "traces.trc")]
     trace_path: PathBuf,
+    /// Optional specs csv (as written by a previous run) used to fix agent spec assignments
+    #[arg(long, value_name = "SPECS")]
+    assignments: Option<PathBuf>,
+    /// Merge the traces of persons sharing a vehicle, given by a person attribute (such as a household id)
+    #[arg(long, value_name = "ATTRIBUTE")]
+    vehicles: Option<String>,
     /// Output directory path
     #[clap(short, long, default_value = "outputs")]
     outpath: PathBuf,
     /// Read traces from human readable json format
     #[arg(short, long)]
     json: bool,
+    /// Optional MATSim network path, used to write link geometries with '--geojson' (required)
+    #[clap(short, long, required_if_eq("geojson", "true"))]
+    network: Option<PathBuf>,
+    #[command(flatten)]
+    outputs: OutputArgs,
 }
 impl OptimiseCommand {
     pub fn run(&self) -> Result<()> {
+        let mut timer = Timer::start();
+
         // Load config
-        let config = match &self.config {
-            Some(path) => Config::load(path),
-            None => Ok(Config::default()),
-        }?;
+        let mut config = Config::build(self.config.as_deref(), &self.set)?;
         config.valid()?;
+        config.seed = Some(sampler::resolve_seed(config.seed));
 
         // Prepare input files
         let traces_file = File::open(&self.trace_path).context(format!(
@@ -49,39 +61,41 @@ impl OptimiseCommand {
         ))?;
         let traces_reader = BufReader::new(traces_file);
 
-        // Prepare output paths
-        create_dir_all(&self.outpath)?;
-        let mut specs_path = self.outpath.clone();
-        specs_path.push("specs.csv");
-        let mut report_path = self.outpath.clone();
-        report_path.push("report.csv");
-        let mut charge_events_path = self.outpath.clone();
-        charge_events_path.push("events.csv");
+        // Load optional network
+        let network = match &self.network {
+            Some(path) => {
+                let mut network_reader = xml::reader(path)?;
+                Some(Network::from_xml(&mut network_reader).context("failed to load network")?)
+            }
+            None => None,
+        };
 
         // Prepare output files
-        let specs_file = File::create(&specs_path).context(format!(
-            // filter records
-            "unable to create out file for filters '{}'",
-            specs_path.display()
-        ))?;
-        let mut specs_wtr = csv::Writer::from_writer(specs_file);
-
-        let report_file = File::create(&report_path).context(format!(
-            // agent records
-            "unable to create out file '{}'",
-            report_path.display()
-        ))?;
-        let mut record_wtr = csv::Writer::from_writer(report_file);
+        create_dir_all(&self.outpath)?;
 
-        let events_file = File::create(&charge_events_path).context(format!(
-            // events
-            "unable to create out file '{}'",
-            charge_events_path.display()
-        ))?;
-        let mut events_wtr = csv::Writer::from_writer(events_file);
+        // Record inputs
+        let mut manifest = Manifest::new("optimise", &config, self.config.as_deref());
+        manifest.add_input(&self.trace_path)?;
+        if let Some(path) = &self.network {
+            manifest.add_input(path)?;
+        }
+        for path in [&self.config, &self.assignments, &self.outputs.zones]
+            .into_iter()
+            .flatten()
+        {
+            manifest.add_input(path)?;
+        }
+        manifest.write(&self.outpath)?;
+        let mut outputs = OutputHandler::new(&self.outpath, &self.outputs, &config)?;
+        if let Some(network) = &network {
+            outputs.add_network(network);
+        }
 
-        //Rng
-        let mut rng = sampler::new(config.seed);
+        // Fixed assignments
+        let assignments = match &self.assignments {
+            Some(path) => Some(Assignments::load(path, &config)?),
+            None => None,
+        };
 
         // Load traces
         let spinner = utils::default_spinner();
@@ -94,27 +108,49 @@ impl OptimiseCommand {
             "[1/3] Completed loading traces ({} persons/plans)",
             HumanCount(population.len() as u64)
         ));
+        timer.stage("load");
+
+        // Merge shared vehicles
+        let (population, drivers) = utils::vehicles(population, self.vehicles.as_deref())?;
+        utils::unassigned(assignments.as_ref(), &population);
+
+        // Sampler (selecting agents for any spec quotas)
+        let sampler = sampler::new(config.seed).with_quotas(&config, &population);
 
         // Optimisation
-        let optimiser: OptimiseHandler = OptimiseHandler::new(&config);
+        let optimiser: OptimiseHandler = OptimiseHandler::new(&config).with_soc(self.outputs.soc);
         let progress_bar = utils::default_progress_bar(population.len() as u64);
         progress_bar.set_message("[2/3] Optimising agent charging...");
 
-        let sim_records: Vec<AgentSimulationRecord> = population
+        let agents = population
             .into_iter()
             .map(|(pid, person)| {
-                progress_bar.inc(1);
-                let agent_config = AgentConfig::build(&config, pid, person, &mut rng);
-                specs_wtr
-                    .serialize(agent_config.to_record())
+                let agent_config =
+                    AgentConfig::assign(&config, pid, person, &sampler, assignments.as_ref())?;
+                outputs
+                    .add_specs(pid, person, &agent_config)
                     .context(format!("failed to write specs for pid: '{}'", pid))?;
-                optimiser
-                    .optimise(&config, pid, person, agent_config)
-                    .context(format!("optimiser failed at '{pid}'"))
+                Ok((pid, person, agent_config))
             })
             .collect::<Result<Vec<_>>>()?;
-
-        specs_wtr.flush()?;
+        let mut sim_records: Vec<AgentSimulationRecord> = Vec::new();
+        for agents in shared::group(agents) {
+            progress_bar.inc(agents.len() as u64);
+            let (sims, charger) = optimiser.optimise_shared(&config, agents)?;
+            if let Some(charger) = charger {
+                outputs.add_charger(&charger)?;
+            }
+            for mut sim in sims {
+                outputs.assign_zones(&mut sim);
+                if let Some(drivers) = &drivers {
+                    let pid = sim.pid();
+                    sim.assign_drivers(|start_time, component| {
+                        drivers.driver(pid, start_time, component).map(String::from)
+                    });
+                }
+                sim_records.push(sim);
+            }
+        }
 
         progress_bar.set_length(0);
         progress_bar.tick();
@@ -122,6 +158,7 @@ impl OptimiseCommand {
             "[2/3] Completed {} optimised battery simulations",
             sim_records.len()
         ));
+        timer.stage("optimise");
 
         // Write Results
         let progress_bar = utils::default_progress_bar(sim_records.len() as u64);
@@ -130,33 +167,22 @@ impl OptimiseCommand {
             &self.outpath.display()
         ));
 
-        let mut summary = SummaryHandler::new(&config);
-
         for sim in sim_records.iter() {
             progress_bar.inc(1);
-
-            let record = sim.to_record();
-            record_wtr
-                .serialize(&record)
-                .context(format!("failed to write record pid '{}'", record.pid))?;
-            summary.add_leak(record.leak.unwrap());
-            for day in sim.slice() {
-                for event in day {
-                    summary.add(event);
-                    events_wtr
-                        .serialize(event)
-                        .context(format!("failed to write event for pid '{}'", record.pid))?;
-                }
-            }
+            outputs.add(sim)?;
         }
-        record_wtr.flush()?;
-        events_wtr.flush()?;
-        summary.finalise();
+        let (summary, groups) = outputs.finish()?;
         progress_bar.finish_with_message(format!(
             "[3/3] Completed writing results to '{}'",
             self.outpath.display()
         ));
+        timer.stage("write");
+        RunSummary::new(&summary, groups.as_ref(), sim_records.len(), &timer)
+            .write(&self.outpath)?;
         println!("{}", summary);
+        if let Some(groups) = &groups {
+            println!("{}", groups);
+        }
 
         Ok(())
     }
@@ -4,11 +4,11 @@ use indicatif::HumanCount;
 use std::fs::{create_dir_all, File};
 use std::path::{Path, PathBuf};
 
+use crate::outputs::{Manifest, OutputArgs, OutputHandler, RunSummary, Timer};
 use crate::utils;
-use configuration::{config::Config, handler::AgentConfig, sampler};
-use optimise::handler::OptimiseHandler;
-use simulate::{record::EventsRecord, results::SummaryHandler};
-use tracer::{self, Network, Population};
+use configuration::{assignments::Assignments, config::Config, handler::AgentConfig, sampler};
+use optimise::{handler::OptimiseHandler, shared};
+use tracer::{self, network, Elevations, Network, Population};
 use xml;
 
 #[derive(Parser)]
@@ -17,6 +17,9 @@ pub struct RunCommand {
     /// Config path
     #[clap(short, long)]
     config: Option<PathBuf>,
+    /// Override config values using 'key.path=value' (can be used multiple times)
+    #[arg(long, value_name = "KEY=VALUE")]
+    set: Vec<String>,
     /// MATSim output directory
     #[clap(short, long, default_value = "tests/data")]
     dir: PathBuf,
@@ -29,25 +32,37 @@ pub struct RunCommand {
     /// Name of events file
     #[clap(short, long, default_value = "output_events.xml")]
     events: PathBuf,
+    /// Optional node elevations csv (node 'id' and 'z' columns), used for link gradients in
+    /// place of network node 'z' values
+    #[arg(long, value_name = "CSV")]
+    elevations: Option<PathBuf>,
     /// Path to traces file
     #[clap(short, long, default_value = "traces.trc")]
     trace_path: PathBuf,
+    /// Optional specs csv (as written by a previous run) used to fix agent spec assignments
+    #[arg(long, value_name = "SPECS")]
+    assignments: Option<PathBuf>,
+    /// Merge the traces of persons sharing a vehicle, given by a person attribute (such as a household id)
+    #[arg(long, value_name = "ATTRIBUTE")]
+    vehicles: Option<String>,
     /// Output directory path
     #[clap(short, long, default_value = "outputs")]
     outpath: PathBuf,
     /// Write traces to human readable json format
     #[arg(short, long)]
     json: bool,
+    #[command(flatten)]
+    outputs: OutputArgs,
 }
 
 impl RunCommand {
     pub fn run(&self) -> Result<()> {
+        let mut timer = Timer::start();
+
         // Load config
-        let config = match &self.config {
-            Some(path) => Config::load(path),
-            None => Ok(Config::default()),
-        }?;
+        let mut config = Config::build(self.config.as_deref(), &self.set)?;
         config.valid()?;
+        config.seed = Some(sampler::resolve_seed(config.seed));
 
         // Prepare input paths
         let network_path = self.dir.join(&self.network);
@@ -62,44 +77,44 @@ impl RunCommand {
         // Prepare output paths
         create_dir_all(&self.outpath)?;
         let traces_path = self.outpath.join(Path::new("traces.trc"));
-        let mut specs_path = self.outpath.clone();
-        specs_path.push("specs.csv");
-        let mut report_path = self.outpath.clone();
-        report_path.push("report.csv");
-        let mut charge_events_path = self.outpath.clone();
-        charge_events_path.push("events.csv");
+
+        // Record inputs
+        let mut manifest = Manifest::new("run", &config, self.config.as_deref());
+        for path in [&network_path, &population_path, &events_path] {
+            manifest.add_input(path)?;
+        }
+        for path in [
+            &self.config,
+            &self.assignments,
+            &self.elevations,
+            &self.outputs.zones,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            manifest.add_input(path)?;
+        }
+        manifest.write(&self.outpath)?;
 
         // Prepare output files
         let traces_file = File::create(&traces_path)?;
+        let mut outputs = OutputHandler::new(&self.outpath, &self.outputs, &config)?;
 
-        let specs_file = File::create(&specs_path).context(format!(
-            // filter records
-            "unable to create out file for filters '{}'",
-            specs_path.display()
-        ))?;
-        let mut specs_wtr = csv::Writer::from_writer(specs_file);
-
-        let report_file = File::create(&report_path).context(format!(
-            // agent records
-            "unable to create out file '{}'",
-            report_path.display()
-        ))?;
-        let mut record_wtr = csv::Writer::from_writer(report_file);
-
-        let events_file = File::create(&charge_events_path).context(format!(
-            // events
-            "unable to create out file '{}'",
-            charge_events_path.display()
-        ))?;
-        let mut events_wtr = csv::Writer::from_writer(events_file);
-
-        //Rng
-        let mut rng = sampler::new(config.seed);
+        // Fixed assignments
+        let assignments = match &self.assignments {
+            Some(path) => Some(Assignments::load(path, &config)?),
+            None => None,
+        };
 
         // Load network
         let spinner = utils::default_spinner();
         spinner.set_message("[1/6] Loading MATSim network...");
-        let network = Network::from_xml(&mut network_reader).context("failed to load network")?;
+        let elevations = match &self.elevations {
+            Some(path) => network::load_elevations(path)?,
+            None => Elevations::new(),
+        };
+        let network = Network::from_xml_with_elevations(&mut network_reader, &elevations)
+            .context("failed to load network")?;
         spinner.finish_with_message(format!(
             "[1/6] Completed loading network ({} links)",
             HumanCount(network.links.len() as u64)
@@ -121,8 +136,10 @@ impl RunCommand {
         let mut tracer = tracer::TraceHandler::new();
         let mut events = tracer::MATSimEventsReader::from_xml(events_reader);
         tracer.add_network(&network);
+        outputs.add_network(&network);
         tracer.add_traces(&mut population, &mut events)?;
         progress.finish_with_message("[3/6] Completed building all traces for population");
+        timer.stage("trace");
 
         // Write Traces
         let spinner = utils::default_spinner();
@@ -135,27 +152,49 @@ impl RunCommand {
             "[4/6] Completed writing traces to {}",
             &traces_path.display()
         ));
+        timer.stage("write_traces");
+
+        // Merge shared vehicles
+        let (population, drivers) = utils::vehicles(population, self.vehicles.as_deref())?;
+        utils::unassigned(assignments.as_ref(), &population);
+
+        // Sampler (selecting agents for any spec quotas)
+        let sampler = sampler::new(config.seed).with_quotas(&config, &population);
 
         // Optimisation
-        let optimiser: OptimiseHandler = OptimiseHandler::new(&config);
+        let optimiser: OptimiseHandler = OptimiseHandler::new(&config).with_soc(self.outputs.soc);
         let progress_bar = utils::default_progress_bar(population.len() as u64);
         progress_bar.set_message("[5/6] Optimising agent charging...");
 
-        let sim_records = population
+        let agents = population
             .into_iter()
             .map(|(pid, person)| {
-                progress_bar.inc(1);
-                let agent_config = AgentConfig::build(&config, pid, person, &mut rng);
-                specs_wtr
-                    .serialize(agent_config.to_record())
+                let agent_config =
+                    AgentConfig::assign(&config, pid, person, &sampler, assignments.as_ref())?;
+                outputs
+                    .add_specs(pid, person, &agent_config)
                     .context(format!("failed to write specs for pid: '{}'", pid))?;
-                optimiser
-                    .optimise(&config, pid, person, agent_config)
-                    .context(format!("optimiser failed at '{pid}'"))
+                Ok((pid, person, agent_config))
             })
             .collect::<Result<Vec<_>>>()?;
-
-        specs_wtr.flush()?;
+        let mut sim_records = Vec::new();
+        for agents in shared::group(agents) {
+            progress_bar.inc(agents.len() as u64);
+            let (sims, charger) = optimiser.optimise_shared(&config, agents)?;
+            if let Some(charger) = charger {
+                outputs.add_charger(&charger)?;
+            }
+            for mut sim in sims {
+                outputs.assign_zones(&mut sim);
+                if let Some(drivers) = &drivers {
+                    let pid = sim.pid();
+                    sim.assign_drivers(|start_time, component| {
+                        drivers.driver(pid, start_time, component).map(String::from)
+                    });
+                }
+                sim_records.push(sim);
+            }
+        }
 
         progress_bar.set_length(0);
         progress_bar.tick();
@@ -163,6 +202,7 @@ impl RunCommand {
             "[5/6] Completed {} optimised battery simulations",
             sim_records.len()
         ));
+        timer.stage("optimise");
 
         // Write Results
         let progress_bar = utils::default_progress_bar(sim_records.len() as u64);
@@ -171,33 +211,22 @@ impl RunCommand {
             &self.outpath.display()
         ));
 
-        let mut summary = SummaryHandler::new(&config);
-
         for sim in sim_records.iter() {
             progress_bar.inc(1);
-
-            let record = sim.to_record();
-            record_wtr
-                .serialize(&record)
-                .context(format!("failed to write record pid '{}'", record.pid))?;
-            summary.add_leak(record.leak.unwrap());
-            for day in sim.slice() {
-                for event in day {
-                    summary.add(event);
-                    events_wtr
-                        .serialize(event)
-                        .context(format!("failed to write event for pid '{}'", record.pid))?;
-                }
-            }
+            outputs.add(sim)?;
         }
-        record_wtr.flush()?;
-        events_wtr.flush()?;
-        summary.finalise();
+        let (summary, groups) = outputs.finish()?;
         progress_bar.finish_with_message(format!(
             "[6/6] Completed writing results to '{}'",
             self.outpath.display()
         ));
+        timer.stage("write");
+        RunSummary::new(&summary, groups.as_ref(), sim_records.len(), &timer)
+            .write(&self.outpath)?;
         println!("{}", summary);
+        if let Some(groups) = &groups {
+            println!("{}", groups);
+        }
 
         Ok(())
     }
@@ -0,0 +1,408 @@
+use anyhow::{bail, Context, Result};
+use clap::{ArgGroup, Parser};
+use indicatif::HumanCount;
+use serde_yaml::{Mapping, Value};
+use std::{
+    fs::{create_dir_all, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use crate::outputs::{InputRecord, Manifest, OutputArgs, OutputHandler, RunSummary, Timer};
+use crate::utils;
+use configuration::{
+    assignments::Assignments, config::Config, handler::AgentConfig, overrides, sampler,
+};
+use optimise::{handler::OptimiseHandler, shared};
+use simulate::{record::AgentSimulationRecord, results::SummaryRecord};
+use tracer::{Drivers, Network, Population};
+
+// Entry point for `sweep` CLI command.
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+#[command(group(ArgGroup::new("scenarios").required(true).args(["grid", "configs"])))]
+pub struct SweepCommand {
+    /// Base config path, parameter grid values are applied to this config
+    #[clap(short, long, conflicts_with = "configs")]
+    config: Option<PathBuf>,
+    /// Override base config values using 'key.path=value' (can be used multiple times)
+    #[arg(long, value_name = "KEY=VALUE")]
+    set: Vec<String>,
+    /// Parameter grid path, a yaml mapping of config key paths to lists of values
+    #[clap(short, long)]
+    grid: Option<PathBuf>,
+    /// Config paths, each config is run as a scenario
+    #[arg(long, num_args = 1..)]
+    configs: Vec<PathBuf>,
+    /// Path to traces file
+    #[clap(short, long, default_value = "traces.trc")]
+    trace_path: PathBuf,
+    /// Optional specs csv (as written by a previous run) used to fix agent spec assignments
+    #[arg(long, value_name = "SPECS")]
+    assignments: Option<PathBuf>,
+    /// Merge the traces of persons sharing a vehicle, given by a person attribute (such as a household id)
+    #[arg(long, value_name = "ATTRIBUTE")]
+    vehicles: Option<String>,
+    /// Output directory path, each scenario is written to a subdirectory
+    #[clap(short, long, default_value = "sweep")]
+    outpath: PathBuf,
+    /// Read traces from human readable json format
+    #[arg(short, long)]
+    json: bool,
+    /// Number of scenarios to run in parallel (not available with '--sqlite')
+    #[arg(short, long, default_value = "1", value_parser = clap::value_parser!(u32).range(1..))]
+    parallel: u32,
+    /// Optional MATSim network path, used to write link geometries with '--geojson' (required)
+    #[clap(short, long, required_if_eq("geojson", "true"))]
+    network: Option<PathBuf>,
+    #[command(flatten)]
+    outputs: OutputArgs,
+}
+
+/// A single sweep scenario, with the grid values (or config path) used to build its config
+struct Scenario {
+    name: String,
+    params: Vec<String>,
+    config_path: Option<PathBuf>,
+    config: Config,
+}
+
+impl SweepCommand {
+    pub fn run(&self) -> Result<()> {
+        if self.parallel > 1 && self.outputs.sqlite.is_some() {
+            // each scenario holds a write transaction on the database until it is finished
+            bail!("'--sqlite' can not be used with '--parallel' greater than 1")
+        }
+
+        // Build scenario configs, scenarios without a seed share a single generated seed
+        let seed = sampler::resolve_seed(None);
+        let (param_keys, mut scenarios) = match &self.grid {
+            Some(grid) => self.grid_scenarios(grid)?,
+            None => self.config_scenarios()?,
+        };
+        for scenario in scenarios.iter_mut() {
+            scenario
+                .config
+                .valid()
+                .context(format!("invalid config for scenario '{}'", scenario.name))?;
+            scenario.config.seed = scenario.config.seed.or(Some(seed));
+        }
+
+        // Record shared inputs (hashed once for all scenarios)
+        let mut inputs = vec![InputRecord::new(&self.trace_path)?];
+        for path in [
+            &self.network,
+            &self.grid,
+            &self.assignments,
+            &self.outputs.zones,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            inputs.push(InputRecord::new(path)?);
+        }
+
+        // Load optional network once
+        let network = match &self.network {
+            Some(path) => {
+                let mut network_reader = xml::reader(path)?;
+                Some(Network::from_xml(&mut network_reader).context("failed to load network")?)
+            }
+            None => None,
+        };
+
+        // Load traces once
+        let traces_file = File::open(&self.trace_path).context(format!(
+            "unable to open file '{}'",
+            self.trace_path.display()
+        ))?;
+        let spinner = utils::default_spinner();
+        spinner.set_message(format!(
+            "[1/2] Loading traces from {}...",
+            &self.trace_path.display()
+        ));
+        let population = Population::deserialise(BufReader::new(traces_file), self.json)?;
+        spinner.finish_with_message(format!(
+            "[1/2] Completed loading traces ({} persons/plans)",
+            HumanCount(population.len() as u64)
+        ));
+        let (population, drivers) = utils::vehicles(population, self.vehicles.as_deref())?;
+
+        // Agents missing from fixed assignments are the same for each scenario
+        if let (Some(path), Some(scenario)) = (&self.assignments, scenarios.first()) {
+            let assignments = Assignments::load(path, &scenario.config)?;
+            utils::unassigned(Some(&assignments), &population);
+        }
+
+        // Run scenarios, using a pool of workers that take the next scenario until all are done
+        create_dir_all(&self.outpath)?;
+        let progress_bar = utils::default_progress_bar(scenarios.len() as u64);
+        progress_bar.set_message(format!("[2/2] Running {} scenarios...", scenarios.len()));
+        let next = AtomicUsize::new(0);
+        let workers = (self.parallel as usize).min(scenarios.len());
+        let mut results = thread::scope(|scope| {
+            let handles: Vec<_> = (0..workers)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut results = Vec::new();
+                        loop {
+                            let i = next.fetch_add(1, Ordering::SeqCst);
+                            let Some(scenario) = scenarios.get(i) else {
+                                break;
+                            };
+                            let result = self
+                                .run_scenario(
+                                    scenario,
+                                    &population,
+                                    drivers.as_ref(),
+                                    network.as_ref(),
+                                    &inputs,
+                                )
+                                .context(format!("scenario '{}' failed", scenario.name));
+                            progress_bar.inc(1);
+                            results.push((i, result));
+                        }
+                        results
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("sweep worker panicked"))
+                .collect::<Vec<_>>()
+        });
+        results.sort_by_key(|(i, _)| *i);
+        let summaries = results
+            .into_iter()
+            .map(|(_, result)| result)
+            .collect::<Result<Vec<_>>>()?;
+        progress_bar.finish_with_message(format!(
+            "[2/2] Completed {} scenarios, written to '{}'",
+            scenarios.len(),
+            self.outpath.display()
+        ));
+
+        // Combined summary
+        let table = summary_table(&param_keys, &scenarios, &summaries)?;
+        let mut wtr = csv::Writer::from_path(self.outpath.join("summary.csv"))
+            .context("unable to create sweep summary")?;
+        for row in &table {
+            wtr.write_record(row)?;
+        }
+        wtr.flush()?;
+        println!("\n{}", format_table(&table));
+        Ok(())
+    }
+
+    /// Scenarios for every combination of grid values, applied to the base config
+    fn grid_scenarios(&self, grid_path: &Path) -> Result<(Vec<String>, Vec<Scenario>)> {
+        let mut base = match &self.config {
+            Some(path) => Config::load_value(path)?,
+            None => Value::Null,
+        };
+        Config::apply_overrides(&mut base, &self.set)?;
+        let grid: Mapping = serde_yaml::from_reader(
+            File::open(grid_path)
+                .context(format!("unable to open grid '{}'", grid_path.display()))?,
+        )
+        .context(format!("failed to parse grid '{}'", grid_path.display()))?;
+        let mut keys = Vec::new();
+        let mut combinations: Vec<Vec<Value>> = vec![Vec::new()];
+        for (key, values) in grid {
+            let Some(key) = key.as_str() else {
+                bail!("grid keys must be config key paths")
+            };
+            keys.push(key.to_string());
+            let values = match values {
+                Value::Sequence(values) if !values.is_empty() => values,
+                Value::Sequence(_) => bail!("grid key '{}' has no values", key),
+                value => vec![value],
+            };
+            combinations = combinations
+                .into_iter()
+                .flat_map(|combination| {
+                    values.iter().map(move |value| {
+                        let mut combination = combination.clone();
+                        combination.push(value.clone());
+                        combination
+                    })
+                })
+                .collect();
+        }
+        let scenarios = combinations
+            .into_iter()
+            .enumerate()
+            .map(|(i, values)| {
+                let mut config = base.clone();
+                for (key, value) in keys.iter().zip(values.iter()) {
+                    overrides::set(&mut config, key, value.clone())?;
+                }
+                let params = values.iter().map(yaml_string).collect();
+                Ok(Scenario {
+                    name: format!("scenario_{:03}", i),
+                    params,
+                    config_path: self.config.clone(),
+                    config: Config::from_value(config)?,
+                })
+            })
+            .collect::<Result<Vec<Scenario>>>()?;
+        Ok((keys, scenarios))
+    }
+
+    /// Scenarios for each config, named by config file name
+    fn config_scenarios(&self) -> Result<(Vec<String>, Vec<Scenario>)> {
+        let mut scenarios: Vec<Scenario> = Vec::new();
+        for path in &self.configs {
+            let stem = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let name = match scenarios.iter().any(|scenario| scenario.name == stem) {
+                true => format!("{}_{}", stem, scenarios.len()),
+                false => stem,
+            };
+            scenarios.push(Scenario {
+                name,
+                params: vec![path.display().to_string()],
+                config_path: Some(path.clone()),
+                config: Config::build(Some(path), &self.set)?,
+            });
+        }
+        Ok((vec!["config".to_string()], scenarios))
+    }
+
+    /// Optimise and write outputs for a single scenario, returning the number of agents and totals
+    fn run_scenario(
+        &self,
+        scenario: &Scenario,
+        population: &Population,
+        drivers: Option<&Drivers>,
+        network: Option<&Network>,
+        inputs: &[InputRecord],
+    ) -> Result<(usize, SummaryRecord)> {
+        let mut timer = Timer::start();
+        let config = &scenario.config;
+        let outpath = self.outpath.join(&scenario.name);
+        create_dir_all(&outpath)?;
+
+        let mut manifest = Manifest::new("sweep", config, scenario.config_path.as_deref());
+        manifest.inputs.extend(inputs.iter().cloned());
+        if let Some(path) = &scenario.config_path {
+            manifest.add_input(path)?;
+        }
+        manifest.write(&outpath)?;
+        let mut outputs = OutputHandler::new(&outpath, &self.outputs, config)?;
+        if let Some(network) = network {
+            outputs.add_network(network);
+        }
+
+        let sampler = sampler::new(config.seed).with_quotas(config, population);
+        let assignments = match &self.assignments {
+            Some(path) => Some(Assignments::load(path, config)?),
+            None => None,
+        };
+        let optimiser = OptimiseHandler::new(config).with_soc(self.outputs.soc);
+        let agents = population
+            .into_iter()
+            .map(|(pid, person)| {
+                let agent_config =
+                    AgentConfig::assign(config, pid, person, &sampler, assignments.as_ref())?;
+                outputs
+                    .add_specs(pid, person, &agent_config)
+                    .context(format!("failed to write specs for pid: '{}'", pid))?;
+                Ok((pid, person, agent_config))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut sim_records: Vec<AgentSimulationRecord> = Vec::new();
+        for agents in shared::group(agents) {
+            let (sims, charger) = optimiser.optimise_shared(config, agents)?;
+            if let Some(charger) = charger {
+                outputs.add_charger(&charger)?;
+            }
+            for mut sim in sims {
+                outputs.assign_zones(&mut sim);
+                if let Some(drivers) = &drivers {
+                    let pid = sim.pid();
+                    sim.assign_drivers(|start_time, component| {
+                        drivers.driver(pid, start_time, component).map(String::from)
+                    });
+                }
+                sim_records.push(sim);
+            }
+        }
+        timer.stage("optimise");
+
+        for sim in sim_records.iter() {
+            outputs.add(sim)?;
+        }
+        let (summary, groups) = outputs.finish()?;
+        timer.stage("write");
+        RunSummary::new(&summary, groups.as_ref(), sim_records.len(), &timer).write(&outpath)?;
+        Ok((sim_records.len(), summary.to_record()))
+    }
+}
+
+fn yaml_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        value => serde_yaml::to_string(value)
+            .unwrap_or_default()
+            .trim()
+            .to_string(),
+    }
+}
+
+/// Combined summary rows (including header) of scenario name, params, agents and totals
+fn summary_table(
+    param_keys: &[String],
+    scenarios: &[Scenario],
+    summaries: &[(usize, SummaryRecord)],
+) -> Result<Vec<Vec<String>>> {
+    let mut table = Vec::new();
+    let mut header = vec!["scenario".to_string()];
+    header.extend(param_keys.iter().cloned());
+    header.push("agents".to_string());
+    for (scenario, (agents, totals)) in scenarios.iter().zip(summaries.iter()) {
+        let totals = serde_json::to_value(totals)?;
+        let totals = totals.as_object().context("summary is not a record")?;
+        if table.is_empty() {
+            header.extend(totals.keys().cloned());
+            table.push(header.clone());
+        }
+        let mut row = vec![scenario.name.clone()];
+        row.extend(scenario.params.iter().cloned());
+        row.push(agents.to_string());
+        row.extend(totals.values().map(|value| match value.as_f64() {
+            Some(value) => format!("{:.2}", value),
+            None => value.to_string(),
+        }));
+        table.push(row);
+    }
+    Ok(table)
+}
+
+fn format_table(table: &[Vec<String>]) -> String {
+    let columns = table.first().map(Vec::len).unwrap_or_default();
+    let widths: Vec<usize> = (0..columns)
+        .map(|i| {
+            table
+                .iter()
+                .map(|row| row[i].len())
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+    table
+        .iter()
+        .map(|row| {
+            row.iter()
+                .zip(widths.iter())
+                .map(|(cell, width)| format!("{:>width$}", cell))
+                .collect::<Vec<String>>()
+                .join("  ")
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
@@ -4,7 +4,7 @@ use indicatif::HumanCount;
 use std::{fs::File, path::PathBuf};
 
 use crate::utils;
-use tracer::{self, Network, Population};
+use tracer::{self, network, Elevations, Network, Population};
 use xml;
 
 #[derive(Parser)]
@@ -22,6 +22,10 @@ pub struct TracerCommand {
     /// Name of events file
     #[clap(short, long, default_value = "output_events.xml")]
     events: PathBuf,
+    /// Optional node elevations csv (node 'id' and 'z' columns), used for link gradients in
+    /// place of network node 'z' values
+    #[arg(long, value_name = "CSV")]
+    elevations: Option<PathBuf>,
     /// Output file path
     #[clap(short, long, default_value = "traces.trc")]
     output: PathBuf,
@@ -48,7 +52,12 @@ impl TracerCommand {
         // Load network
         let spinner = utils::default_spinner();
         spinner.set_message("[1/4] Loading MATSim network...");
-        let network = Network::from_xml(&mut network_reader).context("failed to load network")?;
+        let elevations = match &self.elevations {
+            Some(path) => network::load_elevations(path)?,
+            None => Elevations::new(),
+        };
+        let network = Network::from_xml_with_elevations(&mut network_reader, &elevations)
+            .context("failed to load network")?;
         spinner.finish_with_message(format!(
             "[1/4] Completed loading network ({} links)",
             HumanCount(network.links.len() as u64)
@@ -114,6 +123,7 @@ mod tests {
             network: path.join("output_network.xml"),
             population: path.join("output_plans.xml"),
             events: path.join("output_events.xml"),
+            elevations: None,
             output: tested_dir.join("traces.json"),
             json: true,
         });
@@ -1,9 +1,13 @@
 mod commands;
+mod outputs;
 mod utils;
+pub use commands::compare::CompareCommand;
+pub use commands::config::ConfigCommand;
 pub use commands::dryrun::DryrunCommand;
 pub use commands::optimise::OptimiseCommand;
 pub use commands::peek::PeekCommand;
 pub use commands::run::RunCommand;
+pub use commands::sweep::SweepCommand;
 pub use commands::trace::TracerCommand;
 
 use clap::{Parser, Subcommand};
@@ -14,8 +18,11 @@ fn main() {
         Commands::Run(run_command) => run_command.run(),
         Commands::Tracer(tracer_command) => tracer_command.run(),
         Commands::Optimise(simulation_command) => simulation_command.run(),
+        Commands::Sweep(sweep_command) => sweep_command.run(),
         Commands::Dryrun(config_command) => config_command.run(),
         Commands::Attributes(peek_command) => peek_command.run(),
+        Commands::Compare(compare_command) => compare_command.run(),
+        Commands::Config(config_command) => config_command.run(),
     } {
         eprintln!("Error: {:?}", err);
         std::process::exit(1);
@@ -37,8 +44,14 @@ enum Commands {
     Tracer(commands::TracerCommand),
     /// Calculate optimal charge events from given traces
     Optimise(commands::OptimiseCommand),
+    /// Optimise many scenarios (a parameter grid or list of configs) for the same traces
+    Sweep(commands::SweepCommand),
     /// Dry run agent configurations
     Dryrun(commands::DryrunCommand),
     /// Peek attributes in a plans file
     Attributes(commands::PeekCommand),
+    /// Compare results from multiple output directories
+    Compare(commands::CompareCommand),
+    /// Inspect configs
+    Config(commands::ConfigCommand),
 }
@@ -0,0 +1,387 @@
+use anyhow::{bail, Context, Result};
+use clap::{Args, ValueEnum};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use configuration::{
+    config::Config,
+    handler::{AgentConfig, AgentConfigRecord},
+};
+use optimise::shared::ChargerRecord;
+use simulate::{
+    groups::GroupHandler,
+    profiles::{FlexibilityHandler, ProfileHandler, ProfileLocation},
+    record::{AgentSimulationRecord, EventsRecord},
+    results::SummaryHandler,
+    zones::ZoneHandler,
+};
+use tracer::{Network, Person};
+use zones::Zones;
+
+mod geojson;
+mod manifest;
+mod matsim;
+#[cfg(feature = "parquet")]
+mod parquet;
+mod sqlite;
+mod table;
+use geojson::{FeatureWriter, LinkRecord};
+pub use manifest::{InputRecord, Manifest, RunSummary, Timer};
+use matsim::MATSimEventsWriter;
+use sqlite::SqliteWriter;
+pub use table::Format;
+use table::{Table, TableWriter};
+
+/// Optional outputs shared by the `run` and `optimise` commands
+#[derive(Args)]
+pub struct OutputArgs {
+    /// Format of specs, report and events outputs (parquet requires the 'parquet' feature)
+    #[arg(long, value_enum, default_value = "csv")]
+    pub format: Format,
+    /// Write agent battery state of charge timelines to 'soc.csv'
+    #[arg(long)]
+    pub soc: bool,
+    /// Write average power profiles to 'profiles.csv' using given time bin size (seconds), also used by 'flexibility.csv'
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u32).range(1..))]
+    pub profile_bin: Option<u32>,
+    /// Additionally split power profiles by location
+    #[arg(long, value_enum, requires = "profile_bin")]
+    pub profile_by: Option<LocationKey>,
+    /// Zones file (GeoJSON or WKT csv) used to assign events to zones and write 'zones.csv'
+    #[arg(long, value_name = "PATH")]
+    pub zones: Option<PathBuf>,
+    /// Name of the zone id property (GeoJSON) or column (csv)
+    #[arg(long, default_value = "id", requires = "zones")]
+    pub zone_field: String,
+    /// Write charge events to 'events.geojson' and en-route charging by network link to 'links.geojson'
+    #[arg(long)]
+    pub geojson: bool,
+    /// Write time ordered MATSim charging start and end events to given file name (gzipped if '.gz')
+    #[arg(
+        long,
+        value_name = "FILENAME",
+        num_args = 0..=1,
+        default_missing_value = "charging_events.xml.gz"
+    )]
+    pub matsim_events: Option<String>,
+    /// Also write specs, reports, events and summary to a SQLite database, appending to existing results
+    #[arg(long, value_name = "PATH")]
+    pub sqlite: Option<PathBuf>,
+    /// Run id used to key results in the SQLite database, defaults to the output directory path
+    #[arg(long, requires = "sqlite")]
+    pub run_id: Option<String>,
+    /// Break down results by given person attribute to 'groups.csv' (can be used multiple times)
+    #[arg(long, value_name = "ATTRIBUTE")]
+    pub group_by: Vec<String>,
+}
+
+/// Location keys available for splitting outputs
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum LocationKey {
+    /// Network link id (en-route charging only)
+    Link,
+    /// Zone id (requires zones)
+    Zone,
+}
+
+impl From<LocationKey> for ProfileLocation {
+    fn from(key: LocationKey) -> Self {
+        match key {
+            LocationKey::Link => ProfileLocation::Link,
+            LocationKey::Zone => ProfileLocation::Zone,
+        }
+    }
+}
+
+/// Writes agent specifications, reports and charge events to the output directory,
+/// whilst collecting the run summary
+pub struct OutputHandler<'a> {
+    outpath: PathBuf,
+    specs_wtr: TableWriter,
+    record_wtr: TableWriter,
+    events_wtr: TableWriter,
+    soc_wtr: Option<csv::Writer<File>>,
+    profiles: Option<(ProfileHandler<'a>, csv::Writer<File>)>,
+    flexibility: Option<(FlexibilityHandler, csv::Writer<File>)>,
+    zones: Option<(Zones, ZoneHandler<'a>, csv::Writer<File>)>,
+    geojson: Option<(FeatureWriter, BTreeMap<&'a str, LinkRecord<'a>>)>,
+    network: Option<&'a Network>,
+    sqlite: Option<SqliteWriter>,
+    matsim_events: Option<MATSimEventsWriter<'a>>,
+    groups: Option<(GroupHandler<'a>, csv::Writer<File>)>,
+    chargers: Option<csv::Writer<File>>,
+    summary: SummaryHandler<'a>,
+}
+
+impl<'a> OutputHandler<'a> {
+    pub fn new(outpath: &Path, args: &'a OutputArgs, config: &'a Config) -> Result<Self> {
+        let soc_wtr = match args.soc {
+            true => Some(writer(&outpath.join("soc.csv"))?),
+            false => None,
+        };
+        let zones = match &args.zones {
+            Some(path) => Some((
+                Zones::load(path, &args.zone_field)?,
+                ZoneHandler::new(),
+                writer(&outpath.join("zones.csv"))?,
+            )),
+            None if matches!(args.profile_by, Some(LocationKey::Zone)) => {
+                bail!("profiles by zone require a zones file (--zones)")
+            }
+            None => None,
+        };
+        let geojson = match args.geojson {
+            true => Some((
+                FeatureWriter::new(&outpath.join("events.geojson"))?,
+                BTreeMap::new(),
+            )),
+            false => None,
+        };
+        let sqlite = match &args.sqlite {
+            Some(path) => {
+                let run_id = match &args.run_id {
+                    Some(run_id) => run_id.clone(),
+                    None => outpath.display().to_string(),
+                };
+                Some(SqliteWriter::new(path, &run_id)?)
+            }
+            None => None,
+        };
+        let profiles = match args.profile_bin {
+            Some(bin_size) => Some((
+                ProfileHandler::new(bin_size, args.profile_by.map(ProfileLocation::from)),
+                writer(&outpath.join("profiles.csv"))?,
+            )),
+            None => None,
+        };
+        // vehicle-to-grid flexibility uses the profile bin size (hourly by default)
+        let flexibility = match config
+            .activity_group
+            .iter()
+            .any(|spec| spec.discharge.is_some())
+        {
+            true => Some((
+                FlexibilityHandler::new(args.profile_bin.unwrap_or(3600)),
+                writer(&outpath.join("flexibility.csv"))?,
+            )),
+            false => None,
+        };
+        let groups = match args.group_by.is_empty() {
+            false => Some((
+                GroupHandler::new(config, &args.group_by),
+                writer(&outpath.join("groups.csv"))?,
+            )),
+            true => None,
+        };
+        Ok(OutputHandler {
+            outpath: outpath.to_path_buf(),
+            specs_wtr: TableWriter::new(outpath, Table::Specs, args.format)?,
+            record_wtr: TableWriter::new(outpath, Table::Report, args.format)?,
+            events_wtr: TableWriter::new(outpath, Table::Events, args.format)?,
+            soc_wtr,
+            profiles,
+            flexibility,
+            zones,
+            geojson,
+            network: None,
+            sqlite,
+            matsim_events: args
+                .matsim_events
+                .as_ref()
+                .map(|name| MATSimEventsWriter::new(outpath.join(name))),
+            groups,
+            chargers: None,
+            summary: SummaryHandler::new(config),
+        })
+    }
+
+    /// Add agent specifications, person attributes are used for group breakdowns
+    pub fn add_specs(
+        &mut self,
+        pid: &'a str,
+        person: &'a Person,
+        agent_config: &AgentConfig,
+    ) -> Result<()> {
+        let record: AgentConfigRecord = agent_config.to_record();
+        if let Some(sqlite) = &mut self.sqlite {
+            sqlite.insert(&sqlite::SPECS, &record)?;
+        }
+        self.specs_wtr.serialize(record)?;
+        if let Some((groups, _)) = &mut self.groups {
+            groups.add_agent(pid, &person.attributes, agent_config.battery.is_some());
+        }
+        Ok(())
+    }
+
+    /// Add network, used for writing link geometries
+    pub fn add_network(&mut self, network: &'a Network) {
+        self.network = Some(network);
+    }
+
+    /// Assign simulation events to zones, if zones are available
+    pub fn assign_zones(&self, sim: &mut AgentSimulationRecord) {
+        if let Some((zones, _, _)) = &self.zones {
+            sim.assign_zones(|x, y| zones.find(x as f64, y as f64).map(String::from));
+        }
+    }
+
+    /// Add the contention of a shared charger, written to 'chargers.csv' (created if any chargers
+    /// are shared)
+    pub fn add_charger(&mut self, record: &ChargerRecord) -> Result<()> {
+        let chargers_wtr = match &mut self.chargers {
+            Some(chargers_wtr) => chargers_wtr,
+            None => self
+                .chargers
+                .insert(writer(&self.outpath.join("chargers.csv"))?),
+        };
+        chargers_wtr
+            .serialize(record)
+            .context(format!("failed to write charger '{}'", record.charger))
+    }
+
+    pub fn add(&mut self, sim: &'a AgentSimulationRecord<'a>) -> Result<()> {
+        let record = sim.to_record();
+        self.record_wtr
+            .serialize(&record)
+            .context(format!("failed to write record pid '{}'", record.pid))?;
+        if let Some(sqlite) = &mut self.sqlite {
+            sqlite.insert(&sqlite::REPORTS, &record)?;
+        }
+        self.summary.add_leak(record.leak.unwrap());
+        self.summary
+            .add_distance(record.electric_distance, record.fuel_distance, record.fuel);
+        if let Some((groups, _)) = &mut self.groups {
+            groups.add(sim);
+        }
+        for day in sim.slice() {
+            if let Some((flexibility, _)) = &mut self.flexibility {
+                for window in &day.flexibility {
+                    flexibility.add(window);
+                }
+            }
+            for event in day {
+                self.summary.add(event);
+                if let Some((profiles, _)) = &mut self.profiles {
+                    profiles.add(event);
+                }
+                if let Some((_, zone_totals, _)) = &mut self.zones {
+                    zone_totals.add(event);
+                }
+                if let Some((events_geojson, links)) = &mut self.geojson {
+                    events_geojson.point((event.x, event.y), event)?;
+                    if let Some(link_id) = event.link_id {
+                        let link = links.entry(link_id).or_insert_with(|| LinkRecord {
+                            link_id,
+                            ..Default::default()
+                        });
+                        link.number_enroute += 1;
+                        // convert from kWs to kWh
+                        link.total_enroute += event.charge / 3600.0;
+                    }
+                }
+                if let Some(sqlite) = &mut self.sqlite {
+                    sqlite.insert(&sqlite::EVENTS, event)?;
+                }
+                if let Some(matsim_events) = &mut self.matsim_events {
+                    matsim_events.add(event);
+                }
+                self.events_wtr
+                    .serialize(event)
+                    .context(format!("failed to write event for pid '{}'", record.pid))?;
+            }
+        }
+        if let Some(soc_wtr) = &mut self.soc_wtr {
+            for soc in sim.soc_records() {
+                soc_wtr
+                    .serialize(soc)
+                    .context(format!("failed to write soc for pid '{}'", record.pid))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Flush all outputs and return the finalised summary and optional group breakdowns
+    pub fn finish(mut self) -> Result<(SummaryHandler<'a>, Option<GroupHandler<'a>>)> {
+        self.specs_wtr.finish()?;
+        self.record_wtr.finish()?;
+        self.events_wtr.finish()?;
+        if let Some(soc_wtr) = &mut self.soc_wtr {
+            soc_wtr.flush()?;
+        }
+        if let Some(chargers_wtr) = &mut self.chargers {
+            chargers_wtr.flush()?;
+        }
+        if let Some((profiles, profiles_wtr)) = &mut self.profiles {
+            for record in profiles.records() {
+                profiles_wtr
+                    .serialize(record)
+                    .context("failed to write profiles")?;
+            }
+            profiles_wtr.flush()?;
+        }
+        if let Some((flexibility, flexibility_wtr)) = &mut self.flexibility {
+            for record in flexibility.records() {
+                flexibility_wtr
+                    .serialize(record)
+                    .context("failed to write flexibility")?;
+            }
+            flexibility_wtr.flush()?;
+        }
+        if let Some((_, zone_totals, zones_wtr)) = &mut self.zones {
+            for record in zone_totals.records() {
+                zones_wtr
+                    .serialize(record)
+                    .context("failed to write zones")?;
+            }
+            zones_wtr.flush()?;
+        }
+        if let Some((events_geojson, links)) = self.geojson {
+            events_geojson.finish()?;
+            if let Some(network) = self.network {
+                let mut links_geojson = FeatureWriter::new(&self.outpath.join("links.geojson"))?;
+                for (link_id, record) in links {
+                    let link = network
+                        .links
+                        .get(link_id)
+                        .context(format!("failed to find link '{}' in network", link_id))?;
+                    links_geojson.link(link, record)?;
+                }
+                links_geojson.finish()?;
+            }
+        }
+        if let Some(matsim_events) = self.matsim_events {
+            matsim_events.finish()?;
+        }
+        let groups = match self.groups {
+            Some((mut groups, mut groups_wtr)) => {
+                groups.finalise();
+                for record in groups.records() {
+                    groups_wtr
+                        .serialize(record)
+                        .context("failed to write groups")?;
+                }
+                groups_wtr.flush()?;
+                Some(groups)
+            }
+            None => None,
+        };
+        self.summary.finalise();
+        if let Some(mut sqlite) = self.sqlite {
+            sqlite.insert(&sqlite::SUMMARY, self.summary.to_record())?;
+            for record in self.summary.activity_records() {
+                sqlite.insert(&sqlite::ACTIVITY_SUMMARY, record)?;
+            }
+            sqlite.finish()?;
+        }
+        Ok((self.summary, groups))
+    }
+}
+
+fn writer(path: &Path) -> Result<csv::Writer<File>> {
+    let file =
+        File::create(path).context(format!("unable to create out file '{}'", path.display()))?;
+    Ok(csv::Writer::from_writer(file))
+}
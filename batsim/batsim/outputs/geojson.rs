@@ -0,0 +1,121 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use tracer::NetworkLink;
+
+/// En-route charging totals for a single network link
+#[derive(Serialize, Default)]
+pub struct LinkRecord<'a> {
+    pub link_id: &'a str,
+    pub number_enroute: usize,
+    #[serde(rename = "total_enroute_(kWh)")]
+    pub total_enroute: f32,
+}
+
+/// Streams features into a GeoJSON FeatureCollection file
+pub struct FeatureWriter {
+    wtr: BufWriter<File>,
+    empty: bool,
+}
+
+impl FeatureWriter {
+    pub fn new(path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .context(format!("unable to create out file '{}'", path.display()))?;
+        let mut wtr = BufWriter::new(file);
+        write!(wtr, r#"{{"type":"FeatureCollection","features":["#)?;
+        Ok(FeatureWriter { wtr, empty: true })
+    }
+
+    /// Write a point feature with given properties
+    pub fn point(&mut self, point: (f32, f32), properties: impl Serialize) -> Result<()> {
+        let geometry = json!({"type": "Point", "coordinates": [point.0, point.1]});
+        self.write(geometry, properties)
+    }
+
+    /// Write a link line feature with given properties
+    pub fn link(&mut self, link: &NetworkLink, properties: impl Serialize) -> Result<()> {
+        let geometry = json!({
+            "type": "LineString",
+            "coordinates": [[link.from.0, link.from.1], [link.to.0, link.to.1]]
+        });
+        self.write(geometry, properties)
+    }
+
+    fn write(&mut self, geometry: serde_json::Value, properties: impl Serialize) -> Result<()> {
+        if !self.empty {
+            writeln!(self.wtr, ",")?;
+        } else {
+            writeln!(self.wtr)?;
+        }
+        let feature = json!({
+            "type": "Feature",
+            "geometry": geometry,
+            "properties": properties,
+        });
+        serde_json::to_writer(&mut self.wtr, &feature)?;
+        self.empty = false;
+        Ok(())
+    }
+
+    /// Close the feature collection and flush
+    pub fn finish(mut self) -> Result<()> {
+        writeln!(self.wtr, "]}}")?;
+        self.wtr.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_dir::{DirBuilder, TestDir};
+
+    #[test]
+    fn writes_feature_collection() {
+        let temp_dir = TestDir::temp();
+        let path = temp_dir.root().join("links.geojson");
+        let mut wtr = FeatureWriter::new(&path).unwrap();
+        let link = NetworkLink {
+            length: 1.0,
+            from: (0.0, 0.0),
+            to: (1.0, 0.0),
+            rise: 0.0,
+        };
+        let record = LinkRecord {
+            link_id: "a",
+            number_enroute: 2,
+            total_enroute: 1.5,
+        };
+        wtr.link(&link, &record).unwrap();
+        wtr.point((0.5, 0.0), &record).unwrap();
+        wtr.finish().unwrap();
+
+        let output: serde_json::Value =
+            serde_json::from_reader(File::open(&path).unwrap()).unwrap();
+        assert_eq!(
+            output,
+            json!({
+                "type": "FeatureCollection",
+                "features": [
+                    {
+                        "type": "Feature",
+                        "geometry": {"type": "LineString", "coordinates": [[0.0, 0.0], [1.0, 0.0]]},
+                        "properties": {"link_id": "a", "number_enroute": 2, "total_enroute_(kWh)": 1.5}
+                    },
+                    {
+                        "type": "Feature",
+                        "geometry": {"type": "Point", "coordinates": [0.5, 0.0]},
+                        "properties": {"link_id": "a", "number_enroute": 2, "total_enroute_(kWh)": 1.5}
+                    }
+                ]
+            })
+        );
+    }
+}
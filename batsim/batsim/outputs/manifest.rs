@@ -0,0 +1,197 @@
+use anyhow::{Context, Result};
+use serde::{ser::SerializeMap, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    path::Path,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use configuration::config::Config;
+use simulate::{
+    groups::{GroupHandler, GroupRecord},
+    results::{ActivitySummaryRecord, SummaryHandler, SummaryRecord},
+};
+
+/// Records elapsed time (seconds) of each stage of a command
+pub struct Timer {
+    start: Instant,
+    last: Instant,
+    stages: Vec<(&'static str, f32)>,
+}
+
+impl Timer {
+    pub fn start() -> Self {
+        let now = Instant::now();
+        Timer {
+            start: now,
+            last: now,
+            stages: Vec::new(),
+        }
+    }
+
+    /// Record the time since the last stage (or start) as given stage
+    pub fn stage(&mut self, name: &'static str) {
+        let now = Instant::now();
+        self.stages
+            .push((name, now.duration_since(self.last).as_secs_f32()));
+        self.last = now;
+    }
+}
+
+impl Serialize for Timer {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.stages.len() + 1))?;
+        for (name, seconds) in &self.stages {
+            map.serialize_entry(name, seconds)?;
+        }
+        map.serialize_entry("total", &self.last.duration_since(self.start).as_secs_f32())?;
+        map.end()
+    }
+}
+
+/// Machine readable run summary, written to 'summary.json'
+#[derive(Serialize)]
+pub struct RunSummary<'a> {
+    pub agents: usize,
+    #[serde(flatten)]
+    pub totals: SummaryRecord,
+    pub activities: Vec<ActivitySummaryRecord<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<&'a GroupRecord<'a>>,
+    #[serde(rename = "timings_(s)")]
+    pub timings: &'a Timer,
+}
+
+impl<'a> RunSummary<'a> {
+    pub fn new(
+        summary: &SummaryHandler<'a>,
+        groups: Option<&'a GroupHandler<'a>>,
+        agents: usize,
+        timings: &'a Timer,
+    ) -> Self {
+        RunSummary {
+            agents,
+            totals: summary.to_record(),
+            activities: summary.activity_records(),
+            groups: groups
+                .map(|groups| groups.records().collect())
+                .unwrap_or_default(),
+            timings,
+        }
+    }
+
+    pub fn write(&self, outpath: &Path) -> Result<()> {
+        write_json(&outpath.join("summary.json"), self)
+    }
+}
+
+/// Input file record, including size and hash so that inputs can be checked later
+#[derive(Serialize, Clone)]
+pub struct InputRecord {
+    pub path: String,
+    pub bytes: u64,
+    pub sha256: String,
+}
+
+impl InputRecord {
+    pub fn new(path: &Path) -> Result<Self> {
+        let file = File::open(path).context(format!("unable to open '{}'", path.display()))?;
+        let bytes = file.metadata()?.len();
+        let mut hasher = Sha256::new();
+        io::copy(&mut BufReader::new(file), &mut hasher)
+            .context(format!("unable to hash '{}'", path.display()))?;
+        let sha256 = hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        Ok(InputRecord {
+            path: path.display().to_string(),
+            bytes,
+            sha256,
+        })
+    }
+}
+
+/// Record of how an output directory was produced, written to 'manifest.json'
+#[derive(Serialize)]
+pub struct Manifest<'a> {
+    pub batsim_version: &'static str,
+    pub command: &'static str,
+    pub created: u64,
+    pub seed: Option<u64>,
+    pub config_path: Option<String>,
+    pub inputs: Vec<InputRecord>,
+    pub config: &'a Config,
+}
+
+impl<'a> Manifest<'a> {
+    /// Create a new manifest, the config should already have its seed resolved
+    pub fn new(command: &'static str, config: &'a Config, config_path: Option<&Path>) -> Self {
+        Manifest {
+            batsim_version: env!("CARGO_PKG_VERSION"),
+            command,
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|t| t.as_secs())
+                .unwrap_or_default(),
+            seed: config.seed,
+            config_path: config_path.map(|path| path.display().to_string()),
+            inputs: Vec::new(),
+            config,
+        }
+    }
+
+    pub fn add_input(&mut self, path: &Path) -> Result<()> {
+        self.inputs.push(InputRecord::new(path)?);
+        Ok(())
+    }
+
+    pub fn write(&self, outpath: &Path) -> Result<()> {
+        write_json(&outpath.join("manifest.json"), self)
+    }
+}
+
+fn write_json(path: &Path, value: &impl Serialize) -> Result<()> {
+    let file =
+        File::create(path).context(format!("unable to create out file '{}'", path.display()))?;
+    serde_json::to_writer_pretty(BufWriter::new(file), value)
+        .context(format!("failed to write '{}'", path.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use test_dir::{DirBuilder, TestDir};
+
+    #[test]
+    fn input_record_hashes_file() {
+        let temp_dir = TestDir::temp();
+        let path = temp_dir.root().join("input.txt");
+        File::create(&path).unwrap().write_all(b"abc").unwrap();
+        let record = InputRecord::new(&path).unwrap();
+        assert_eq!(record.bytes, 3);
+        assert_eq!(
+            record.sha256,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn manifest_records_config() {
+        let config = Config {
+            seed: Some(1234),
+            ..Default::default()
+        };
+        let manifest = Manifest::new("optimise", &config, None);
+        let value = serde_json::to_value(&manifest).unwrap();
+        assert_eq!(value["seed"], 1234);
+        assert_eq!(value["config"]["seed"], 1234);
+        assert_eq!(value["config"]["scale"], 1.0);
+        assert_eq!(value["config"]["battery_group"][0]["name"], "default");
+    }
+}
@@ -0,0 +1,128 @@
+use anyhow::{Context, Result};
+use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, Event as XmlEvent};
+use std::path::PathBuf;
+
+use simulate::events::{ChargeType, Event};
+
+/// Collects charge events to be written as MATSim "charging start" and "charging end" events.
+/// Events are held until finished so that they can be ordered by time across all agents.
+pub struct MATSimEventsWriter<'a> {
+    path: PathBuf,
+    // time, is end, event
+    events: Vec<(u32, bool, &'a Event<'a>)>,
+}
+
+impl<'a> MATSimEventsWriter<'a> {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, event: &'a Event) {
+        self.events.push((event.start_time, false, event));
+        self.events.push((event.end_time, true, event));
+    }
+
+    /// Write all events to xml, ordered by time (starts before ends at the same time)
+    pub fn finish(mut self) -> Result<()> {
+        self.events.sort_by_key(|(time, end, _)| (*time, *end));
+        let mut writer = xml::writer(&self.path)?;
+        writer.write_event(XmlEvent::Decl(BytesDecl::new("1.0", Some("utf-8"), None)))?;
+        writer.write_event(XmlEvent::Start(
+            BytesStart::new("events").with_attributes([("version", "1.0")]),
+        ))?;
+        for (time, end, event) in self.events {
+            writer.write_event(XmlEvent::Empty(element(time, end, event)))?;
+        }
+        writer.write_event(XmlEvent::End(BytesEnd::new("events")))?;
+        writer
+            .into_inner()
+            .finish()
+            .context(format!("failed to write '{}'", self.path.display()))?;
+        Ok(())
+    }
+}
+
+fn element<'b>(time: u32, end: bool, event: &'b Event) -> BytesStart<'b> {
+    let mut element = BytesStart::new("event");
+    element.push_attribute(("time", format!("{:.1}", time as f32).as_str()));
+    element.push_attribute((
+        "type",
+        match end {
+            false => "charging start",
+            true => "charging end",
+        },
+    ));
+    element.push_attribute(("person", event.pid));
+    if let Some(link_id) = event.link_id {
+        element.push_attribute(("link", link_id));
+    }
+    if let Some(activity) = event.activity {
+        element.push_attribute(("actType", activity));
+    }
+    element.push_attribute(("x", event.x.to_string().as_str()));
+    element.push_attribute(("y", event.y.to_string().as_str()));
+    element.push_attribute((
+        "chargeType",
+        match event.charge_type {
+            ChargeType::EnRoute => "EnRoute",
+            ChargeType::Activity => "Activity",
+            ChargeType::Discharge => "Discharge",
+        },
+    ));
+    // convert from kWs to kWh
+    element.push_attribute(("energy", (event.charge / 3600.0).to_string().as_str()));
+    if let Some(spec) = &event.spec {
+        element.push_attribute(("spec", spec.as_str()));
+    }
+    element.push_attribute(("day", event.day.to_string().as_str()));
+    element
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quick_xml::events::Event as XmlEvent;
+    use test_dir::{DirBuilder, TestDir};
+
+    #[test]
+    fn writes_time_ordered_gzipped_events() {
+        let temp_dir = TestDir::temp();
+        let path = temp_dir.root().join("charging_events.xml.gz");
+        let event_a = Event::activity("a", None, 3600., 1, (100, 200), "home", (0., 0.));
+        let event_b = Event::en_route("b", None, 7200., 1, (150, 150), "1-2", (0., 0.));
+        let mut writer = MATSimEventsWriter::new(path.clone());
+        writer.add(&event_a);
+        writer.add(&event_b);
+        writer.finish().unwrap();
+
+        let mut reader = xml::reader(&path).unwrap();
+        let mut buf = Vec::new();
+        let mut events = Vec::new();
+        loop {
+            match reader.read_event_into(&mut buf).unwrap() {
+                XmlEvent::Eof => break,
+                XmlEvent::Empty(e) => events.push((
+                    String::from_utf8(xml::get_attribute(b"time", &e).unwrap().into_owned())
+                        .unwrap(),
+                    String::from_utf8(xml::get_attribute(b"type", &e).unwrap().into_owned())
+                        .unwrap(),
+                    String::from_utf8(xml::get_attribute(b"person", &e).unwrap().into_owned())
+                        .unwrap(),
+                )),
+                _ => (),
+            }
+            buf.clear();
+        }
+        let expected = [
+            ("100.0", "charging start", "a"),
+            ("150.0", "charging start", "b"),
+            ("150.0", "charging end", "b"),
+            ("200.0", "charging end", "a"),
+        ]
+        .map(|(t, k, p)| (t.to_string(), k.to_string(), p.to_string()));
+        assert_eq!(events, expected);
+    }
+}
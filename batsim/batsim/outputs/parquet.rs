@@ -0,0 +1,200 @@
+use anyhow::Result;
+use arrow_schema::{DataType, Field, FieldRef, Schema};
+use parquet::{
+    arrow::ArrowWriter,
+    basic::Compression,
+    file::properties::{WriterProperties, WriterVersion},
+};
+use serde::Serialize;
+use serde_arrow::ArrayBuilder;
+use std::{fs::File, sync::Arc};
+
+use super::table::Table;
+
+// number of rows buffered before being written as a parquet row group
+const ROW_GROUP_SIZE: usize = 1_000_000;
+
+/// Buffers records into arrow arrays, written to parquet one row group at a time
+pub struct ParquetWriter {
+    builder: ArrayBuilder,
+    writer: ArrowWriter<File>,
+    rows: usize,
+}
+
+impl ParquetWriter {
+    pub fn new(file: File, table: Table) -> Result<Self> {
+        let fields = schema(table);
+        let schema = Arc::new(Schema::new(fields.clone()));
+        let properties = WriterProperties::builder()
+            .set_writer_version(WriterVersion::PARQUET_2_0)
+            .set_compression(Compression::SNAPPY)
+            .set_max_row_group_size(ROW_GROUP_SIZE)
+            .build();
+        Ok(ParquetWriter {
+            builder: ArrayBuilder::from_arrow(&fields)?,
+            writer: ArrowWriter::try_new(file, schema, Some(properties))?,
+            rows: 0,
+        })
+    }
+
+    pub fn serialize(&mut self, record: impl Serialize) -> Result<()> {
+        self.builder.push(record)?;
+        self.rows += 1;
+        if self.rows >= ROW_GROUP_SIZE {
+            self.write_row_group()?;
+        }
+        Ok(())
+    }
+
+    fn write_row_group(&mut self) -> Result<()> {
+        if self.rows > 0 {
+            let batch = self.builder.to_record_batch()?;
+            self.writer.write(&batch)?;
+            self.writer.flush()?;
+            self.rows = 0;
+        }
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> Result<()> {
+        self.write_row_group()?;
+        self.writer.finish()?;
+        Ok(())
+    }
+}
+
+fn field(name: &str, data_type: DataType, nullable: bool) -> FieldRef {
+    Arc::new(Field::new(name, data_type, nullable))
+}
+
+/// Parquet schema of a table
+fn schema(table: Table) -> Vec<FieldRef> {
+    match table {
+        Table::Specs => specs_schema(),
+        Table::Report => report_schema(),
+        Table::Events => events_schema(),
+    }
+}
+
+fn charge_type() -> DataType {
+    DataType::Dictionary(Box::new(DataType::UInt32), Box::new(DataType::Utf8))
+}
+
+/// Parquet schema of agent specifications (`AgentConfigRecord`)
+fn specs_schema() -> Vec<FieldRef> {
+    vec![
+        field("pid", DataType::Utf8, false),
+        field("battery", DataType::Utf8, false),
+        field("trigger", DataType::Utf8, false),
+        field("en_route", DataType::Utf8, false),
+        field("activities", DataType::Utf8, false),
+    ]
+}
+
+/// Parquet schema of agent reports (`PlanRecord`)
+fn report_schema() -> Vec<FieldRef> {
+    vec![
+        field("pid", DataType::Utf8, false),
+        field("days", DataType::UInt64, false),
+        field("number_enroute", DataType::UInt64, false),
+        field("number_activity", DataType::UInt64, false),
+        field("number_charges", DataType::UInt64, false),
+        field("total_charge_(kWh)", DataType::Float32, false),
+        field("total_enroute_(kWh)", DataType::Float32, false),
+        field("total_activity_(kWh)", DataType::Float32, false),
+        field("leak_(kWh)", DataType::Float32, true),
+        field("min_soc_(kWh)", DataType::Float32, true),
+        field("max_soc_(kWh)", DataType::Float32, true),
+        field("electric_(km)", DataType::Float32, false),
+        field("fuel_(km)", DataType::Float32, false),
+        field("fuel_(l)", DataType::Float32, false),
+        field("number_discharge", DataType::UInt64, false),
+        field("total_discharge_(kWh)", DataType::Float32, false),
+    ]
+}
+
+/// Parquet schema of charge events (`Event`)
+fn events_schema() -> Vec<FieldRef> {
+    vec![
+        field("charge_type", charge_type(), false),
+        field("pid", DataType::Utf8, false),
+        field("spec", DataType::Utf8, true),
+        field("charge_(kWs)", DataType::Float32, false),
+        field("day", DataType::UInt32, false),
+        field("start_time_(s)", DataType::UInt32, false),
+        field("end_time_(s)", DataType::UInt32, false),
+        field("activity", DataType::Utf8, true),
+        field("link_id", DataType::Utf8, true),
+        field("x", DataType::Float32, false),
+        field("y", DataType::Float32, false),
+        field("zone", DataType::Utf8, true),
+        field("driver", DataType::Utf8, true),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::outputs::table::{csv_header, Format, TableWriter};
+    use configuration::handler::AgentConfig;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use simulate::{
+        events::Event,
+        record::{AgentSimulationRecord, EventsRecord},
+    };
+    use test_dir::{DirBuilder, TestDir};
+
+    fn names(schema: Vec<FieldRef>) -> Vec<String> {
+        schema.iter().map(|field| field.name().clone()).collect()
+    }
+
+    #[test]
+    fn schemas_match_csv_headers() {
+        let agent_config = AgentConfig {
+            pid: "a",
+            battery: None,
+            trigger: None,
+            en_route: None,
+            activities: vec![],
+        };
+        assert_eq!(names(specs_schema()), csv_header(agent_config.to_record()));
+        let mut sim = AgentSimulationRecord::new("a", 1.0);
+        sim.new_day(0.0);
+        assert_eq!(names(report_schema()), csv_header(sim.to_record()));
+        let event = Event::en_route("a", None, 1.0, 1, (0, 1), "a", (0., 0.));
+        assert_eq!(names(events_schema()), csv_header(&event));
+    }
+
+    #[test]
+    fn events_write_to_parquet() {
+        let temp_dir = TestDir::temp();
+        let mut wtr = TableWriter::new(temp_dir.root(), Table::Events, Format::Parquet).unwrap();
+        let mut event = Event::en_route("a", None, 1.0, 1, (0, 1), "a", (0., 0.));
+        event.zone = Some("x".to_string());
+        wtr.serialize(&event).unwrap();
+        wtr.serialize(Event::activity(
+            "b",
+            Some("home".to_string()),
+            2.0,
+            1,
+            (0, 1),
+            "home",
+            (0., 0.),
+        ))
+        .unwrap();
+        wtr.finish().unwrap();
+
+        let file = File::open(temp_dir.root().join("events.parquet")).unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file)
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(batch.schema().fields().len(), 13);
+        assert_eq!(batch.column(0).data_type(), &charge_type());
+        assert_eq!(batch.column(5).data_type(), &DataType::UInt32);
+    }
+}
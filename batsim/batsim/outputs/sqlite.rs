@@ -0,0 +1,333 @@
+use anyhow::{Context, Result};
+use rusqlite::{params, params_from_iter, types, Connection};
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Results table, column names match the csv outputs
+pub struct Table {
+    pub name: &'static str,
+    pub columns: &'static [(&'static str, &'static str)],
+}
+
+pub const SPECS: Table = Table {
+    name: "specs",
+    columns: &[
+        ("pid", "TEXT"),
+        ("battery", "TEXT"),
+        ("trigger", "TEXT"),
+        ("en_route", "TEXT"),
+        ("activities", "TEXT"),
+    ],
+};
+
+pub const REPORTS: Table = Table {
+    name: "reports",
+    columns: &[
+        ("pid", "TEXT"),
+        ("days", "INTEGER"),
+        ("number_enroute", "INTEGER"),
+        ("number_activity", "INTEGER"),
+        ("number_charges", "INTEGER"),
+        ("total_charge_(kWh)", "REAL"),
+        ("total_enroute_(kWh)", "REAL"),
+        ("total_activity_(kWh)", "REAL"),
+        ("leak_(kWh)", "REAL"),
+        ("min_soc_(kWh)", "REAL"),
+        ("max_soc_(kWh)", "REAL"),
+        ("electric_(km)", "REAL"),
+        ("fuel_(km)", "REAL"),
+        ("fuel_(l)", "REAL"),
+        ("number_discharge", "INTEGER"),
+        ("total_discharge_(kWh)", "REAL"),
+    ],
+};
+
+pub const EVENTS: Table = Table {
+    name: "events",
+    columns: &[
+        ("charge_type", "TEXT"),
+        ("pid", "TEXT"),
+        ("spec", "TEXT"),
+        ("charge_(kWs)", "REAL"),
+        ("day", "INTEGER"),
+        ("start_time_(s)", "INTEGER"),
+        ("end_time_(s)", "INTEGER"),
+        ("activity", "TEXT"),
+        ("link_id", "TEXT"),
+        ("x", "REAL"),
+        ("y", "REAL"),
+        ("zone", "TEXT"),
+        ("driver", "TEXT"),
+    ],
+};
+
+pub const SUMMARY: Table = Table {
+    name: "summary",
+    columns: &[
+        ("total_charge_(kWh)", "REAL"),
+        ("number_charges", "REAL"),
+        ("leak_(kWh)", "REAL"),
+        ("total_enroute_(kWh)", "REAL"),
+        ("number_enroute", "REAL"),
+        ("total_activity_(kWh)", "REAL"),
+        ("number_activity", "REAL"),
+        ("electric_(km)", "REAL"),
+        ("fuel_(km)", "REAL"),
+        ("fuel_(l)", "REAL"),
+        ("total_discharge_(kWh)", "REAL"),
+        ("number_discharge", "REAL"),
+    ],
+};
+
+pub const ACTIVITY_SUMMARY: Table = Table {
+    name: "activity_summary",
+    columns: &[
+        ("activity", "TEXT"),
+        ("total_charge_(kWh)", "REAL"),
+        ("number_charges", "REAL"),
+    ],
+};
+
+const TABLES: [&Table; 5] = [&SPECS, &REPORTS, &EVENTS, &SUMMARY, &ACTIVITY_SUMMARY];
+
+impl Table {
+    fn create_sql(&self) -> String {
+        let columns = self
+            .columns
+            .iter()
+            .map(|(name, kind)| format!(r#""{}" {}"#, name, kind))
+            .collect::<Vec<String>>()
+            .join(", ");
+        format!(
+            r#"CREATE TABLE IF NOT EXISTS "{}" ("run_id" TEXT NOT NULL, {});
+            CREATE INDEX IF NOT EXISTS "{}_run_id" ON "{}" ("run_id");"#,
+            self.name, columns, self.name, self.name
+        )
+    }
+
+    // add columns missing from a table created by a previous version
+    fn add_missing_columns(&self, conn: &Connection) -> Result<()> {
+        let existing = conn
+            .prepare(&format!(r#"PRAGMA table_info("{}")"#, self.name))?
+            .query_map([], |row| row.get::<_, String>(1))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        for (name, kind) in self.columns {
+            if !existing.iter().any(|column| column == name) {
+                conn.execute_batch(&format!(
+                    r#"ALTER TABLE "{}" ADD COLUMN "{}" {}"#,
+                    self.name, name, kind
+                ))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn insert_sql(&self) -> String {
+        let columns = self
+            .columns
+            .iter()
+            .map(|(name, _)| format!(r#""{}""#, name))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let values = (1..=self.columns.len() + 1)
+            .map(|i| format!("?{}", i))
+            .collect::<Vec<String>>()
+            .join(", ");
+        format!(
+            r#"INSERT INTO "{}" ("run_id", {}) VALUES ({})"#,
+            self.name, columns, values
+        )
+    }
+}
+
+/// Writes results into a SQLite database, keyed by run id.
+/// Results are appended to existing tables, replacing any previous results with the same run id.
+/// All results for a run are written in a single transaction.
+pub struct SqliteWriter {
+    conn: Connection,
+    run_id: String,
+}
+
+impl SqliteWriter {
+    pub fn new(path: &Path, run_id: &str) -> Result<Self> {
+        let conn = Connection::open(path).context(format!(
+            "unable to open sqlite database '{}'",
+            path.display()
+        ))?;
+        conn.execute_batch(
+            r#"CREATE TABLE IF NOT EXISTS "runs" ("run_id" TEXT PRIMARY KEY, "created" INTEGER);"#,
+        )?;
+        for table in TABLES {
+            conn.execute_batch(&table.create_sql())
+                .context(format!("failed to create sqlite table '{}'", table.name))?;
+            table
+                .add_missing_columns(&conn)
+                .context(format!("failed to update sqlite table '{}'", table.name))?;
+        }
+        conn.execute_batch("BEGIN")?;
+        conn.execute(r#"DELETE FROM "runs" WHERE "run_id" = ?1"#, [run_id])?;
+        for table in TABLES {
+            conn.execute(
+                &format!(r#"DELETE FROM "{}" WHERE "run_id" = ?1"#, table.name),
+                [run_id],
+            )?;
+        }
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|t| t.as_secs())
+            .unwrap_or_default();
+        conn.execute(
+            r#"INSERT INTO "runs" ("run_id", "created") VALUES (?1, ?2)"#,
+            params![run_id, created],
+        )?;
+        Ok(SqliteWriter {
+            conn,
+            run_id: run_id.to_string(),
+        })
+    }
+
+    /// Insert a serialisable record into given table, record fields are matched to table columns by name
+    pub fn insert(&mut self, table: &Table, record: impl Serialize) -> Result<()> {
+        let value = serde_json::to_value(record)?;
+        let values = table
+            .columns
+            .iter()
+            .map(|(name, _)| value.get(name).map(sql_value).unwrap_or(types::Value::Null));
+        let params = std::iter::once(types::Value::Text(self.run_id.clone())).chain(values);
+        let mut statement = self.conn.prepare_cached(&table.insert_sql())?;
+        statement
+            .execute(params_from_iter(params))
+            .context(format!(
+                "failed to insert into sqlite table '{}'",
+                table.name
+            ))?;
+        Ok(())
+    }
+
+    /// Commit all results
+    pub fn finish(self) -> Result<()> {
+        self.conn
+            .execute_batch("COMMIT")
+            .context("failed to commit sqlite results")?;
+        Ok(())
+    }
+}
+
+/// Convert a serialised json value into a native SQLite value
+fn sql_value(value: &Value) -> types::Value {
+    match value {
+        Value::Null => types::Value::Null,
+        Value::Bool(value) => types::Value::Integer(*value as i64),
+        Value::Number(value) => match value.as_i64() {
+            Some(value) => types::Value::Integer(value),
+            None => types::Value::Real(value.as_f64().unwrap_or(f64::NAN)),
+        },
+        Value::String(value) => types::Value::Text(value.clone()),
+        value => types::Value::Text(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::table::csv_header;
+    use super::*;
+    use configuration::{config::Config, handler::AgentConfig};
+    use simulate::{
+        events::Event,
+        record::{AgentSimulationRecord, EventsRecord},
+        results::SummaryHandler,
+    };
+    use test_dir::{DirBuilder, TestDir};
+
+    fn names(table: &Table) -> Vec<String> {
+        table
+            .columns
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn tables_match_csv_headers() {
+        let agent_config = AgentConfig {
+            pid: "a",
+            battery: None,
+            trigger: None,
+            en_route: None,
+            activities: vec![],
+        };
+        assert_eq!(names(&SPECS), csv_header(agent_config.to_record()));
+        let mut sim = AgentSimulationRecord::new("a", 1.0);
+        sim.new_day(0.0);
+        assert_eq!(names(&REPORTS), csv_header(sim.to_record()));
+        let event = Event::activity("a", None, 1.0, 1, (0, 1), "home", (0., 0.));
+        assert_eq!(names(&EVENTS), csv_header(&event));
+        let config = Config::default();
+        let mut summary = SummaryHandler::new(&config);
+        summary.add(&event);
+        summary.finalise();
+        assert_eq!(names(&SUMMARY), csv_header(summary.to_record()));
+        assert_eq!(
+            names(&ACTIVITY_SUMMARY),
+            csv_header(&summary.activity_records()[0])
+        );
+    }
+
+    fn count(path: &Path, run_id: &str) -> usize {
+        let conn = Connection::open(path).unwrap();
+        conn.query_row(
+            r#"SELECT COUNT(*) FROM "events" WHERE "run_id" = ?1"#,
+            [run_id],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn runs_append_and_replace() {
+        let temp_dir = TestDir::temp();
+        let path = temp_dir.root().join("results.db");
+        let event = Event::en_route("a", None, 1.0, 1, (0, 1), "a", (0., 0.));
+        for run_id in ["a", "b", "b"] {
+            let mut wtr = SqliteWriter::new(&path, run_id).unwrap();
+            wtr.insert(&EVENTS, &event).unwrap();
+            wtr.insert(&EVENTS, &event).unwrap();
+            wtr.finish().unwrap();
+        }
+        assert_eq!(count(&path, "a"), 2);
+        assert_eq!(count(&path, "b"), 2);
+
+        let conn = Connection::open(&path).unwrap();
+        let (charge_type, link_id, zone): (String, String, Option<String>) = conn
+            .query_row(
+                r#"SELECT "charge_type", "link_id", "zone" FROM "events" LIMIT 1"#,
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            (charge_type.as_str(), link_id.as_str(), zone),
+            ("EnRoute", "a", None)
+        );
+    }
+
+    #[test]
+    fn missing_columns_are_added() {
+        let temp_dir = TestDir::temp();
+        let path = temp_dir.root().join("results.db");
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch(r#"CREATE TABLE "events" ("run_id" TEXT NOT NULL, "pid" TEXT)"#)
+                .unwrap();
+        }
+        let event = Event::en_route("a", None, 1.0, 1, (0, 1), "a", (0., 0.));
+        let mut wtr = SqliteWriter::new(&path, "a").unwrap();
+        wtr.insert(&EVENTS, &event).unwrap();
+        wtr.finish().unwrap();
+        assert_eq!(count(&path, "a"), 1);
+    }
+}
@@ -0,0 +1,97 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::{fs::File, path::Path};
+
+#[cfg(feature = "parquet")]
+use super::parquet::ParquetWriter;
+
+/// Output format for tabular outputs (specs, report and events)
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Format {
+    #[default]
+    Csv,
+    /// Requires the `parquet` feature
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+impl Format {
+    fn extension(&self) -> &str {
+        match self {
+            Format::Csv => "csv",
+            #[cfg(feature = "parquet")]
+            Format::Parquet => "parquet",
+        }
+    }
+}
+
+/// Tabular outputs, each written to a single file in the chosen format
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Table {
+    Specs,
+    Report,
+    Events,
+}
+
+impl Table {
+    pub fn name(&self) -> &str {
+        match self {
+            Table::Specs => "specs",
+            Table::Report => "report",
+            Table::Events => "events",
+        }
+    }
+}
+
+/// Writer of serialisable records to either csv or parquet
+pub enum TableWriter {
+    Csv(csv::Writer<File>),
+    #[cfg(feature = "parquet")]
+    Parquet(ParquetWriter),
+}
+
+impl TableWriter {
+    /// Create a new table at `outpath/<table name>.<extension>`
+    pub fn new(outpath: &Path, table: Table, format: Format) -> Result<Self> {
+        let path = outpath.join(format!("{}.{}", table.name(), format.extension()));
+        let file = File::create(&path)
+            .context(format!("unable to create out file '{}'", path.display()))?;
+        match format {
+            Format::Csv => Ok(TableWriter::Csv(csv::Writer::from_writer(file))),
+            #[cfg(feature = "parquet")]
+            Format::Parquet => Ok(TableWriter::Parquet(ParquetWriter::new(file, table)?)),
+        }
+    }
+
+    pub fn serialize(&mut self, record: impl Serialize) -> Result<()> {
+        match self {
+            TableWriter::Csv(wtr) => Ok(wtr.serialize(record)?),
+            #[cfg(feature = "parquet")]
+            TableWriter::Parquet(wtr) => wtr.serialize(record),
+        }
+    }
+
+    /// Flush all records, parquet files are closed and cannot be written to again
+    pub fn finish(&mut self) -> Result<()> {
+        match self {
+            TableWriter::Csv(wtr) => Ok(wtr.flush()?),
+            #[cfg(feature = "parquet")]
+            TableWriter::Parquet(wtr) => wtr.finish(),
+        }
+    }
+}
+
+/// Csv header written for a serialisable record, used to check that schemas match the csv outputs
+#[cfg(test)]
+pub fn csv_header(record: impl Serialize) -> Vec<String> {
+    let mut wtr = csv::Writer::from_writer(vec![]);
+    wtr.serialize(record).unwrap();
+    let data = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+    data.lines()
+        .next()
+        .unwrap()
+        .split(',')
+        .map(String::from)
+        .collect()
+}
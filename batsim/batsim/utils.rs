@@ -1,5 +1,8 @@
-use indicatif::{ProgressBar, ProgressStyle};
+use anyhow::Result;
+use configuration::assignments::Assignments;
+use indicatif::{HumanCount, ProgressBar, ProgressStyle};
 use std::time::Duration;
+use tracer::{Drivers, Population};
 
 // https://github.com/sindresorhus/cli-spinners/blob/master/spinners.json
 const SPINNER: &[&str] = &[
@@ -42,3 +45,42 @@ pub fn default_progress_bar(count: u64) -> ProgressBar {
     );
     pb
 }
+
+/// Merge the traces of persons sharing a vehicle (given by a person attribute), if an attribute is given
+pub fn vehicles(
+    population: Population,
+    attribute: Option<&str>,
+) -> Result<(Population, Option<Drivers>)> {
+    let Some(attribute) = attribute else {
+        return Ok((population, None));
+    };
+    let spinner = default_spinner();
+    spinner.set_message(format!(
+        "Merging traces of shared vehicles by '{}'...",
+        attribute
+    ));
+    let persons = population.len();
+    let (vehicles, drivers) = population.into_vehicles(attribute)?;
+    spinner.finish_with_message(format!(
+        "Completed merging {} persons into {} vehicles ({} with overlapping trips)",
+        HumanCount(persons as u64),
+        HumanCount(vehicles.len() as u64),
+        HumanCount(drivers.conflicts as u64)
+    ));
+    Ok((vehicles, Some(drivers)))
+}
+
+/// Report agents missing from fixed assignments, which fall back to sampling their specs
+pub fn unassigned(assignments: Option<&Assignments>, population: &Population) {
+    let Some(assignments) = assignments else {
+        return;
+    };
+    let unassigned = assignments.unassigned(population.into_iter().map(|(pid, _)| pid.as_str()));
+    if unassigned > 0 {
+        println!(
+            "Warning: {} of {} agents have no fixed assignment, their specs are sampled",
+            HumanCount(unassigned as u64),
+            HumanCount(population.len() as u64)
+        );
+    }
+}
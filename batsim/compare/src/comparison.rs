@@ -0,0 +1,405 @@
+use anyhow::{bail, Result};
+use core::fmt;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use crate::scenario::{Metrics, Scenario};
+
+/// Difference of a metric between a scenario and the baseline (first) scenario
+#[derive(Serialize, Debug, PartialEq)]
+pub struct DeltaRecord<'a> {
+    pub section: &'static str,
+    pub key: String,
+    pub metric: String,
+    pub scenario: &'a str,
+    pub baseline: f64,
+    pub value: f64,
+    pub delta: f64,
+}
+
+/// Agent whose spec assignment or best plan differs from the baseline scenario
+#[derive(Serialize, Debug, PartialEq)]
+pub struct AgentChangeRecord<'a> {
+    pub pid: &'a str,
+    pub scenario: &'a str,
+    pub spec_changed: bool,
+    pub plan_changed: bool,
+    pub baseline_specs: Option<String>,
+    pub specs: Option<String>,
+    pub baseline_activity_charges: usize,
+    pub activity_charges: usize,
+}
+
+/// Compares scenarios against the first (baseline) scenario, missing metrics are taken as zero
+pub struct Comparison<'a> {
+    scenarios: &'a [Scenario],
+    deltas: Vec<DeltaRecord<'a>>,
+    agent_changes: Vec<AgentChangeRecord<'a>>,
+}
+
+impl<'a> Comparison<'a> {
+    /// Compare scenarios, scenario names (output directories) must be unique
+    pub fn new(scenarios: &'a [Scenario]) -> Result<Self> {
+        assert!(!scenarios.is_empty(), "comparison requires scenarios");
+        let mut names = HashSet::new();
+        for scenario in scenarios {
+            if !names.insert(scenario.name.as_str()) {
+                bail!(
+                    "scenario '{}' is given more than once, each output directory can only be compared once",
+                    scenario.name
+                )
+            }
+        }
+        Ok(Self {
+            scenarios,
+            deltas: deltas(scenarios),
+            agent_changes: agent_changes(scenarios),
+        })
+    }
+
+    fn baseline(&self) -> &'a Scenario {
+        &self.scenarios[0]
+    }
+
+    /// Metric deltas of totals, activities, groups and time profiles (total power by charge type)
+    pub fn deltas(&self) -> &[DeltaRecord<'a>] {
+        &self.deltas
+    }
+
+    /// Agents (aligned by pid) with changed spec assignments or best plans, ordered by pid
+    pub fn agent_changes(&self) -> &[AgentChangeRecord<'a>] {
+        &self.agent_changes
+    }
+}
+
+fn deltas(scenarios: &[Scenario]) -> Vec<DeltaRecord> {
+    let mut records = Vec::new();
+    let baseline = &scenarios[0];
+    for scenario in &scenarios[1..] {
+        let name = scenario.name.as_str();
+        push_deltas(
+            &mut records,
+            "totals",
+            name,
+            &single(&baseline.totals),
+            &single(&scenario.totals),
+        );
+        push_deltas(
+            &mut records,
+            "activities",
+            name,
+            &baseline.activities,
+            &scenario.activities,
+        );
+        push_deltas(
+            &mut records,
+            "groups",
+            name,
+            &baseline.groups,
+            &scenario.groups,
+        );
+        if let (Some(a), Some(b)) = (&baseline.profiles, &scenario.profiles) {
+            let keys: BTreeSet<&(String, u32, u32)> = a.keys().chain(b.keys()).collect();
+            for key in keys {
+                let (charge_type, start, end) = key;
+                let baseline = a.get(key).copied().unwrap_or_default();
+                let value = b.get(key).copied().unwrap_or_default();
+                records.push(DeltaRecord {
+                    section: "profiles",
+                    key: format!("{} {}-{}", charge_type, start, end),
+                    metric: "power_(kW)".to_string(),
+                    scenario: name,
+                    baseline,
+                    value,
+                    delta: value - baseline,
+                });
+            }
+        }
+    }
+    records
+}
+
+fn agent_changes(scenarios: &[Scenario]) -> Vec<AgentChangeRecord> {
+    let mut records = Vec::new();
+    let baseline = &scenarios[0];
+    for scenario in &scenarios[1..] {
+        let pids: BTreeSet<&String> = baseline.specs.keys().chain(scenario.specs.keys()).collect();
+        for pid in pids {
+            let baseline_specs = baseline.specs.get(pid);
+            let specs = scenario.specs.get(pid);
+            let baseline_plan = baseline.plan(pid);
+            let plan = scenario.plan(pid);
+            let spec_changed = baseline_specs != specs;
+            let plan_changed = baseline_plan != plan;
+            if spec_changed || plan_changed {
+                records.push(AgentChangeRecord {
+                    pid,
+                    scenario: &scenario.name,
+                    spec_changed,
+                    plan_changed,
+                    baseline_specs: baseline_specs.map(ToString::to_string),
+                    specs: specs.map(ToString::to_string),
+                    baseline_activity_charges: baseline_plan.len(),
+                    activity_charges: plan.len(),
+                });
+            }
+        }
+    }
+    records
+}
+
+// totals as a single unnamed key
+fn single(metrics: &Metrics) -> BTreeMap<String, Metrics> {
+    BTreeMap::from([(String::new(), metrics.clone())])
+}
+
+fn push_deltas<'a>(
+    records: &mut Vec<DeltaRecord<'a>>,
+    section: &'static str,
+    scenario: &'a str,
+    baseline: &BTreeMap<String, Metrics>,
+    other: &BTreeMap<String, Metrics>,
+) {
+    let empty = Metrics::new();
+    let keys: BTreeSet<&String> = baseline.keys().chain(other.keys()).collect();
+    for key in keys {
+        let a = baseline.get(key).unwrap_or(&empty);
+        let b = other.get(key).unwrap_or(&empty);
+        let metrics: BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+        for metric in metrics {
+            let baseline = a.get(metric).copied().unwrap_or_default();
+            let value = b.get(metric).copied().unwrap_or_default();
+            records.push(DeltaRecord {
+                section,
+                key: key.clone(),
+                metric: metric.clone(),
+                scenario,
+                baseline,
+                value,
+                delta: value - baseline,
+            });
+        }
+    }
+}
+
+// baseline value, and the value and delta of each other scenario
+type Row = (f64, Vec<Option<(f64, f64)>>);
+
+impl fmt::Display for Comparison<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let baseline = self.baseline();
+        let deltas = &self.deltas;
+
+        // table rows keyed by (section, key, metric)
+        let mut rows: BTreeMap<(usize, &str, &str), Row> = BTreeMap::new();
+        let columns = self.scenarios.len() - 1;
+        let scenario_index = |name: &str| {
+            self.scenarios[1..]
+                .iter()
+                .position(|scenario| scenario.name == name)
+                .unwrap()
+        };
+        for delta in deltas.iter().filter(|delta| delta.section != "profiles") {
+            let section = ["totals", "activities", "groups"]
+                .iter()
+                .position(|s| *s == delta.section)
+                .unwrap();
+            let row = rows
+                .entry((section, &delta.key, &delta.metric))
+                .or_insert_with(|| (delta.baseline, vec![None; columns]));
+            row.1[scenario_index(delta.scenario)] = Some((delta.value, delta.delta));
+        }
+
+        let labels: Vec<String> = rows
+            .keys()
+            .map(|(_, key, metric)| match key.is_empty() {
+                true => metric.to_string(),
+                false => format!("{} {}", key, metric),
+            })
+            .collect();
+        let width = labels
+            .iter()
+            .map(String::len)
+            .chain(std::iter::once(6))
+            .max()
+            .unwrap_or_default();
+        let cells: Vec<Vec<String>> = rows
+            .values()
+            .map(|(baseline, values)| {
+                std::iter::once(format!("{:.2}", baseline))
+                    .chain(values.iter().map(|value| match value {
+                        Some((value, delta)) => format!("{:.2} ({:+.2})", value, delta),
+                        None => "-".to_string(),
+                    }))
+                    .collect()
+            })
+            .collect();
+        let column_widths: Vec<usize> = self
+            .scenarios
+            .iter()
+            .enumerate()
+            .map(|(i, scenario)| {
+                cells
+                    .iter()
+                    .map(|row| row[i].len())
+                    .chain(std::iter::once(scenario.name.len()))
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect();
+
+        let mut section = None;
+        for (((s, _, _), label), row) in rows.keys().zip(labels.iter()).zip(cells.iter()) {
+            if section != Some(*s) {
+                write!(
+                    f,
+                    "\n\n[{}]\n{:width$}",
+                    ["Totals", "Activities", "Groups"][*s],
+                    "metric"
+                )?;
+                for (scenario, w) in self.scenarios.iter().zip(column_widths.iter()) {
+                    write!(f, "  {:>w$}", scenario.name)?;
+                }
+                section = Some(*s);
+            }
+            write!(f, "\n{:width$}", label)?;
+            for (cell, w) in row.iter().zip(column_widths.iter()) {
+                write!(f, "  {:>w$}", cell)?;
+            }
+        }
+
+        if baseline.profiles.is_some() {
+            write!(f, "\n\n[Profiles]")?;
+            for scenario in &self.scenarios[1..] {
+                let (min, max) = deltas
+                    .iter()
+                    .filter(|d| d.section == "profiles" && d.scenario == scenario.name)
+                    .fold(
+                        (None::<&DeltaRecord>, None::<&DeltaRecord>),
+                        |(min, max), d| {
+                            (
+                                Some(min.filter(|m| m.delta <= d.delta).unwrap_or(d)),
+                                Some(max.filter(|m| m.delta >= d.delta).unwrap_or(d)),
+                            )
+                        },
+                    );
+                match (min, max) {
+                    (Some(min), Some(max)) => write!(
+                        f,
+                        "\n{}: largest increase {:+.2} kW ({}), largest decrease {:+.2} kW ({})",
+                        scenario.name, max.delta, max.key, min.delta, min.key
+                    )?,
+                    _ => write!(f, "\n{}: no profiles to compare", scenario.name)?,
+                }
+            }
+        }
+
+        write!(f, "\n\n[Agents]")?;
+        for scenario in &self.scenarios[1..] {
+            let changes = self
+                .agent_changes
+                .iter()
+                .filter(|c| c.scenario == scenario.name);
+            let (specs, plans) = changes.fold((0, 0), |(specs, plans), c| {
+                (
+                    specs + c.spec_changed as usize,
+                    plans + c.plan_changed as usize,
+                )
+            });
+            write!(
+                f,
+                "\n{}: {} agents with changed specs, {} agents with changed plans",
+                scenario.name, specs, plans
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scenario::Specs;
+
+    fn scenario(name: &str, charge: f64, battery: &str, plan: Vec<(u32, u32, String)>) -> Scenario {
+        let specs = Specs {
+            battery: battery.to_string(),
+            trigger: "default".to_string(),
+            en_route: "default".to_string(),
+            activities: "home".to_string(),
+        };
+        Scenario {
+            name: name.to_string(),
+            totals: Metrics::from([("total_charge_(kWh)".to_string(), charge)]),
+            activities: BTreeMap::from([(
+                "home".to_string(),
+                Metrics::from([("total_charge_(kWh)".to_string(), charge)]),
+            )]),
+            specs: [("a".to_string(), specs)].into(),
+            plans: [("a".to_string(), plan)].into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_deltas() {
+        let scenarios = [
+            scenario("base", 10., "small", vec![]),
+            scenario("high", 15., "small", vec![]),
+        ];
+        let comparison = Comparison::new(&scenarios).unwrap();
+        assert_eq!(
+            comparison.deltas(),
+            vec![
+                DeltaRecord {
+                    section: "totals",
+                    key: String::new(),
+                    metric: "total_charge_(kWh)".to_string(),
+                    scenario: "high",
+                    baseline: 10.,
+                    value: 15.,
+                    delta: 5.,
+                },
+                DeltaRecord {
+                    section: "activities",
+                    key: "home".to_string(),
+                    metric: "total_charge_(kWh)".to_string(),
+                    scenario: "high",
+                    baseline: 10.,
+                    value: 15.,
+                    delta: 5.,
+                },
+            ]
+        );
+        assert!(comparison.agent_changes().is_empty());
+    }
+
+    #[test]
+    fn test_agent_changes() {
+        let scenarios = [
+            scenario("base", 10., "small", vec![(1, 0, "home".to_string())]),
+            scenario("spec", 10., "large", vec![(1, 0, "home".to_string())]),
+            scenario("plan", 10., "small", vec![(1, 10, "home".to_string())]),
+        ];
+        let comparison = Comparison::new(&scenarios).unwrap();
+        let changes = comparison.agent_changes();
+        assert_eq!(changes.len(), 2);
+        assert!(changes[0].spec_changed && !changes[0].plan_changed);
+        assert_eq!(
+            changes[0].specs.as_deref(),
+            Some("large|default|default|home")
+        );
+        assert!(!changes[1].spec_changed && changes[1].plan_changed);
+        assert!(comparison.to_string().contains("[Agents]"));
+    }
+
+    #[test]
+    fn test_duplicate_scenarios() {
+        let scenarios = [
+            scenario("base", 10., "small", vec![]),
+            scenario("out", 10., "small", vec![]),
+            scenario("out", 15., "small", vec![]),
+        ];
+        assert!(Comparison::new(&scenarios).is_err());
+    }
+}
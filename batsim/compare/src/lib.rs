@@ -0,0 +1,5 @@
+pub mod comparison;
+pub mod scenario;
+
+pub use comparison::{AgentChangeRecord, Comparison, DeltaRecord};
+pub use scenario::Scenario;
@@ -0,0 +1,195 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::BufReader,
+    path::Path,
+};
+
+/// Numeric metrics by name
+pub type Metrics = BTreeMap<String, f64>;
+
+/// Agent specification names, as written to 'specs.csv'
+#[derive(Debug, PartialEq, Clone)]
+pub struct Specs {
+    pub battery: String,
+    pub trigger: String,
+    pub en_route: String,
+    pub activities: String,
+}
+
+impl std::fmt::Display for Specs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}|{}|{}|{}",
+            self.battery, self.trigger, self.en_route, self.activities
+        )
+    }
+}
+
+#[derive(Deserialize)]
+struct SpecsRow {
+    pid: String,
+    battery: String,
+    trigger: String,
+    en_route: String,
+    activities: String,
+}
+
+#[derive(Deserialize)]
+struct EventRow {
+    charge_type: String,
+    pid: String,
+    day: u32,
+    #[serde(rename = "start_time_(s)")]
+    start_time: u32,
+    activity: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ProfileRow {
+    charge_type: String,
+    #[serde(rename = "start_time_(s)")]
+    start_time: u32,
+    #[serde(rename = "end_time_(s)")]
+    end_time: u32,
+    #[serde(rename = "power_(kW)")]
+    power: f64,
+}
+
+/// Results loaded from a batsim output directory (as written by `batsim run` or `batsim optimise`)
+#[derive(Debug, Default)]
+pub struct Scenario {
+    pub name: String,
+    pub totals: Metrics,
+    // keyed by activity type
+    pub activities: BTreeMap<String, Metrics>,
+    // keyed by "attribute=value"
+    pub groups: BTreeMap<String, Metrics>,
+    pub specs: HashMap<String, Specs>,
+    // activity charge events (day, start time, activity) of each agent's best plan
+    pub plans: HashMap<String, Vec<(u32, u32, String)>>,
+    // total power (kW) keyed by charge type and time bin
+    pub profiles: Option<BTreeMap<(String, u32, u32), f64>>,
+}
+
+impl Scenario {
+    /// Load results from given output directory, requires csv format outputs
+    pub fn load(dir: &Path) -> Result<Self> {
+        let name = dir.display().to_string();
+        if !dir.join("specs.csv").exists() && dir.join("specs.parquet").exists() {
+            bail!(
+                "'{}' outputs are parquet, comparison requires csv outputs",
+                dir.display()
+            )
+        }
+        let mut scenario = Scenario {
+            name,
+            ..Default::default()
+        };
+        scenario.load_summary(&dir.join("summary.json"))?;
+        scenario.load_specs(&dir.join("specs.csv"))?;
+        scenario.load_events(&dir.join("events.csv"))?;
+        let profiles_path = dir.join("profiles.csv");
+        if profiles_path.exists() {
+            scenario.load_profiles(&profiles_path)?;
+        }
+        Ok(scenario)
+    }
+
+    fn load_summary(&mut self, path: &Path) -> Result<()> {
+        let file = File::open(path).context(format!("unable to open '{}'", path.display()))?;
+        let summary: Map<String, Value> = serde_json::from_reader(BufReader::new(file))
+            .context(format!("failed to read summary '{}'", path.display()))?;
+        self.totals = metrics(&summary);
+        for activity in summary
+            .get("activities")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_object)
+        {
+            let key = activity
+                .get("activity")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            self.activities.insert(key.to_string(), metrics(activity));
+        }
+        for group in summary
+            .get("groups")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_object)
+        {
+            let attribute = group
+                .get("attribute")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            let value = group.get("value").and_then(Value::as_str).unwrap_or("");
+            self.groups
+                .insert(format!("{}={}", attribute, value), metrics(group));
+        }
+        Ok(())
+    }
+
+    fn load_specs(&mut self, path: &Path) -> Result<()> {
+        for row in reader(path)?.deserialize() {
+            let row: SpecsRow = row.context(format!("failed to read '{}'", path.display()))?;
+            let specs = Specs {
+                battery: row.battery,
+                trigger: row.trigger,
+                en_route: row.en_route,
+                activities: row.activities,
+            };
+            self.specs.insert(row.pid, specs);
+        }
+        Ok(())
+    }
+
+    fn load_events(&mut self, path: &Path) -> Result<()> {
+        for row in reader(path)?.deserialize() {
+            let row: EventRow = row.context(format!("failed to read '{}'", path.display()))?;
+            let plan = self.plans.entry(row.pid).or_default();
+            if let ("Activity", Some(activity)) = (row.charge_type.as_str(), row.activity) {
+                plan.push((row.day, row.start_time, activity));
+            }
+        }
+        for plan in self.plans.values_mut() {
+            plan.sort();
+        }
+        Ok(())
+    }
+
+    fn load_profiles(&mut self, path: &Path) -> Result<()> {
+        let mut profiles = BTreeMap::new();
+        for row in reader(path)?.deserialize() {
+            let row: ProfileRow = row.context(format!("failed to read '{}'", path.display()))?;
+            *profiles
+                .entry((row.charge_type, row.start_time, row.end_time))
+                .or_default() += row.power;
+        }
+        self.profiles = Some(profiles);
+        Ok(())
+    }
+
+    /// Agent best plan, agents without activity charge events have an empty plan
+    pub fn plan(&self, pid: &str) -> &[(u32, u32, String)] {
+        self.plans.get(pid).map(Vec::as_slice).unwrap_or_default()
+    }
+}
+
+/// Collect the numeric fields of a json object
+fn metrics(object: &Map<String, Value>) -> Metrics {
+    object
+        .iter()
+        .filter_map(|(k, v)| v.as_f64().map(|v| (k.clone(), v)))
+        .collect()
+}
+
+fn reader(path: &Path) -> Result<csv::Reader<File>> {
+    csv::Reader::from_path(path).context(format!("unable to open '{}'", path.display()))
+}
@@ -0,0 +1,174 @@
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::{collections::HashMap, fs::File, io::Read, path::Path};
+
+use crate::{
+    config::Config,
+    filter::FilterableSpec,
+    group::ConfigGroup,
+    groups::{
+        activity::ActivitySpec, battery::BatterySpec, en_route::EnRouteSpec, trigger::TriggerSpec,
+    },
+    handler::AgentConfig,
+    BatsimConfigError,
+};
+
+/// Name used in specs records for a missing spec (see `AgentConfig::to_record`)
+const NONE: &str = "None";
+
+/// Spec names assigned to an agent, as written to 'specs.csv'
+#[derive(Deserialize)]
+struct AssignmentRecord {
+    pid: String,
+    battery: String,
+    trigger: String,
+    en_route: String,
+    #[serde(default)]
+    activities: String,
+}
+
+/// Specs assigned to an agent
+#[derive(Debug)]
+struct Assignment<'a> {
+    battery: Option<&'a BatterySpec>,
+    trigger: Option<&'a TriggerSpec>,
+    en_route: Option<&'a EnRouteSpec>,
+    activities: Vec<&'a ActivitySpec>,
+}
+
+/// Fixed agent spec assignments, read from the specs output of a previous run.
+/// Spec names are validated against the config, filters and probabilities are not applied.
+#[derive(Debug, Default)]
+pub struct Assignments<'a> {
+    agents: HashMap<String, Assignment<'a>>,
+}
+
+impl<'a> Assignments<'a> {
+    /// Load assignments from a specs csv, for example the 'specs.csv' written by `batsim optimise`
+    pub fn load(path: &Path, config: &'a Config) -> Result<Self> {
+        let file =
+            File::open(path).context(format!("unable to open assignments '{}'", path.display()))?;
+        Self::from_reader(file, config)
+            .context(format!("failed to read assignments '{}'", path.display()))
+    }
+
+    pub fn from_reader<R: Read>(reader: R, config: &'a Config) -> Result<Self> {
+        let mut agents = HashMap::new();
+        for record in csv::Reader::from_reader(reader).deserialize() {
+            let record: AssignmentRecord = record?;
+            let pid = record.pid.as_str();
+            let activities = record
+                .activities
+                .split('+')
+                .filter(|name| !name.is_empty())
+                .map(|name| spec(&config.activity_group, name, pid))
+                .collect::<Result<Vec<&ActivitySpec>>>()?;
+            let assignment = Assignment {
+                battery: optional_spec(&config.battery_group, &record.battery, pid)?,
+                trigger: optional_spec(&config.trigger_group, &record.trigger, pid)?,
+                en_route: optional_spec(&config.enroute_group, &record.en_route, pid)?,
+                activities,
+            };
+            agents.insert(record.pid, assignment);
+        }
+        Ok(Self { agents })
+    }
+
+    /// Number of agents with assignments
+    pub fn len(&self) -> usize {
+        self.agents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.agents.is_empty()
+    }
+
+    /// Number of given agents without an assignment (their specs are sampled)
+    pub fn unassigned<'b>(&self, pids: impl IntoIterator<Item = &'b str>) -> usize {
+        pids.into_iter()
+            .filter(|pid| !self.agents.contains_key(*pid))
+            .count()
+    }
+
+    /// Agent config of the agent's assigned specs, if the agent has an assignment
+    pub fn agent_config(&self, pid: &'a str) -> Option<AgentConfig<'a>> {
+        let assignment = self.agents.get(pid)?;
+        Some(AgentConfig {
+            pid,
+            battery: assignment.battery,
+            trigger: assignment.trigger,
+            en_route: assignment.en_route,
+            activities: assignment.activities.clone(),
+        })
+    }
+}
+
+fn spec<'a, T: FilterableSpec>(group: &'a ConfigGroup<T>, name: &str, pid: &str) -> Result<&'a T> {
+    match group.by_name(name) {
+        Some(spec) => Ok(spec),
+        None => bail!(BatsimConfigError::UnknownAssignment(
+            name.to_string(),
+            T::GROUP.to_string(),
+            pid.to_string()
+        )),
+    }
+}
+
+fn optional_spec<'a, T: FilterableSpec>(
+    group: &'a ConfigGroup<T>,
+    name: &str,
+    pid: &str,
+) -> Result<Option<&'a T>> {
+    match name {
+        NONE | "" => Ok(None),
+        name => spec(group, name, pid).map(Some),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config::from_yaml(
+            "
+battery_group:
+- {name: small, capacity: 40, initial: 40, consumption_rate: 0.15}
+- {name: large, capacity: 80, initial: 80, consumption_rate: 0.15}
+activity_group:
+- {name: home, activities: [home], charge_rate: 7}
+- {name: work, activities: [work], charge_rate: 7}
+",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_assignments() {
+        let config = config();
+        let specs = "pid,battery,trigger,en_route,activities
+a,large,default,default,home+work
+b,None,None,None,
+";
+        let assignments = Assignments::from_reader(specs.as_bytes(), &config).unwrap();
+        assert_eq!(assignments.len(), 2);
+        let a = assignments.agent_config("a").unwrap();
+        assert_eq!(a.battery.unwrap().capacity, 80.0);
+        assert_eq!(a.activities.len(), 2);
+        let b = assignments.agent_config("b").unwrap();
+        assert!(b.battery.is_none() && b.activities.is_empty());
+        assert!(assignments.agent_config("c").is_none());
+        assert_eq!(assignments.unassigned(["a", "b", "c", "d"]), 2);
+    }
+
+    #[test]
+    fn test_unknown_assignment_fails() {
+        let config = config();
+        let specs = "pid,battery,trigger,en_route,activities\na,huge,default,default,home\n";
+        let err = Assignments::from_reader(specs.as_bytes(), &config).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown spec 'huge' in 'battery_group' assigned to pid: 'a'"
+        );
+    }
+}
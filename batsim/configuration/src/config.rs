@@ -1,13 +1,16 @@
 use anyhow::{bail, Context, Result};
-use serde::Deserialize;
-use std::{fs, path::PathBuf};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::{fs, path::Path, path::PathBuf};
 
 use crate::groups::{
     activity::ActivityGroup, battery::BatteryGroup, en_route::EnRouteGroup, trigger::TriggerGroup,
 };
-use crate::BatsimConfigError;
+use crate::{
+    overrides, ownership::OwnershipModel, temperature::TemperatureProfile, BatsimConfigError,
+};
 
-#[derive(Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct Config {
     /// Optional name for configuration
     pub name: Option<String>,
@@ -35,6 +38,15 @@ pub struct Config {
 
     #[serde(default)]
     pub activity_group: ActivityGroup,
+
+    /// Optional vehicle ownership model, used to choose battery specs instead of the battery group
+    /// filters and probabilities
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ownership: Option<OwnershipModel>,
+
+    /// Optional ambient temperature profile, affecting batteries with a temperature sensitivity
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<TemperatureProfile>,
 }
 
 impl Default for Config {
@@ -49,27 +61,120 @@ impl Default for Config {
             trigger_group: TriggerGroup::default(),
             enroute_group: EnRouteGroup::default(),
             activity_group: ActivityGroup::default(),
+            ownership: None,
+            temperature: None,
         }
     }
 }
 
 impl Config {
-    pub fn load(path: &PathBuf) -> Result<Self> {
-        let s = fs::read_to_string(path)
-            .context(format!("failed to read config from '{}'", path.display()))?;
-        Self::from_yaml(&s)
+    /// Load config from given path, resolving any 'extends' base configs
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::from_value(Self::load_value(path)?)
+    }
+
+    /// Load config from an optional path (otherwise default) and apply `key.path=value` overrides
+    pub fn build(path: Option<&Path>, overrides: &[String]) -> Result<Self> {
+        let mut value = match path {
+            Some(path) => Self::load_value(path)?,
+            None => Value::Null,
+        };
+        Self::apply_overrides(&mut value, overrides)?;
+        Self::from_value(value)
+    }
+
+    /// Apply `key.path=value` overrides to a config yaml value
+    pub fn apply_overrides(value: &mut Value, overrides: &[String]) -> Result<()> {
+        for s in overrides {
+            let (path, new) = overrides::parse(s)?;
+            overrides::set(value, &path, new)
+                .context(format!("failed to apply config override '{}'", s))?;
+        }
+        Ok(())
     }
 
     pub fn valid(&self) -> Result<()> {
         if self.scale.unwrap() < 0.0 {
             bail!(BatsimConfigError::InvalidScale)
         }
-        Ok(())
+        self.battery_group.valid()?;
+        for spec in self.battery_group.iter() {
+            spec.valid_energy_model()?;
+        }
+        self.trigger_group.valid()?;
+        self.enroute_group.valid()?;
+        self.activity_group.valid()?;
+        if let Some(spec) = self
+            .activity_group
+            .iter()
+            .find(|spec| spec.shared_by.is_some() && spec.name.is_none())
+        {
+            bail!(BatsimConfigError::UnnamedSharedCharger(
+                spec.shared_by.clone().unwrap()
+            ))
+        }
+        for spec in self.activity_group.iter() {
+            spec.valid_discharge()?;
+            spec.valid_smart()?;
+        }
+        if let Some(profile) = &self.temperature {
+            profile.valid()?;
+        }
+        match &self.ownership {
+            Some(model) => model.valid(&self.battery_group),
+            None => Ok(()),
+        }
     }
 
     pub fn from_yaml(s: &str) -> Result<Self> {
         serde_yaml::from_str(s).context("Failed to parse .yaml config")
     }
+
+    /// Load config as a yaml value, for example so that overrides can be applied before building.
+    /// A config can extend a base config (path relative to the config) using `extends: base.yaml`,
+    /// the config is deep merged into the base (see `overrides::merge`).
+    pub fn load_value(path: &Path) -> Result<Value> {
+        Self::load_extended(path, &mut Vec::new())
+    }
+
+    fn load_extended(path: &Path, visited: &mut Vec<PathBuf>) -> Result<Value> {
+        let canonical = path
+            .canonicalize()
+            .context(format!("failed to read config from '{}'", path.display()))?;
+        if visited.contains(&canonical) {
+            bail!(BatsimConfigError::CyclicExtends(path.display().to_string()))
+        }
+        visited.push(canonical);
+        let s = fs::read_to_string(path)
+            .context(format!("failed to read config from '{}'", path.display()))?;
+        let mut value: Value = serde_yaml::from_str(&s)
+            .context(format!("Failed to parse .yaml config '{}'", path.display()))?;
+        let extends = match &mut value {
+            Value::Mapping(mapping) => mapping.remove("extends"),
+            _ => None,
+        };
+        match extends {
+            Some(Value::String(base)) => {
+                let base_path = path.parent().unwrap_or(Path::new("")).join(base);
+                let mut base = Self::load_extended(&base_path, visited).context(format!(
+                    "failed to load base config for '{}'",
+                    path.display()
+                ))?;
+                overrides::merge(&mut base, value);
+                Ok(base)
+            }
+            Some(_) => bail!("config 'extends' must be a path, in '{}'", path.display()),
+            None => Ok(value),
+        }
+    }
+
+    pub fn from_value(value: Value) -> Result<Self> {
+        match value {
+            // empty config
+            Value::Null => Ok(Config::default()),
+            value => serde_yaml::from_value(value).context("Failed to parse .yaml config"),
+        }
+    }
 }
 
 fn default_scale() -> Option<f32> {
@@ -88,6 +193,7 @@ fn default_precision() -> Option<f32> {
 mod tests {
     use super::*;
     use std::str::FromStr;
+    use test_dir::{DirBuilder, TestDir};
 
     #[test]
     fn load_default_config() {
@@ -96,6 +202,37 @@ mod tests {
         assert_eq!(decoded, Config::default());
     }
 
+    #[test]
+    fn load_extended_config() {
+        let test_dir = TestDir::temp();
+        let temp_dir = test_dir.root();
+        fs::create_dir_all(temp_dir.join("variants")).unwrap();
+        fs::write(
+            temp_dir.join("base.yaml"),
+            "scale: 10\nbattery_group:\n- {name: default, capacity: 40, initial: 40, consumption_rate: 0.15}\n",
+        )
+        .unwrap();
+        let variant = temp_dir.join("variants/variant.yaml");
+        fs::write(
+            &variant,
+            "extends: ../base.yaml\nname: variant\nbattery_group:\n- {name: large, capacity: 80, initial: 80, consumption_rate: 0.15}\n",
+        )
+        .unwrap();
+        let config = Config::build(Some(&variant), &["seed=1".to_string()]).unwrap();
+        assert_eq!(config.name.as_deref(), Some("variant"));
+        assert_eq!(config.scale, Some(10.0));
+        assert_eq!(config.seed, Some(1));
+        let names: Vec<&str> = config
+            .battery_group
+            .iter()
+            .map(|spec| spec.name.as_deref().unwrap())
+            .collect();
+        assert_eq!(names, vec!["default", "large"]);
+
+        fs::write(temp_dir.join("loop.yaml"), "extends: loop.yaml\n").unwrap();
+        assert!(Config::load(&temp_dir.join("loop.yaml")).is_err());
+    }
+
     #[test]
     fn load_example_yaml_config() {
         let path = PathBuf::from_str("configs/sim_config.yaml").unwrap();
@@ -1,13 +1,90 @@
-use rand::Rng;
-use serde::Deserialize;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
 use tracer::population::PersonAttributes;
 
+use crate::{filters::Filters, utils, BatsimConfigError};
+
 pub trait FilterableSpec {
-    fn matches(&self, attributes: &PersonAttributes, rng: &mut impl Rng) -> bool;
+    /// Name of the config group, used (with the spec name) to derive random draws
+    const GROUP: &'static str;
+
+    fn name(&self) -> Option<&str>;
+
+    fn p(&self) -> Option<f32>;
+
+    fn share(&self) -> Option<f32>;
+
+    fn count(&self) -> Option<u32>;
+
+    fn filters(&self) -> Option<&Filters>;
+
+    /// Check if spec filters match given attributes
+    fn eligible(&self, attributes: &PersonAttributes) -> bool {
+        match self.filters() {
+            None => true,
+            Some(filters) => filters.filter(attributes),
+        }
+    }
+
+    /// Check if spec is available given attributes and a uniform random draw
+    fn matches(&self, attributes: &PersonAttributes, draw: f32) -> bool {
+        self.eligible(attributes) && utils::sample_p(self.p(), draw)
+    }
+
+    /// Exact quota of eligible agents, assigned over the population rather than by independent draws
+    fn quota(&self) -> Option<Quota> {
+        match (self.share(), self.count()) {
+            (Some(share), _) => Some(Quota::Share(share)),
+            (None, Some(count)) => Some(Quota::Count(count)),
+            (None, None) => None,
+        }
+    }
+
+    /// Check that the spec declares at most one of `p`, `share` or `count`, with valid values
+    fn valid(&self) -> Result<()> {
+        let invalid = |reason: &str| {
+            BatsimConfigError::InvalidQuota(
+                self.name().unwrap_or_default().to_string(),
+                Self::GROUP.to_string(),
+                reason.to_string(),
+            )
+        };
+        let declared = [
+            self.p().is_some(),
+            self.share().is_some(),
+            self.count().is_some(),
+        ];
+        if declared.iter().filter(|d| **d).count() > 1 {
+            bail!(invalid("only one of 'p', 'share' or 'count' can be used"))
+        }
+        if let Some(share) = self.share() {
+            if !(0.0..=1.0).contains(&share) {
+                bail!(invalid("'share' must be between 0 and 1"))
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Exact proportion or number of eligible agents to assign a spec to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Quota {
+    Share(f32),
+    Count(u32),
+}
+
+impl Quota {
+    /// Number of agents to select from given number of eligible agents
+    pub fn target(&self, eligible: usize) -> usize {
+        match self {
+            Quota::Share(share) => (share * eligible as f32).round() as usize,
+            Quota::Count(count) => (*count as usize).min(eligible),
+        }
+    }
 }
 
 /// Filter struct, holds a key and vec of valid values all as Strings
-#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Filter {
     pub key: String,
     pub values: Vec<String>,
@@ -1,10 +1,10 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use tracer::population::PersonAttributes;
 
 use crate::filter::Filter;
 
 /// Convenience struct for dealing with filters
-#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 #[serde(transparent)] // transparent so deserializing uses the internal Vec
 pub struct Filters(Vec<Filter>);
 
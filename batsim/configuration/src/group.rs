@@ -1,24 +1,134 @@
-use rand::Rng;
-use serde::Deserialize;
-use std::ops::{Deref, DerefMut};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    ops::{Deref, DerefMut},
+};
 
-use crate::filter::FilterableSpec;
-use tracer::population::PersonAttributes;
+use crate::{
+    filter::FilterableSpec,
+    sampler::{AgentSampler, Sampler},
+};
+use tracer::{population::PersonAttributes, Population};
 
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
 #[serde(transparent)] // transparent so derializing uses the internal Vec
 pub struct ConfigGroup<T>(Vec<T>);
 
 impl<T: FilterableSpec> ConfigGroup<T> {
-    pub fn find(&self, attributes: &PersonAttributes, rng: &mut impl Rng) -> Option<&T> {
-        self.iter().rev().find(|cnfg| cnfg.matches(attributes, rng))
+    /// Find the last spec available to the agent
+    pub fn find(&self, attributes: &PersonAttributes, sampler: &AgentSampler) -> Option<&T> {
+        self.iter()
+            .enumerate()
+            .rev()
+            .find(|(i, cnfg)| Self::available(*i, cnfg, attributes, sampler))
+            .map(|(_, cnfg)| cnfg)
     }
 
-    pub fn filter(&self, attributes: &PersonAttributes, rng: &mut impl Rng) -> Vec<&T> {
+    /// Filter all specs available to the agent
+    pub fn filter(&self, attributes: &PersonAttributes, sampler: &AgentSampler) -> Vec<&T> {
         self.iter()
-            .filter(|cnfg| cnfg.matches(attributes, rng))
+            .enumerate()
+            .filter(|(i, cnfg)| Self::available(*i, cnfg, attributes, sampler))
+            .map(|(_, cnfg)| cnfg)
             .collect()
     }
+
+    /// Get the last spec with given name
+    pub fn by_name(&self, name: &str) -> Option<&T> {
+        self.iter().rev().find(|cnfg| cnfg.name() == Some(name))
+    }
+
+    /// Check all specs are valid
+    pub fn valid(&self) -> Result<()> {
+        self.iter().try_for_each(FilterableSpec::valid)
+    }
+
+    /// Select the agents given each quota spec, keyed by spec position. Eligible agents (matching
+    /// the spec filters) are ranked by their stable draw and the quota is taken in order, so the
+    /// selection is exact and monotone in the quota. If `exclusive` (groups where a single spec is
+    /// found), agents given a later spec cannot be selected for earlier specs, so selection is
+    /// without replacement (quotas are still a share of all eligible agents).
+    pub fn select<'a>(
+        &self,
+        population: &'a Population,
+        sampler: &Sampler,
+        exclusive: bool,
+    ) -> Vec<(usize, HashSet<&'a str>)> {
+        let mut selections = Vec::new();
+        if self.iter().all(|cnfg| cnfg.quota().is_none()) {
+            return selections;
+        }
+        let agents: Vec<(&'a str, &'a PersonAttributes)> = population
+            .into_iter()
+            .map(|(pid, person)| (pid.as_str(), &person.attributes))
+            .collect();
+        let mut claimed = vec![false; agents.len()];
+        // later specs take priority when finding a single spec
+        let order: Vec<usize> = match exclusive {
+            true => (0..self.len()).rev().collect(),
+            false => (0..self.len()).collect(),
+        };
+        for i in order {
+            let cnfg = &self[i];
+            match cnfg.quota() {
+                Some(quota) => {
+                    // quota is of all eligible agents, selected from those not already claimed
+                    let eligible: Vec<usize> = (0..agents.len())
+                        .filter(|j| cnfg.eligible(agents[*j].1))
+                        .collect();
+                    let target = quota.target(eligible.len());
+                    // ties are ordered by pid (population order)
+                    let mut eligible: Vec<(f32, usize)> = eligible
+                        .into_iter()
+                        .filter(|j| !claimed[*j])
+                        .map(|j| (Self::draw(i, cnfg, &sampler.agent(agents[j].0)), j))
+                        .collect();
+                    eligible.sort_by(|a, b| a.0.total_cmp(&b.0));
+                    let mut selected = HashSet::new();
+                    for (_, j) in eligible.into_iter().take(target) {
+                        claimed[j] = exclusive;
+                        selected.insert(agents[j].0);
+                    }
+                    selections.push((i, selected));
+                }
+                None if exclusive => {
+                    for (j, (pid, attributes)) in agents.iter().enumerate() {
+                        if !claimed[j] {
+                            claimed[j] =
+                                cnfg.matches(attributes, Self::draw(i, cnfg, &sampler.agent(pid)));
+                        }
+                    }
+                }
+                None => {}
+            }
+        }
+        selections
+    }
+
+    // quota specs are only available to selected agents (none if quotas have not been selected)
+    fn available(
+        i: usize,
+        cnfg: &T,
+        attributes: &PersonAttributes,
+        sampler: &AgentSampler,
+    ) -> bool {
+        match (cnfg.quota(), sampler.selected(T::GROUP, i)) {
+            (_, Some(selected)) => selected,
+            (Some(_), None) => false,
+            (None, None) => cnfg.matches(attributes, Self::draw(i, cnfg, sampler)),
+        }
+    }
+
+    // draws are keyed by spec name, or position in the group if the spec is unnamed
+    fn draw(i: usize, cnfg: &T, sampler: &AgentSampler) -> f32 {
+        let key = match cnfg.name() {
+            Some(name) => Cow::Borrowed(name),
+            None => Cow::Owned(i.to_string()),
+        };
+        sampler.draw(T::GROUP, &key)
+    }
 }
 
 impl<T> From<Vec<T>> for ConfigGroup<T> {
@@ -49,39 +159,53 @@ impl<T> DerefMut for ConfigGroup<T> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{filter::Filter, filters::Filters, utils};
-    use rand::{rngs::SmallRng, SeedableRng};
+    use crate::{filter::Filter, filters::Filters};
+    use tracer::{Person, Trace};
 
     type TestGroup = ConfigGroup<TestSpec>;
 
-    #[derive(Deserialize, Debug, PartialEq, Clone)]
+    #[derive(Deserialize, Debug, PartialEq, Clone, Default)]
     struct TestSpec {
         pub name: Option<String>,
         pub p: Option<f32>,
+        pub share: Option<f32>,
+        pub count: Option<u32>,
         pub filters: Option<Filters>,
     }
 
-    impl Default for TestSpec {
-        fn default() -> Self {
-            TestSpec {
-                name: Some("default".to_string()),
-                p: None,
-                filters: None,
-            }
+    fn default_spec() -> TestSpec {
+        TestSpec {
+            name: Some("default".to_string()),
+            ..Default::default()
         }
     }
 
     impl FilterableSpec for TestSpec {
-        fn matches(&self, attributes: &PersonAttributes, rng: &mut impl Rng) -> bool {
-            match &self.filters {
-                None => utils::sample_p(self.p, rng),
-                Some(filters) => filters.filter(attributes) && utils::sample_p(self.p, rng),
-            }
+        const GROUP: &'static str = "test_group";
+
+        fn name(&self) -> Option<&str> {
+            self.name.as_deref()
+        }
+
+        fn p(&self) -> Option<f32> {
+            self.p
+        }
+
+        fn share(&self) -> Option<f32> {
+            self.share
+        }
+
+        fn count(&self) -> Option<u32> {
+            self.count
+        }
+
+        fn filters(&self) -> Option<&Filters> {
+            self.filters.as_ref()
         }
     }
     fn test_config_group() -> TestGroup {
         TestGroup::from(vec![
-            TestSpec::default(),
+            default_spec(),
             TestSpec {
                 name: Some("A".to_string()),
                 p: None,
@@ -95,12 +219,13 @@ mod tests {
                         values: vec!["B1".to_string(), "B2".to_string()],
                     },
                 ])),
+                ..Default::default()
             },
         ])
     }
     fn test_config_group_with_p() -> TestGroup {
         TestGroup::from(vec![
-            TestSpec::default(),
+            default_spec(),
             TestSpec {
                 name: Some("A".to_string()),
                 p: Some(0.5),
@@ -114,6 +239,7 @@ mod tests {
                         values: vec!["B1".to_string(), "B2".to_string()],
                     },
                 ])),
+                ..Default::default()
             },
         ])
     }
@@ -141,43 +267,36 @@ mod tests {
 
     #[test]
     fn test_find() {
-        let mut rng = SmallRng::from_entropy();
+        let sampler = Sampler::new(1234);
+        let agent = &sampler.agent("a");
         assert_eq!(
             test_config_group()
-                .find(&person_empty(), &mut rng)
+                .find(&person_empty(), agent)
                 .unwrap()
                 .name,
             Some("default".to_string())
         );
         assert_eq!(
-            test_config_group()
-                .find(&person_a(), &mut rng)
-                .unwrap()
-                .name,
+            test_config_group().find(&person_a(), agent).unwrap().name,
             Some("default".to_string())
         );
         assert_eq!(
-            test_config_group()
-                .find(&person_b(), &mut rng)
-                .unwrap()
-                .name,
+            test_config_group().find(&person_b(), agent).unwrap().name,
             Some("A".to_string())
         );
         assert_eq!(
-            test_config_group()
-                .find(&person_c(), &mut rng)
-                .unwrap()
-                .name,
+            test_config_group().find(&person_c(), agent).unwrap().name,
             Some("default".to_string())
         );
     }
 
     #[test]
     fn test_filter() {
-        let mut rng = SmallRng::seed_from_u64(1234);
+        let sampler = Sampler::new(1234);
+        let agent = &sampler.agent("a");
         assert_eq!(
             test_config_group()
-                .filter(&person_empty(), &mut rng)
+                .filter(&person_empty(), agent)
                 .iter()
                 .map(|c| c.name.as_ref().unwrap())
                 .collect::<Vec<&String>>(),
@@ -185,7 +304,7 @@ mod tests {
         );
         assert_eq!(
             test_config_group()
-                .filter(&person_a(), &mut rng)
+                .filter(&person_a(), agent)
                 .iter()
                 .map(|c| c.name.as_ref().unwrap())
                 .collect::<Vec<&String>>(),
@@ -193,7 +312,7 @@ mod tests {
         );
         assert_eq!(
             test_config_group()
-                .filter(&person_b(), &mut rng)
+                .filter(&person_b(), agent)
                 .iter()
                 .map(|c| c.name.as_ref().unwrap())
                 .collect::<Vec<&String>>(),
@@ -201,7 +320,7 @@ mod tests {
         );
         assert_eq!(
             test_config_group()
-                .filter(&person_c(), &mut rng)
+                .filter(&person_c(), agent)
                 .iter()
                 .map(|c| c.name.as_ref().unwrap())
                 .collect::<Vec<&String>>(),
@@ -211,53 +330,176 @@ mod tests {
 
     #[test]
     fn test_find_with_probs() {
-        for _ in 0..10 {
-            // failed to mock a generator so hacking seeds...
-            let mut rng = SmallRng::seed_from_u64(8); // 0.45353144, 0.32385755, 0.16778237
-            assert_eq!(
-                test_config_group_with_p()
-                    .find(&person_b(), &mut rng)
-                    .unwrap()
-                    .name,
-                Some("A".to_string())
-            );
-        }
-        for _ in 0..10 {
-            let mut rng = SmallRng::seed_from_u64(5); // 0.732753, 0.7052366, 0.71241844
+        let sampler = Sampler::new(1234);
+        let mut found = 0;
+        for i in 0..1000 {
+            let pid = i.to_string();
+            let agent = sampler.agent(&pid);
+            let expected = match agent.draw("test_group", "A") < 0.5 {
+                true => "A",
+                false => "default",
+            };
+            let name = test_config_group_with_p()
+                .find(&person_b(), &agent)
+                .unwrap()
+                .name
+                .clone()
+                .unwrap();
+            assert_eq!(name, expected);
+            // stable for repeated draws
             assert_eq!(
                 test_config_group_with_p()
-                    .find(&person_b(), &mut rng)
+                    .find(&person_b(), &agent)
                     .unwrap()
                     .name,
-                Some("default".to_string())
+                Some(name.clone())
             );
+            found += (name == "A") as usize;
         }
+        assert!((450..550).contains(&found));
     }
 
     #[test]
     fn test_filter_with_probs() {
-        for _ in 0..10 {
-            // failed to mock a generator so hacking seeds...
-            let mut rng = SmallRng::seed_from_u64(8); // 0.45353144, 0.32385755, 0.16778237
+        let sampler = Sampler::new(1234);
+        for i in 0..100 {
+            let pid = i.to_string();
+            let agent = sampler.agent(&pid);
+            let expected = match agent.draw("test_group", "A") < 0.5 {
+                true => vec!["default", "A"],
+                false => vec!["default"],
+            };
             assert_eq!(
                 test_config_group_with_p()
-                    .filter(&person_b(), &mut rng)
+                    .filter(&person_b(), &agent)
                     .iter()
                     .map(|c| c.name.as_ref().unwrap())
                     .collect::<Vec<&String>>(),
-                vec!["default", "A"]
+                expected
             );
         }
-        for _ in 0..10 {
-            let mut rng = SmallRng::seed_from_u64(5); // 0.732753, 0.7052366, 0.71241844
-            assert_eq!(
-                test_config_group_with_p()
-                    .filter(&person_b(), &mut rng)
-                    .iter()
-                    .map(|c| c.name.as_ref().unwrap())
-                    .collect::<Vec<&String>>(),
-                vec!["default"]
-            );
+    }
+
+    #[test]
+    fn test_find_is_monotone_in_p() {
+        let sampler = Sampler::new(1234);
+        let mut low = test_config_group_with_p();
+        low[1].p = Some(0.3);
+        for i in 0..1000 {
+            let pid = i.to_string();
+            let agent = sampler.agent(&pid);
+            if low.find(&person_b(), &agent).unwrap().name.as_deref() == Some("A") {
+                assert_eq!(
+                    test_config_group_with_p()
+                        .find(&person_b(), &agent)
+                        .unwrap()
+                        .name
+                        .as_deref(),
+                    Some("A")
+                );
+            }
+        }
+    }
+
+    fn population(n: usize) -> Population {
+        let people = (0..n)
+            .map(|i| {
+                let attributes = match i % 2 {
+                    0 => person_b(),
+                    _ => person_empty(),
+                };
+                let person = Person {
+                    attributes,
+                    trace: Trace::default(),
+                };
+                (format!("{:03}", i), person)
+            })
+            .collect();
+        Population { people }
+    }
+
+    fn found<'a>(group: &TestGroup, population: &'a Population, sampler: &Sampler) -> Vec<&'a str> {
+        population
+            .into_iter()
+            .filter(|(pid, person)| {
+                group
+                    .find(&person.attributes, &sampler.agent(pid))
+                    .and_then(|cnfg| cnfg.name.as_deref())
+                    == Some("A")
+            })
+            .map(|(pid, _)| pid.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_find_with_share_is_exact() {
+        let population = population(100);
+        let mut group = test_config_group();
+        group[1].share = Some(0.3);
+        let mut sampler = Sampler::new(1234);
+        sampler.add_quotas(&group, &population, true);
+        let selected = found(&group, &population, &sampler);
+        // 30% of the 50 eligible agents
+        assert_eq!(selected.len(), 15);
+        assert!(selected
+            .iter()
+            .all(|pid| pid.parse::<usize>().unwrap() % 2 == 0));
+
+        // monotone in share
+        group[1].share = Some(0.5);
+        let mut sampler = Sampler::new(1234);
+        sampler.add_quotas(&group, &population, true);
+        let more = found(&group, &population, &sampler);
+        assert_eq!(more.len(), 25);
+        assert!(selected.iter().all(|pid| more.contains(pid)));
+
+        // quotas are unavailable without a population pass
+        let sampler = Sampler::new(1234);
+        assert!(found(&group, &population, &sampler).is_empty());
+    }
+
+    #[test]
+    fn test_find_with_quotas_is_without_replacement() {
+        let population = population(100);
+        let group = TestGroup::from(vec![
+            default_spec(),
+            TestSpec {
+                name: Some("small".to_string()),
+                share: Some(0.3),
+                ..Default::default()
+            },
+            TestSpec {
+                name: Some("large".to_string()),
+                count: Some(20),
+                ..Default::default()
+            },
+        ]);
+        let mut sampler = Sampler::new(1234);
+        sampler.add_quotas(&group, &population, true);
+        let mut counts = std::collections::HashMap::new();
+        for (pid, person) in &population {
+            let name = group
+                .find(&person.attributes, &sampler.agent(pid))
+                .unwrap()
+                .name
+                .clone()
+                .unwrap();
+            *counts.entry(name).or_insert(0) += 1;
         }
+        assert_eq!(counts["large"], 20);
+        assert_eq!(counts["small"], 30);
+        assert_eq!(counts["default"], 50);
+    }
+
+    #[test]
+    fn test_invalid_quotas() {
+        let mut group = test_config_group_with_p();
+        assert!(group.valid().is_ok());
+        group[1].share = Some(0.5);
+        assert!(group.valid().is_err());
+        group[1].p = None;
+        assert!(group.valid().is_ok());
+        group[1].share = Some(1.5);
+        assert!(group.valid().is_err());
     }
 }
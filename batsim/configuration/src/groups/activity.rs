@@ -1,8 +1,7 @@
-use rand::Rng;
-use serde::Deserialize;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
 
-use crate::{filter::FilterableSpec, filters::Filters, group::ConfigGroup, utils};
-use tracer::population::PersonAttributes;
+use crate::{filter::FilterableSpec, filters::Filters, group::ConfigGroup, BatsimConfigError};
 
 pub type ActivityGroup = ConfigGroup<ActivitySpec>;
 
@@ -12,13 +11,80 @@ impl Default for ActivityGroup {
     }
 }
 
-#[derive(Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct ActivitySpec {
     pub name: Option<String>,
     pub activities: Vec<String>,
     pub charge_rate: f32,
     pub p: Option<f32>,
+    pub share: Option<f32>,
+    pub count: Option<u32>,
     pub filters: Option<Filters>,
+    /// Person attribute (such as a household id) of agents sharing a single charger
+    pub shared_by: Option<String>,
+    /// Optional bidirectional (vehicle-to-grid or vehicle-to-home) operation of the charger
+    pub discharge: Option<DischargeSpec>,
+    /// Optional managed charging, scheduling the charge within a stay rather than charging on arrival
+    pub smart: Option<SmartChargingSpec>,
+}
+
+/// Discharging of a parked battery during time of day windows, down to a floor state of charge
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct DischargeSpec {
+    /// Discharge rate (kW)
+    pub rate: f32,
+    /// Proportion of battery capacity below which the battery is not discharged
+    pub floor: f32,
+    /// Time of day windows (seconds from midnight) in which the battery is discharged
+    pub windows: Vec<[u32; 2]>,
+}
+
+impl DischargeSpec {
+    pub fn valid(&self) -> std::result::Result<(), String> {
+        if self.rate <= 0.0 {
+            return Err("rate must be positive".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.floor) {
+            return Err("floor must be between 0 and 1".to_string());
+        }
+        if self
+            .windows
+            .iter()
+            .any(|[start, end]| start >= end || *end > 24 * 60 * 60)
+        {
+            return Err("windows must be [start, end] seconds within a day".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Managed charging within a stay, charging is either delayed to a fixed time of day or
+/// scheduled into the cheapest slots of a price profile, but always completed before departure
+/// where the stay allows
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SmartChargingSpec {
+    /// Start charging at a time of day (seconds from midnight)
+    Delayed { start: u32 },
+    /// Price (or carbon intensity) of equal length slots covering the day from midnight, for
+    /// example 24 hourly or 48 half hourly values
+    Cheapest { prices: Vec<f32> },
+}
+
+impl SmartChargingSpec {
+    pub fn valid(&self) -> std::result::Result<(), String> {
+        match self {
+            SmartChargingSpec::Delayed { start } if *start >= 24 * 60 * 60 => {
+                Err("start must be seconds within a day".to_string())
+            }
+            SmartChargingSpec::Cheapest { prices }
+                if prices.is_empty() || (24 * 60 * 60) % prices.len() != 0 =>
+            {
+                Err("prices must divide the day into equal slots".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
 impl ActivitySpec {
@@ -35,7 +101,12 @@ impl ActivitySpec {
             activities,
             charge_rate,
             p,
+            share: None,
+            count: None,
             filters,
+            shared_by: None,
+            discharge: None,
+            smart: None,
         }
     }
 }
@@ -47,7 +118,12 @@ impl ActivitySpec {
             activities: self.activities.clone(),
             charge_rate: self.charge_rate,
             p: None,
+            share: None,
+            count: None,
             filters: None,
+            shared_by: self.shared_by.clone(),
+            discharge: self.discharge.clone(),
+            smart: self.smart.clone(),
         }
     }
 }
@@ -59,17 +135,65 @@ impl Default for ActivitySpec {
             activities: vec!["home".to_string()],
             charge_rate: 3.0,
             p: None,
+            share: None,
+            count: None,
             filters: None,
+            shared_by: None,
+            discharge: None,
+            smart: None,
         }
     }
 }
 
-impl FilterableSpec for ActivitySpec {
-    fn matches(&self, attributes: &PersonAttributes, rng: &mut impl Rng) -> bool {
-        match self.filters {
-            None => utils::sample_p(self.p, rng),
-            Some(ref filters) => filters.filter(attributes) & utils::sample_p(self.p, rng),
+impl ActivitySpec {
+    /// Check the discharge spec (if given) is valid
+    pub fn valid_discharge(&self) -> Result<()> {
+        if let Some(discharge) = &self.discharge {
+            if let Err(reason) = discharge.valid() {
+                bail!(BatsimConfigError::InvalidDischarge(
+                    self.name.clone().unwrap_or_default(),
+                    reason
+                ))
+            }
         }
+        Ok(())
+    }
+
+    /// Check the smart charging spec (if given) is valid
+    pub fn valid_smart(&self) -> Result<()> {
+        if let Some(smart) = &self.smart {
+            if let Err(reason) = smart.valid() {
+                bail!(BatsimConfigError::InvalidSmartCharging(
+                    self.name.clone().unwrap_or_default(),
+                    reason
+                ))
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FilterableSpec for ActivitySpec {
+    const GROUP: &'static str = "activity_group";
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn p(&self) -> Option<f32> {
+        self.p
+    }
+
+    fn share(&self) -> Option<f32> {
+        self.share
+    }
+
+    fn count(&self) -> Option<u32> {
+        self.count
+    }
+
+    fn filters(&self) -> Option<&Filters> {
+        self.filters.as_ref()
     }
 }
 
@@ -97,7 +221,12 @@ mod tests {
             activities: vec!["home".to_string()],
             charge_rate: 2.0,
             p: Some(0.5),
+            share: None,
+            count: None,
             filters: Some(Filters::from(expected_filters)),
+            shared_by: None,
+            discharge: None,
+            smart: None,
         });
         assert_eq!(decoded.activity_group, expected_charge_activities)
     }
@@ -125,16 +254,106 @@ mod tests {
                 activities: vec!["home".to_string()],
                 charge_rate: 2.0,
                 p: None,
+                share: None,
+                count: None,
                 filters: None,
+                shared_by: None,
+                discharge: None,
+                smart: None,
             },
             ActivitySpec {
                 name: Some("test_b".to_string()),
                 activities: vec!["shop".to_string(), "work".to_string()],
                 charge_rate: 3.0,
                 p: Some(0.5),
+                share: None,
+                count: None,
                 filters: Some(Filters::from(expected_filters)),
+                shared_by: None,
+                discharge: None,
+                smart: None,
             },
         ]);
         assert_eq!(decoded.activity_group, expected_charge_activities)
     }
+
+    #[test]
+    fn load_shared_charger() {
+        let str = "activity_group:
+  - name: home
+    activities: [home]
+    charge_rate: 7
+    shared_by: hid";
+        let decoded: Config = Config::from_yaml(str).unwrap();
+        assert_eq!(decoded.activity_group[0].shared_by.as_deref(), Some("hid"));
+        assert_eq!(
+            decoded.activity_group[0].spec().shared_by.as_deref(),
+            Some("hid")
+        );
+        assert!(decoded.valid().is_ok());
+
+        let unnamed = "activity_group:
+  - activities: [home]
+    charge_rate: 7
+    shared_by: hid";
+        assert!(Config::from_yaml(unnamed).unwrap().valid().is_err());
+    }
+
+    #[test]
+    fn load_discharge() {
+        let str = "activity_group:
+  - name: v2g
+    activities: [home]
+    charge_rate: 7
+    discharge: {rate: 7, floor: 0.5, windows: [[61200, 72000]]}";
+        let decoded: Config = Config::from_yaml(str).unwrap();
+        assert_eq!(
+            decoded.activity_group[0].spec().discharge,
+            Some(DischargeSpec {
+                rate: 7.0,
+                floor: 0.5,
+                windows: vec![[61200, 72000]],
+            })
+        );
+        assert!(decoded.valid().is_ok());
+
+        let invalid = "activity_group:
+  - name: v2g
+    activities: [home]
+    charge_rate: 7
+    discharge: {rate: 7, floor: 1.5, windows: [[72000, 61200]]}";
+        assert!(Config::from_yaml(invalid).unwrap().valid().is_err());
+    }
+
+    #[test]
+    fn load_smart_charging() {
+        let str = "activity_group:
+  - name: delayed
+    activities: [home]
+    charge_rate: 7
+    smart: {type: delayed, start: 3600}
+  - name: cheapest
+    activities: [home]
+    charge_rate: 7
+    smart: {type: cheapest, prices: [2, 1]}";
+        let decoded: Config = Config::from_yaml(str).unwrap();
+        assert_eq!(
+            decoded.activity_group[0].spec().smart,
+            Some(SmartChargingSpec::Delayed { start: 3600 })
+        );
+        assert_eq!(
+            decoded.activity_group[1].spec().smart,
+            Some(SmartChargingSpec::Cheapest {
+                prices: vec![2.0, 1.0]
+            })
+        );
+        assert!(decoded.valid().is_ok());
+
+        let invalid = "activity_group:
+  - name: cheapest
+    activities: [home]
+    charge_rate: 7
+    smart: {type: cheapest, prices: [1, 2, 3, 4, 5, 6, 7]}";
+        assert!(Config::from_yaml(invalid).unwrap().valid().is_err());
+    }
 }
@@ -1,7 +1,6 @@
-use crate::{filter::FilterableSpec, filters::Filters, group::ConfigGroup, utils};
-use rand::Rng;
-use serde::Deserialize;
-use tracer::population::PersonAttributes;
+use crate::{filter::FilterableSpec, filters::Filters, group::ConfigGroup, BatsimConfigError};
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
 
 pub type BatteryGroup = ConfigGroup<BatterySpec>;
 
@@ -11,13 +10,26 @@ impl Default for BatteryGroup {
     }
 }
 
-#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct BatterySpec {
     pub name: Option<String>,
     pub capacity: f32,
     pub initial: f32,
     pub consumption_rate: f32,
+    /// Fuel consumption rate (litres/km) once the battery is depleted, given for plug-in hybrids
+    pub fuel_consumption_rate: Option<f32>,
+    /// Model of energy used per link, defaults to the constant `consumption_rate`
+    pub energy_model: Option<EnergyModelSpec>,
+    /// Vehicle mass (kg), used for the potential energy of link gradients by the constant and speed
+    /// energy models (the physics model uses its own mass)
+    pub mass: Option<f32>,
+    /// Sensitivity of consumption and charging to ambient temperature (see config `temperature`)
+    pub temperature_sensitivity: Option<TemperatureSensitivity>,
+    /// Auxiliary loads while parked and driving
+    pub auxiliary: Option<AuxiliaryLoads>,
     pub p: Option<f32>,
+    pub share: Option<f32>,
+    pub count: Option<u32>,
     pub filters: Option<Filters>,
 }
 
@@ -29,22 +41,227 @@ impl Default for BatterySpec {
             capacity: 100.0,
             initial: 100.0,
             consumption_rate: 0.15,
+            fuel_consumption_rate: None,
+            energy_model: None,
+            mass: None,
+            temperature_sensitivity: None,
+            auxiliary: None,
             p: None,
+            share: None,
+            count: None,
             filters: None,
         }
     }
 }
 
-impl FilterableSpec for BatterySpec {
-    fn matches(&self, attributes: &PersonAttributes, rng: &mut impl Rng) -> bool {
-        match self.filters {
-            None => utils::sample_p(self.p, rng),
-            Some(ref filters) => filters.filter(attributes) & utils::sample_p(self.p, rng),
+/// Energy consumption model, selected by `type`
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EnergyModelSpec {
+    /// Constant `consumption_rate` (kWh/km)
+    Constant,
+    /// Consumption rate (kWh/km) by link speed (km/h), given as `[speed, rate]` points in order of
+    /// speed, linearly interpolated (and constant beyond the first and last points)
+    Speed { curve: Vec<[f32; 2]> },
+    /// Tractive energy from vehicle mass (kg), drag area (drag coefficient times frontal area, m2)
+    /// and rolling resistance coefficient, at the link speed. Drivetrain `efficiency` converts
+    /// battery to tractive energy, `regen_efficiency` recovers negative tractive energy.
+    Physics {
+        mass: f32,
+        drag_area: f32,
+        #[serde(default = "default_rolling_resistance")]
+        rolling_resistance: f32,
+        #[serde(default = "default_efficiency")]
+        efficiency: f32,
+        #[serde(default = "default_regen_efficiency")]
+        regen_efficiency: f32,
+    },
+}
+
+fn default_rolling_resistance() -> f32 {
+    0.01
+}
+
+fn default_efficiency() -> f32 {
+    0.9
+}
+
+fn default_regen_efficiency() -> f32 {
+    0.6
+}
+
+impl EnergyModelSpec {
+    pub fn valid(&self) -> std::result::Result<(), String> {
+        match self {
+            EnergyModelSpec::Constant => Ok(()),
+            EnergyModelSpec::Speed { curve } => {
+                if curve.is_empty() {
+                    return Err("speed curve is empty".to_string());
+                }
+                if curve.windows(2).any(|pair| pair[1][0] <= pair[0][0]) {
+                    return Err("speed curve must be in increasing order of speed".to_string());
+                }
+                Ok(())
+            }
+            EnergyModelSpec::Physics {
+                mass,
+                drag_area,
+                rolling_resistance,
+                efficiency,
+                regen_efficiency,
+            } => {
+                if *mass <= 0.0 || *drag_area < 0.0 || *rolling_resistance < 0.0 {
+                    return Err(
+                        "mass must be positive, drag and rolling resistance must not be negative"
+                            .to_string(),
+                    );
+                }
+                if *efficiency <= 0.0 || *efficiency > 1.0 {
+                    return Err("efficiency must be in (0, 1]".to_string());
+                }
+                if !(0.0..=1.0).contains(regen_efficiency) {
+                    return Err("regen_efficiency must be in [0, 1]".to_string());
+                }
+                Ok(())
+            }
         }
     }
 }
 
+const DAY: u32 = 24 * 60 * 60;
+
+/// Auxiliary loads (kW) drawn from the battery in addition to traction
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
+pub struct AuxiliaryLoads {
+    /// Standby drain while parked
+    #[serde(default)]
+    pub standby: f32,
+    /// Cabin preconditioning before each departure (or before the preconditioning time)
+    #[serde(default)]
+    pub preconditioning: f32,
+    /// Time (seconds) spent preconditioning before each departure
+    #[serde(default)]
+    pub preconditioning_duration: u32,
+    /// Optional time of day (seconds from midnight) of a scheduled departure, if given the cabin
+    /// is only preconditioned before this time each day (if parked), rather than before each
+    /// departure
+    #[serde(default)]
+    pub preconditioning_time: Option<u32>,
+    /// Load while driving, such as HVAC, applied for the duration of each link
+    #[serde(default)]
+    pub driving: f32,
+}
+
+impl AuxiliaryLoads {
+    /// Energy (kWs) drawn while parked from start to end (trace seconds), including any
+    /// preconditioning at the end of the stay or before the preconditioning time
+    pub fn parked(&self, start: u32, end: u32) -> f32 {
+        let duration = end.saturating_sub(start);
+        let preconditioning = match self.preconditioning_time {
+            None => duration.min(self.preconditioning_duration),
+            Some(time) => (start / DAY..=end / DAY + 1)
+                .map(|day| {
+                    let window_end = day * DAY + time;
+                    let window_start = window_end.saturating_sub(self.preconditioning_duration);
+                    window_end.min(end).saturating_sub(window_start.max(start))
+                })
+                .sum(),
+        };
+        self.standby * duration as f32 + self.preconditioning * preconditioning as f32
+    }
+
+    /// Energy (kWs) drawn while driving for given duration (seconds)
+    pub fn driving(&self, duration: u32) -> f32 {
+        self.driving * duration as f32
+    }
+}
+
+/// Minimum proportion of the charge rate accepted when cold
+const MIN_CHARGE_FACTOR: f32 = 0.1;
+
+/// Proportional change in consumption and charge rate per °C away from a reference temperature
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct TemperatureSensitivity {
+    /// Temperature (°C) at which consumption and charging are unaffected
+    #[serde(default = "default_reference_temperature")]
+    pub reference: f32,
+    /// Increase in consumption per °C below the reference (heating)
+    #[serde(default)]
+    pub heating: f32,
+    /// Increase in consumption per °C above the reference (cooling)
+    #[serde(default)]
+    pub cooling: f32,
+    /// Reduction in charge rate per °C below the reference
+    #[serde(default)]
+    pub charging: f32,
+}
+
+fn default_reference_temperature() -> f32 {
+    20.0
+}
+
+impl TemperatureSensitivity {
+    /// Consumption multiplier at given temperature (°C)
+    pub fn consumption_factor(&self, temperature: f32) -> f32 {
+        1.0 + self.heating * (self.reference - temperature).max(0.0)
+            + self.cooling * (temperature - self.reference).max(0.0)
+    }
+
+    /// Charge rate multiplier at given temperature (°C), at least `MIN_CHARGE_FACTOR`
+    pub fn charge_factor(&self, temperature: f32) -> f32 {
+        (1.0 - self.charging * (self.reference - temperature).max(0.0)).max(MIN_CHARGE_FACTOR)
+    }
+}
+
+impl FilterableSpec for BatterySpec {
+    const GROUP: &'static str = "battery_group";
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn p(&self) -> Option<f32> {
+        self.p
+    }
+
+    fn share(&self) -> Option<f32> {
+        self.share
+    }
+
+    fn count(&self) -> Option<u32> {
+        self.count
+    }
+
+    fn filters(&self) -> Option<&Filters> {
+        self.filters.as_ref()
+    }
+}
+
 impl BatterySpec {
+    /// Check if the battery is a plug-in hybrid (falling back to fuel when depleted)
+    pub fn is_hybrid(&self) -> bool {
+        self.fuel_consumption_rate.is_some()
+    }
+
+    /// Check the energy model (if given) and mass are valid
+    pub fn valid_energy_model(&self) -> Result<()> {
+        if matches!(self.mass, Some(mass) if mass <= 0.0) {
+            bail!(BatsimConfigError::InvalidEnergyModel(
+                self.name.clone().unwrap_or_default(),
+                "mass must be positive".to_string()
+            ))
+        }
+        if let Some(model) = &self.energy_model {
+            if let Err(reason) = model.valid() {
+                bail!(BatsimConfigError::InvalidEnergyModel(
+                    self.name.clone().unwrap_or_default(),
+                    reason
+                ))
+            }
+        }
+        Ok(())
+    }
+
     #[allow(dead_code)]
     pub fn unit() -> Self {
         BatterySpec {
@@ -52,7 +269,14 @@ impl BatterySpec {
             capacity: 1.0 / 3600.0,
             initial: 1.0 / 3600.0,
             consumption_rate: 1.0 / 3.6,
+            fuel_consumption_rate: None,
+            energy_model: None,
+            mass: None,
+            temperature_sensitivity: None,
+            auxiliary: None,
             p: None,
+            share: None,
+            count: None,
             filters: None,
         }
     }
@@ -94,6 +318,20 @@ impl BatterySpecBuilder {
         self.battery.consumption_rate = consumption_rate;
         self
     }
+    /// Fuel consumption rate in litres/km, making the battery a plug-in hybrid
+    pub fn fuel_consumption_rate(mut self, fuel_consumption_rate: f32) -> BatterySpecBuilder {
+        self.battery.fuel_consumption_rate = Some(fuel_consumption_rate);
+        self
+    }
+    pub fn energy_model(mut self, energy_model: EnergyModelSpec) -> BatterySpecBuilder {
+        self.battery.energy_model = Some(energy_model);
+        self
+    }
+    /// Vehicle mass in kg
+    pub fn mass(mut self, mass: f32) -> BatterySpecBuilder {
+        self.battery.mass = Some(mass);
+        self
+    }
     pub fn build(self) -> BatterySpec {
         self.battery
     }
@@ -188,6 +426,110 @@ battery_group:
         )
     }
 
+    #[test]
+    fn load_hybrid_battery() {
+        let str = "battery_group:
+  - name: phev
+    capacity: 12
+    initial: 12
+    consumption_rate: 0.2
+    fuel_consumption_rate: 0.06";
+        let decoded: Config = Config::from_yaml(str).unwrap();
+        let battery = &decoded.battery_group[0];
+        assert_eq!(battery.fuel_consumption_rate, Some(0.06));
+        assert!(battery.is_hybrid());
+        assert!(!BatterySpec::default().is_hybrid());
+    }
+
+    #[test]
+    fn load_energy_models() {
+        let str = "battery_group:
+  - name: speed
+    capacity: 60
+    initial: 60
+    consumption_rate: 0.15
+    energy_model:
+      type: speed
+      curve: [[10, 0.2], [50, 0.12], [120, 0.2]]
+  - {name: physics, capacity: 60, initial: 60, consumption_rate: 0.15,
+     energy_model: {type: physics, mass: 1800, drag_area: 0.6}}
+  - {name: constant, capacity: 60, initial: 60, consumption_rate: 0.15, mass: 1500,
+     energy_model: {type: constant}}";
+        let decoded: Config = Config::from_yaml(str).unwrap();
+        assert_eq!(
+            decoded.battery_group[0].energy_model,
+            Some(EnergyModelSpec::Speed {
+                curve: vec![[10.0, 0.2], [50.0, 0.12], [120.0, 0.2]]
+            })
+        );
+        assert_eq!(
+            decoded.battery_group[1].energy_model,
+            Some(EnergyModelSpec::Physics {
+                mass: 1800.0,
+                drag_area: 0.6,
+                rolling_resistance: 0.01,
+                efficiency: 0.9,
+                regen_efficiency: 0.6,
+            })
+        );
+        assert_eq!(decoded.battery_group[2].mass, Some(1500.0));
+        assert!(decoded.valid().is_ok());
+
+        let invalid = "battery_group:
+  - {name: speed, capacity: 60, initial: 60, consumption_rate: 0.15,
+     energy_model: {type: speed, curve: [[50, 0.12], [10, 0.2]]}}";
+        let decoded: Config = Config::from_yaml(invalid).unwrap();
+        assert!(decoded.valid().is_err());
+        let invalid = "battery_group:
+  - {name: constant, capacity: 60, initial: 60, consumption_rate: 0.15, mass: 0}";
+        let decoded: Config = Config::from_yaml(invalid).unwrap();
+        assert!(decoded.valid().is_err());
+    }
+
+    #[test]
+    fn load_temperature_sensitivity() {
+        let str = "battery_group:
+  - {name: test, capacity: 60, initial: 60, consumption_rate: 0.15,
+     temperature_sensitivity: {heating: 0.02, cooling: 0.01, charging: 0.05}}";
+        let decoded: Config = Config::from_yaml(str).unwrap();
+        let sensitivity = decoded.battery_group[0]
+            .temperature_sensitivity
+            .clone()
+            .unwrap();
+        assert_eq!(sensitivity.reference, 20.0);
+        assert_eq!(sensitivity.consumption_factor(20.0), 1.0);
+        assert_eq!(sensitivity.consumption_factor(0.0), 1.4);
+        assert_eq!(sensitivity.consumption_factor(30.0), 1.1);
+        assert_eq!(sensitivity.charge_factor(30.0), 1.0);
+        assert_eq!(sensitivity.charge_factor(10.0), 0.5);
+        assert_eq!(sensitivity.charge_factor(-20.0), MIN_CHARGE_FACTOR);
+    }
+
+    #[test]
+    fn load_auxiliary_loads() {
+        let str = "battery_group:
+  - {name: taxi, capacity: 60, initial: 60, consumption_rate: 0.15,
+     auxiliary: {standby: 0.01, preconditioning: 3, preconditioning_duration: 600, driving: 1}}";
+        let decoded: Config = Config::from_yaml(str).unwrap();
+        let auxiliary = decoded.battery_group[0].auxiliary.clone().unwrap();
+        assert_eq!(auxiliary.parked(0, 100), 301.0);
+        assert_eq!(auxiliary.parked(0, 1000), 1810.0);
+        assert_eq!(auxiliary.driving(60), 60.0);
+
+        // preconditioning for a 07:00 departure, parked overnight and during the day
+        let scheduled = AuxiliaryLoads {
+            preconditioning_time: Some(25200),
+            ..auxiliary
+        };
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-3;
+        assert!(close(
+            scheduled.parked(64800, 86400 + 28800),
+            504.0 + 1800.0
+        ));
+        assert!(close(scheduled.parked(32400, 61200), 288.0));
+        assert!(close(scheduled.parked(24900, 32400), 75.0 + 900.0));
+    }
+
     #[test]
     fn load_battery_filter_group() {
         let str = "name: test
@@ -1,8 +1,6 @@
-use rand::Rng;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-use crate::{filter::FilterableSpec, filters::Filters, group::ConfigGroup, utils};
-use tracer::population::PersonAttributes;
+use crate::{filter::FilterableSpec, filters::Filters, group::ConfigGroup};
 
 pub type EnRouteGroup = ConfigGroup<EnRouteSpec>;
 
@@ -12,12 +10,14 @@ impl Default for EnRouteGroup {
     }
 }
 
-#[derive(Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
 #[serde(default)]
 pub struct EnRouteSpec {
     pub name: Option<String>,
     pub charge_rate: f32,
     pub p: Option<f32>,
+    pub share: Option<f32>,
+    pub count: Option<u32>,
     pub filters: Option<Filters>,
 }
 
@@ -28,6 +28,8 @@ impl EnRouteSpec {
             name,
             charge_rate: rate,
             p,
+            share: None,
+            count: None,
             filters,
         }
     }
@@ -39,17 +41,34 @@ impl Default for EnRouteSpec {
             name: Some("default".to_string()),
             charge_rate: 10.0,
             p: None,
+            share: None,
+            count: None,
             filters: None,
         }
     }
 }
 
 impl FilterableSpec for EnRouteSpec {
-    fn matches(&self, attributes: &PersonAttributes, rng: &mut impl Rng) -> bool {
-        match self.filters {
-            None => utils::sample_p(self.p, rng),
-            Some(ref filters) => filters.filter(attributes) & utils::sample_p(self.p, rng),
-        }
+    const GROUP: &'static str = "enroute_group";
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn p(&self) -> Option<f32> {
+        self.p
+    }
+
+    fn share(&self) -> Option<f32> {
+        self.share
+    }
+
+    fn count(&self) -> Option<u32> {
+        self.count
+    }
+
+    fn filters(&self) -> Option<&Filters> {
+        self.filters.as_ref()
     }
 }
 
@@ -74,6 +93,8 @@ mod tests {
             name: Some("test".to_string()),
             charge_rate: 10.0,
             p: Some(0.5),
+            share: None,
+            count: None,
             filters: Some(expected_filters),
         });
         assert_eq!(decoded.enroute_group, expected)
@@ -1,7 +1,5 @@
-use crate::{filter::FilterableSpec, filters::Filters, group::ConfigGroup, utils};
-use rand::Rng;
-use serde::Deserialize;
-use tracer::population::PersonAttributes;
+use crate::{filter::FilterableSpec, filters::Filters, group::ConfigGroup};
+use serde::{Deserialize, Serialize};
 
 pub type TriggerGroup = ConfigGroup<TriggerSpec>;
 
@@ -11,11 +9,13 @@ impl Default for TriggerGroup {
     }
 }
 
-#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct TriggerSpec {
     pub name: Option<String>,
     pub trigger: f32, // todo - ensure this is between 0 and 1 inclusive
     pub p: Option<f32>,
+    pub share: Option<f32>,
+    pub count: Option<u32>,
     pub filters: Option<Filters>,
 }
 
@@ -25,17 +25,34 @@ impl Default for TriggerSpec {
             name: Some("default".to_string()),
             trigger: 0.2,
             p: None,
+            share: None,
+            count: None,
             filters: None,
         }
     }
 }
 
 impl FilterableSpec for TriggerSpec {
-    fn matches(&self, attributes: &PersonAttributes, rng: &mut impl Rng) -> bool {
-        match self.filters {
-            None => utils::sample_p(self.p, rng),
-            Some(ref filters) => filters.filter(attributes) & utils::sample_p(self.p, rng),
-        }
+    const GROUP: &'static str = "trigger_group";
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn p(&self) -> Option<f32> {
+        self.p
+    }
+
+    fn share(&self) -> Option<f32> {
+        self.share
+    }
+
+    fn count(&self) -> Option<u32> {
+        self.count
+    }
+
+    fn filters(&self) -> Option<&Filters> {
+        self.filters.as_ref()
     }
 }
 
@@ -45,6 +62,8 @@ impl TriggerSpec {
             name: Some("empty".to_string()),
             trigger: 0.0,
             p: None,
+            share: None,
+            count: None,
             filters: None,
         }
     }
@@ -73,12 +92,16 @@ mod tests {
                 name: Some("default".to_string()),
                 trigger: 0.2,
                 p: None,
+                share: None,
+                count: None,
                 filters: None,
             },
             TriggerSpec {
                 name: Some("brave".to_string()),
                 trigger: 0.1,
                 p: Some(0.5),
+                share: None,
+                count: None,
                 filters: Some(expected_filter),
             },
         ]);
@@ -1,12 +1,14 @@
-use anyhow::Result;
-use rand::Rng;
+use anyhow::{Context, Result};
 use serde::Serialize;
 
 use crate::{
+    assignments::Assignments,
     config::Config,
     groups::{
         activity::ActivitySpec, battery::BatterySpec, en_route::EnRouteSpec, trigger::TriggerSpec,
     },
+    ownership::OwnershipModel,
+    sampler::Sampler,
     BatsimConfigError,
 };
 use tracer::Person;
@@ -29,19 +31,47 @@ pub struct AgentConfigRecord<'a> {
 }
 
 impl<'a> AgentConfig<'a> {
+    /// Build agent config from the config specs available to the agent, if the config has an
+    /// ownership model it is used to choose the battery spec
     pub fn build(
         config: &'a Config,
         pid: &'a str,
         person: &'a Person,
-        rng: &mut impl Rng,
-    ) -> AgentConfig<'a> {
+        sampler: &Sampler,
+    ) -> Result<AgentConfig<'a>> {
         let attributes = &person.attributes;
-        AgentConfig {
+        let agent_sampler = &sampler.agent(pid);
+        let battery = match &config.ownership {
+            Some(model) => {
+                let draw = agent_sampler.draw(OwnershipModel::GROUP, "battery");
+                model
+                    .choose(attributes, draw)
+                    .context(format!("ownership model failed for pid: '{}'", pid))?
+                    .battery
+                    .as_deref()
+                    .and_then(|name| config.battery_group.by_name(name))
+            }
+            None => config.battery_group.find(attributes, agent_sampler),
+        };
+        Ok(AgentConfig {
             pid,
-            battery: config.battery_group.find(attributes, rng),
-            trigger: config.trigger_group.find(attributes, rng),
-            en_route: config.enroute_group.find(attributes, rng),
-            activities: config.activity_group.filter(attributes, rng),
+            battery,
+            trigger: config.trigger_group.find(attributes, agent_sampler),
+            en_route: config.enroute_group.find(attributes, agent_sampler),
+            activities: config.activity_group.filter(attributes, agent_sampler),
+        })
+    }
+    /// Use the agent's fixed assignment if available, otherwise build from the config
+    pub fn assign(
+        config: &'a Config,
+        pid: &'a str,
+        person: &'a Person,
+        sampler: &Sampler,
+        assignments: Option<&Assignments<'a>>,
+    ) -> Result<AgentConfig<'a>> {
+        match assignments.and_then(|assignments| assignments.agent_config(pid)) {
+            Some(agent_config) => Ok(agent_config),
+            None => Self::build(config, pid, person, sampler),
         }
     }
     /// Check that enroute charging is available if a battery is available
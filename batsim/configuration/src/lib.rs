@@ -1,3 +1,4 @@
+pub mod assignments;
 pub mod charge_plan;
 pub mod config;
 pub mod filter;
@@ -5,7 +6,10 @@ pub mod filters;
 pub mod group;
 pub mod groups;
 pub mod handler;
+pub mod overrides;
+pub mod ownership;
 pub mod sampler;
+pub mod temperature;
 pub mod utils;
 
 use thiserror::Error;
@@ -20,4 +24,43 @@ pub enum BatsimConfigError {
 
     #[error("charge 'trigger' not made available for pid: '{0}'")]
     AgentMissingTrigger(String),
+
+    #[error("invalid config key path: '{0}'")]
+    InvalidKeyPath(String),
+
+    #[error("unknown key '{0}' in config key path: '{1}'")]
+    UnknownKey(String, String),
+
+    #[error("invalid config override '{0}', expected 'key.path=value'")]
+    InvalidOverride(String),
+
+    #[error("config extends itself: '{0}'")]
+    CyclicExtends(String),
+
+    #[error("invalid quota for spec '{0}' in '{1}': {2}")]
+    InvalidQuota(String, String, String),
+
+    #[error("invalid ownership model: {0}")]
+    InvalidOwnershipModel(String),
+
+    #[error("attribute '{0}' is not numeric: '{1}'")]
+    NonNumericAttribute(String, String),
+
+    #[error("unknown spec '{0}' in '{1}' assigned to pid: '{2}'")]
+    UnknownAssignment(String, String, String),
+
+    #[error("shared charger spec in 'activity_group' requires a name (shared by '{0}')")]
+    UnnamedSharedCharger(String),
+
+    #[error("invalid energy model for battery spec '{0}': {1}")]
+    InvalidEnergyModel(String, String),
+
+    #[error("hourly temperature profile requires 24 values, found {0}")]
+    InvalidTemperatureProfile(usize),
+
+    #[error("invalid discharge for activity spec '{0}': {1}")]
+    InvalidDischarge(String, String),
+
+    #[error("invalid smart charging for activity spec '{0}': {1}")]
+    InvalidSmartCharging(String, String),
 }
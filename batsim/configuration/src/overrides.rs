@@ -0,0 +1,211 @@
+use anyhow::{bail, Result};
+use serde_yaml::{Mapping, Value};
+
+use crate::BatsimConfigError;
+
+/// Set a config value at given key path, for example `scale` or `battery_group.default.capacity`.
+/// Path segments into groups (lists) can be either a spec name or an index.
+/// Missing keys in mappings are created, missing group specs are an error.
+pub fn set(root: &mut Value, path: &str, value: Value) -> Result<()> {
+    let segments: Vec<&str> = path.split('.').collect();
+    if segments.iter().any(|segment| segment.is_empty()) {
+        bail!(BatsimConfigError::InvalidKeyPath(path.to_string()))
+    }
+    let mut current = root;
+    for (i, segment) in segments.iter().enumerate() {
+        let last = i == segments.len() - 1;
+        if current.is_null() {
+            *current = Value::Mapping(Mapping::new());
+        }
+        current = match current {
+            Value::Mapping(mapping) => {
+                let key = Value::String(segment.to_string());
+                if last {
+                    mapping.insert(key, value);
+                    return Ok(());
+                }
+                mapping.entry(key).or_insert(Value::Null)
+            }
+            Value::Sequence(sequence) => {
+                let index = match segment.parse::<usize>() {
+                    Ok(index) if index < sequence.len() => index,
+                    _ => match sequence
+                        .iter()
+                        .position(|spec| spec.get("name").and_then(Value::as_str) == Some(segment))
+                    {
+                        Some(index) => index,
+                        None => bail!(BatsimConfigError::UnknownKey(
+                            segment.to_string(),
+                            path.to_string()
+                        )),
+                    },
+                };
+                if last {
+                    sequence[index] = value;
+                    return Ok(());
+                }
+                &mut sequence[index]
+            }
+            _ => bail!(BatsimConfigError::InvalidKeyPath(path.to_string())),
+        };
+    }
+    Ok(())
+}
+
+/// Parse a `key.path=value` override, the value is parsed as yaml (falling back to a string)
+pub fn parse(s: &str) -> Result<(String, Value)> {
+    let Some((path, value)) = s.split_once('=') else {
+        bail!(BatsimConfigError::InvalidOverride(s.to_string()))
+    };
+    let value = serde_yaml::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()));
+    Ok((path.trim().to_string(), value))
+}
+
+/// Deep merge a config into a base config. Mappings are merged by key.
+/// Groups (lists of specs) are merged by spec name: specs replace base specs with the same name,
+/// otherwise they are appended. Any other values (including lists of values) replace base values.
+pub fn merge(base: &mut Value, other: Value) {
+    match (base, other) {
+        (Value::Mapping(base), Value::Mapping(other)) => {
+            for (key, value) in other {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (Value::Sequence(base), Value::Sequence(other))
+            if base.iter().chain(other.iter()).all(Value::is_mapping) =>
+        {
+            for spec in other {
+                let name = spec.get("name").and_then(Value::as_str);
+                match base.iter_mut().find(|existing| {
+                    name.is_some() && existing.get("name").and_then(Value::as_str) == name
+                }) {
+                    Some(existing) => *existing = spec,
+                    None => base.push(spec),
+                }
+            }
+        }
+        (base, other) => *base = other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn yaml(s: &str) -> Value {
+        serde_yaml::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn set_by_key_name_and_index() {
+        let mut root = yaml(
+            "
+scale: 1
+battery_group:
+- name: small
+  capacity: 40
+- name: large
+  capacity: 80
+",
+        );
+        set(&mut root, "scale", yaml("10")).unwrap();
+        set(&mut root, "battery_group.large.capacity", yaml("100")).unwrap();
+        set(&mut root, "battery_group.0.capacity", yaml("50")).unwrap();
+        set(&mut root, "seed", yaml("1234")).unwrap();
+        assert_eq!(
+            root,
+            yaml(
+                "
+scale: 10
+battery_group:
+- name: small
+  capacity: 50
+- name: large
+  capacity: 100
+seed: 1234
+"
+            )
+        );
+    }
+
+    #[test]
+    fn set_creates_missing_mappings() {
+        let mut root = Value::Null;
+        set(&mut root, "a.b", yaml("1")).unwrap();
+        assert_eq!(root, yaml("a: {b: 1}"));
+    }
+
+    #[test]
+    fn parse_overrides() {
+        assert_eq!(
+            parse("scale=10").unwrap(),
+            ("scale".to_string(), yaml("10"))
+        );
+        assert_eq!(
+            parse("name=a=b").unwrap(),
+            ("name".to_string(), Value::String("a=b".to_string()))
+        );
+        assert_eq!(
+            parse("activity_group.0.activities=[home, work]").unwrap().1,
+            yaml("[home, work]")
+        );
+        assert!(parse("scale").is_err());
+    }
+
+    #[test]
+    fn merge_groups_by_spec_name() {
+        let mut base = yaml(
+            "
+name: base
+scale: 1
+battery_group:
+- name: small
+  capacity: 40
+  filters: [{key: subpopulation, values: [low income]}]
+- name: large
+  capacity: 80
+",
+        );
+        merge(
+            &mut base,
+            yaml(
+                "
+name: variant
+battery_group:
+- name: small
+  capacity: 50
+- name: huge
+  capacity: 120
+",
+            ),
+        );
+        assert_eq!(
+            base,
+            yaml(
+                "
+name: variant
+scale: 1
+battery_group:
+- name: small
+  capacity: 50
+- name: large
+  capacity: 80
+- name: huge
+  capacity: 120
+"
+            )
+        );
+    }
+
+    #[test]
+    fn set_unknown_spec_fails() {
+        let mut root = yaml("battery_group: [{name: small}]");
+        assert!(set(&mut root, "battery_group.large.capacity", yaml("1")).is_err());
+        assert!(set(&mut root, "battery_group..capacity", yaml("1")).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::{groups::battery::BatteryGroup, BatsimConfigError};
+use tracer::population::PersonAttributes;
+
+/// Multinomial logit model of vehicle ownership, used to choose each agent's battery spec.
+/// Each alternative has a utility of its constant plus the sum of its attribute coefficients,
+/// alternatives are chosen with (softmax) probability using a stable random draw.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct OwnershipModel {
+    pub alternatives: Vec<Alternative>,
+}
+
+/// Ownership alternative, for example a vehicle class, or no EV (no battery)
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct Alternative {
+    pub name: String,
+    /// Name of the battery spec (in the battery group), no battery if not given
+    pub battery: Option<String>,
+    /// Alternative specific constant
+    #[serde(default)]
+    pub constant: f32,
+    #[serde(default)]
+    pub coefficients: Vec<Coefficient>,
+}
+
+/// Utility coefficient on a person attribute, agents without the attribute contribute nothing
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(untagged)]
+pub enum Coefficient {
+    /// Coefficient multiplied by a numeric attribute value
+    Numeric { attribute: String, value: f32 },
+    /// Coefficient for each value of a categorical attribute (missing values contribute nothing)
+    Categorical {
+        attribute: String,
+        values: BTreeMap<String, f32>,
+    },
+}
+
+impl Coefficient {
+    fn utility(&self, attributes: &PersonAttributes) -> Result<f32> {
+        match self {
+            Coefficient::Numeric { attribute, value } => match attributes.get(attribute) {
+                None => Ok(0.0),
+                Some(x) => match x.trim().parse::<f32>() {
+                    Ok(x) => Ok(value * x),
+                    Err(_) => bail!(BatsimConfigError::NonNumericAttribute(
+                        attribute.to_string(),
+                        x.to_string()
+                    )),
+                },
+            },
+            Coefficient::Categorical { attribute, values } => Ok(attributes
+                .get(attribute)
+                .and_then(|x| values.get(x))
+                .copied()
+                .unwrap_or(0.0)),
+        }
+    }
+}
+
+impl OwnershipModel {
+    /// Name used to derive random draws
+    pub const GROUP: &'static str = "ownership";
+
+    /// Utility of each alternative given person attributes
+    pub fn utilities(&self, attributes: &PersonAttributes) -> Result<Vec<f32>> {
+        self.alternatives
+            .iter()
+            .map(|alternative| {
+                alternative
+                    .coefficients
+                    .iter()
+                    .try_fold(alternative.constant, |utility, coefficient| {
+                        Ok(utility + coefficient.utility(attributes)?)
+                    })
+            })
+            .collect()
+    }
+
+    /// Choice probability of each alternative given person attributes
+    pub fn probabilities(&self, attributes: &PersonAttributes) -> Result<Vec<f32>> {
+        let utilities = self.utilities(attributes)?;
+        // subtract max utility to avoid overflow
+        let max = utilities.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let weights: Vec<f32> = utilities.iter().map(|u| (u - max).exp()).collect();
+        let total: f32 = weights.iter().sum();
+        Ok(weights.iter().map(|w| w / total).collect())
+    }
+
+    /// Choose an alternative given person attributes and a uniform random draw
+    pub fn choose(&self, attributes: &PersonAttributes, draw: f32) -> Result<&Alternative> {
+        let mut cumulative = 0.0;
+        let probabilities = self.probabilities(attributes)?;
+        for (alternative, p) in self.alternatives.iter().zip(probabilities) {
+            cumulative += p;
+            if draw < cumulative {
+                return Ok(alternative);
+            }
+        }
+        // rounding, draw is above the cumulative total
+        Ok(self.alternatives.last().unwrap())
+    }
+
+    /// Check the model has alternatives and that alternative batteries are in the battery group
+    pub fn valid(&self, batteries: &BatteryGroup) -> Result<()> {
+        if self.alternatives.is_empty() {
+            bail!(BatsimConfigError::InvalidOwnershipModel(
+                "no alternatives".to_string()
+            ))
+        }
+        for alternative in &self.alternatives {
+            if let Some(battery) = &alternative.battery {
+                if batteries.by_name(battery).is_none() {
+                    bail!(BatsimConfigError::InvalidOwnershipModel(format!(
+                        "unknown battery spec '{}' for alternative '{}'",
+                        battery, alternative.name
+                    )))
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn config() -> Config {
+        Config::from_yaml(
+            "
+battery_group:
+- {name: small, capacity: 40, initial: 40, consumption_rate: 0.15}
+- {name: large, capacity: 80, initial: 80, consumption_rate: 0.2}
+ownership:
+  alternatives:
+  - name: none
+  - name: small_ev
+    battery: small
+    constant: -1.0
+    coefficients:
+    - {attribute: age, value: 0.02}
+  - name: large_ev
+    battery: large
+    constant: -2.0
+    coefficients:
+    - {attribute: income, values: {high: 1.5, medium: 0.5}}
+",
+        )
+        .unwrap()
+    }
+
+    fn attributes(pairs: &[(&str, &str)]) -> PersonAttributes {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_utilities_and_probabilities() {
+        let config = config();
+        let model = config.ownership.as_ref().unwrap();
+        assert!(model.valid(&config.battery_group).is_ok());
+        let person = attributes(&[("age", "50"), ("income", "high")]);
+        assert_eq!(model.utilities(&person).unwrap(), vec![0.0, 0.0, -0.5]);
+        let p = model.probabilities(&person).unwrap();
+        let e = (-0.5f32).exp();
+        assert!((p[0] - 1.0 / (2.0 + e)).abs() < 1e-6);
+        assert!((p[2] - e / (2.0 + e)).abs() < 1e-6);
+
+        // missing attributes contribute nothing
+        assert_eq!(
+            model.utilities(&PersonAttributes::new()).unwrap(),
+            vec![0.0, -1.0, -2.0]
+        );
+        assert!(model.utilities(&attributes(&[("age", "old")])).is_err());
+    }
+
+    #[test]
+    fn test_choose() {
+        let config = config();
+        let model = config.ownership.as_ref().unwrap();
+        let person = attributes(&[("age", "50"), ("income", "high")]);
+        assert_eq!(model.choose(&person, 0.0).unwrap().name, "none");
+        assert_eq!(model.choose(&person, 0.5).unwrap().name, "small_ev");
+        assert_eq!(model.choose(&person, 0.99).unwrap().name, "large_ev");
+        assert_eq!(model.choose(&person, 1.0).unwrap().name, "large_ev");
+    }
+
+    #[test]
+    fn test_unknown_battery_is_invalid() {
+        let mut config = config();
+        config.ownership.as_mut().unwrap().alternatives[1].battery = Some("huge".to_string());
+        assert!(config.valid().is_err());
+    }
+}
@@ -1,36 +1,176 @@
-use rand::rngs::SmallRng;
-use rand::SeedableRng;
+use std::collections::{HashMap, HashSet};
 
-pub fn new(seed: Option<u64>) -> SmallRng {
-    match seed {
-        None => SmallRng::from_entropy(),
-        Some(seed) => SmallRng::seed_from_u64(seed),
+use crate::{config::Config, filter::FilterableSpec, group::ConfigGroup};
+use tracer::Population;
+
+/// Return given seed, or a new random seed if none is given, so that the seed used can be recorded
+pub fn resolve_seed(seed: Option<u64>) -> u64 {
+    seed.unwrap_or_else(rand::random)
+}
+
+/// Create a sampler from given seed, or a new random seed if none is given
+pub fn new<'a>(seed: Option<u64>) -> Sampler<'a> {
+    Sampler::new(resolve_seed(seed))
+}
+
+/// Agents selected for each quota spec, keyed by config group and spec position
+type Quotas<'a> = HashMap<(&'static str, usize), HashSet<&'a str>>;
+
+/// Source of stable random draws. Rather than consuming a shared random stream (in population and
+/// config order), each draw is derived from hashing (seed, pid, group, spec name). So an agent's draws
+/// do not change when other agents or specs are added, and because a spec is sampled as `draw < p`,
+/// assignment is monotone in `p` (an agent given a spec at p=0.3 is also given it at p=0.5).
+/// Specs with a quota (`share` or `count`) are instead assigned by a population level pass
+/// (see `with_quotas`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sampler<'a> {
+    seed: u64,
+    quotas: Quotas<'a>,
+}
+
+impl<'a> Sampler<'a> {
+    pub fn new(seed: u64) -> Self {
+        Sampler {
+            seed,
+            quotas: HashMap::new(),
+        }
+    }
+
+    /// Select the agents of given population for each quota spec in the config. Quota specs are
+    /// only available to selected agents, so this is required before building agent configs.
+    pub fn with_quotas(mut self, config: &Config, population: &'a Population) -> Self {
+        self.add_quotas(&config.battery_group, population, true);
+        self.add_quotas(&config.trigger_group, population, true);
+        self.add_quotas(&config.enroute_group, population, true);
+        self.add_quotas(&config.activity_group, population, false);
+        self
+    }
+
+    /// Select the agents of given population for each quota spec in a group (see `ConfigGroup::select`)
+    pub fn add_quotas<T: FilterableSpec>(
+        &mut self,
+        group: &ConfigGroup<T>,
+        population: &'a Population,
+        exclusive: bool,
+    ) {
+        for (i, selected) in group.select(population, self, exclusive) {
+            self.quotas.insert((T::GROUP, i), selected);
+        }
+    }
+
+    /// Sampler for a single agent
+    pub fn agent<'b>(&'b self, pid: &'b str) -> AgentSampler<'b> {
+        AgentSampler {
+            seed: self.seed,
+            pid,
+            quotas: &self.quotas,
+        }
     }
 }
 
-#[cfg(test)]
-mod tests {
+/// Stable random draws (and quota selections) for a single agent
+#[derive(Debug, Clone, Copy)]
+pub struct AgentSampler<'a> {
+    seed: u64,
+    pid: &'a str,
+    quotas: &'a Quotas<'a>,
+}
+
+impl AgentSampler<'_> {
+    /// If the agent is selected for the quota spec at given position, None if the spec has no quota
+    pub fn selected(&self, group: &'static str, spec: usize) -> Option<bool> {
+        self.quotas
+            .get(&(group, spec))
+            .map(|selected| selected.contains(self.pid))
+    }
+
+    /// Uniform draw in [0, 1) for given config group and spec
+    pub fn draw(&self, group: &str, spec: &str) -> f32 {
+        let mut hash = FNV_OFFSET;
+        for bytes in [
+            &self.seed.to_le_bytes()[..],
+            self.pid.as_bytes(),
+            group.as_bytes(),
+            spec.as_bytes(),
+        ] {
+            for byte in bytes {
+                hash = (hash ^ *byte as u64).wrapping_mul(FNV_PRIME);
+            }
+            // separator so that ("ab", "c") and ("a", "bc") differ
+            hash = (hash ^ 0xff).wrapping_mul(FNV_PRIME);
+        }
+        // use top 24 bits (f32 precision) of the mixed hash
+        (mix(hash) >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
 
-    use rand::Rng;
+// 64 bit FNV-1a, implemented here (rather than std hashers) so that draws are stable across releases
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
 
+// splitmix64 finaliser, to spread FNV hashes of similar inputs
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
     use super::*;
+    use crate::utils::sample_p;
 
     #[test]
-    fn sample_no_seed() {
-        let mut rng = new(None);
-        let _n: f32 = rng.gen();
+    fn resolve_given_seed() {
+        assert_eq!(resolve_seed(Some(1234)), 1234);
+    }
+    #[test]
+    fn draws_are_stable() {
+        let (a, b) = (new(Some(1234)), new(Some(1234)));
+        let (a, b) = (a.agent("a"), b.agent("a"));
+        assert_eq!(
+            a.draw("battery_group", "default"),
+            b.draw("battery_group", "default")
+        );
+        // draws must not change between releases
+        assert_eq!(a.draw("battery_group", "default"), 0.7319381);
+    }
+    #[test]
+    fn draws_differ_by_key() {
+        let sampler = new(Some(1234));
+        let draw = sampler.agent("a").draw("battery_group", "default");
+        assert_ne!(draw, sampler.agent("b").draw("battery_group", "default"));
+        assert_ne!(draw, sampler.agent("a").draw("trigger_group", "default"));
+        assert_ne!(draw, sampler.agent("a").draw("battery_group", "other"));
+        assert_ne!(
+            draw,
+            new(Some(1)).agent("a").draw("battery_group", "default")
+        );
     }
     #[test]
-    fn sample_with_seed() {
-        let mut rng = new(Some(1234));
-        let _n: f32 = rng.gen();
+    fn draws_are_uniform() {
+        let sampler = new(Some(1234));
+        let draws: Vec<f32> = (0..10000)
+            .map(|i| {
+                sampler
+                    .agent(&i.to_string())
+                    .draw("battery_group", "default")
+            })
+            .collect();
+        assert!(draws.iter().all(|d| (0.0..1.0).contains(d)));
+        let mean = draws.iter().sum::<f32>() / draws.len() as f32;
+        assert!((mean - 0.5).abs() < 0.01);
+        let below = draws.iter().filter(|d| **d < 0.3).count();
+        assert!((2850..3150).contains(&below));
     }
     #[test]
-    fn sample_consistently_with_seed() {
-        let mut rng_a = new(Some(1234));
-        let mut rng_b = new(Some(1234));
-        for _ in 0..10 {
-            assert_eq!(rng_a.gen::<f32>(), rng_b.gen::<f32>());
+    fn sampling_is_monotone() {
+        let sampler = new(Some(1234));
+        for i in 0..1000 {
+            let draw = sampler.agent(&i.to_string()).draw("battery_group", "ev");
+            if sample_p(Some(0.3), draw) {
+                assert!(sample_p(Some(0.5), draw));
+            }
         }
     }
 }
@@ -0,0 +1,90 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+use crate::BatsimConfigError;
+
+const HOURS: usize = 24;
+
+/// Ambient temperature (°C) by hour of day, given by a named season or hourly values
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(untagged)]
+pub enum TemperatureProfile {
+    Season {
+        season: Season,
+    },
+    /// Temperature of each hour of the day, from midnight
+    Hourly {
+        hourly: Vec<f32>,
+    },
+}
+
+/// Typical season, a daily cycle around the season's mean temperature, coldest at 03:00 and
+/// warmest at 15:00
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Season {
+    Winter,
+    Spring,
+    Summer,
+    Autumn,
+}
+
+impl Season {
+    /// Mean temperature and daily amplitude (°C)
+    fn climate(&self) -> (f32, f32) {
+        match self {
+            Season::Winter => (5.0, 3.0),
+            Season::Spring => (10.0, 5.0),
+            Season::Summer => (18.0, 6.0),
+            Season::Autumn => (11.0, 4.0),
+        }
+    }
+
+    fn temperature(&self, hour: usize) -> f32 {
+        let (mean, amplitude) = self.climate();
+        mean + amplitude * (2.0 * PI * (hour as f32 - 15.0) / HOURS as f32).cos()
+    }
+}
+
+impl TemperatureProfile {
+    /// Check that hourly profiles have a value for each hour
+    pub fn valid(&self) -> Result<()> {
+        if let TemperatureProfile::Hourly { hourly } = self {
+            if hourly.len() != HOURS {
+                bail!(BatsimConfigError::InvalidTemperatureProfile(hourly.len()))
+            }
+        }
+        Ok(())
+    }
+
+    /// Temperature (°C) of each hour of the day
+    pub fn hourly(&self) -> [f32; HOURS] {
+        let mut temperatures = [0.0; HOURS];
+        for (hour, temperature) in temperatures.iter_mut().enumerate() {
+            *temperature = match self {
+                TemperatureProfile::Season { season } => season.temperature(hour),
+                TemperatureProfile::Hourly { hourly } => hourly[hour],
+            };
+        }
+        temperatures
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+
+    #[test]
+    fn load_temperature_profiles() {
+        let config = Config::from_yaml("temperature: {season: winter}").unwrap();
+        let profile = config.temperature.unwrap();
+        let hourly = profile.hourly();
+        assert_eq!(hourly[15], 8.0);
+        assert!((hourly[3] - 2.0).abs() < 1e-5);
+        assert!(profile.valid().is_ok());
+
+        let config = Config::from_yaml("temperature: {hourly: [1, 2, 3]}").unwrap();
+        assert!(config.valid().is_err());
+    }
+}
@@ -1,9 +1,7 @@
-use rand::Rng;
-
-pub fn sample_p(p: Option<f32>, rng: &mut impl Rng) -> bool {
+/// Sample a spec with probability p, using a uniform draw in [0, 1)
+pub fn sample_p(p: Option<f32>, draw: f32) -> bool {
     match p {
         None => true,
-        Some(p) if p > rng.gen() => true,
-        _ => false,
+        Some(p) => draw < p,
     }
 }
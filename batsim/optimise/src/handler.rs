@@ -1,18 +1,38 @@
 use anyhow::{Context, Result};
 
 use configuration::{charge_plan::ActivityChargingPlanner, config::Config, handler::AgentConfig};
-use simulate::record::{AgentSimulationRecord, EventsRecord};
+use simulate::{
+    charger::{ChargerAccess, Cycle},
+    record::{AgentSimulationRecord, EventsRecord},
+    scoring::score_events,
+};
 use tracer::Person;
 
-use super::run;
+use super::{
+    run,
+    shared::{self, Agent, ChargerRecord, Schedule},
+};
+
+// total score of jointly optimised agents (see `score_events`)
+type Score = (f32, f32, f32, f32);
 
 pub struct OptimiseHandler<'a> {
     pub config: &'a Config,
+    pub record_soc: bool,
 }
 
 impl OptimiseHandler<'_> {
     pub fn new(config: &Config) -> OptimiseHandler {
-        OptimiseHandler { config }
+        OptimiseHandler {
+            config,
+            record_soc: false,
+        }
+    }
+
+    /// Keep the battery state of charge timeline of each optimised agent
+    pub fn with_soc(mut self, record_soc: bool) -> Self {
+        self.record_soc = record_soc;
+        self
     }
 
     pub fn optimise<'a>(
@@ -25,30 +45,316 @@ impl OptimiseHandler<'_> {
         match agent_config.battery {
             // run simulations
             Some(_battery_spec) => {
-                let _trigger_spec = agent_config.trigger.context(format!(
-                    "agent {pid}, no trigger spec provided, agent has a battery so requires a 'trigger' specification"
-                ))?;
-                let _en_route_spec = agent_config.en_route.context(format!(
-                    "agent {pid}, no en-route charging spec provided, agent has a battery so requires an 'en-route' charging specification"
-                ))?;
-                let activity_charging_planner =
-                    ActivityChargingPlanner::new(agent_config.activities.clone());
-                self.config.patience.context("no patience provided")?;
-                self.config
-                    .precision
-                    .context("no closing precision provided")?;
-                let mut result = run::run_simulations(
+                let mut result = self.simulate(
+                    config,
                     pid,
                     person,
                     &agent_config,
-                    activity_charging_planner,
-                    config,
-                )
-                .context(format!("failed find result for '{}'", pid))?;
+                    &ChargerAccess::unrestricted(),
+                )?;
                 result.finalise(config);
                 Ok(result)
             }
             None => Ok(AgentSimulationRecord::empty(pid)), // return empty record
         }
     }
+
+    /// Optimise a group of agents (see `shared::group`). Agents sharing a charger are optimised
+    /// jointly, if their charging overlaps the best of the candidate schedules (by total score)
+    /// is used and a record of the charger contention is returned.
+    pub fn optimise_shared<'a>(
+        &'a self,
+        config: &'a Config,
+        agents: Vec<Agent<'a>>,
+    ) -> Result<(Vec<AgentSimulationRecord<'a>>, Option<ChargerRecord>)> {
+        let Some((spec, charger)) = agents
+            .first()
+            .and_then(shared::charger)
+            .filter(|_| agents.len() > 1)
+        else {
+            let records = agents
+                .into_iter()
+                .map(|(pid, person, agent_config)| {
+                    self.optimise(config, pid, person, agent_config)
+                        .context(format!("optimiser failed at '{pid}'"))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            return Ok((records, None));
+        };
+
+        let mut independent = self.schedule(config, &agents, spec, &Schedule::Independent)?;
+        let cycles: Vec<Cycle> = independent
+            .iter()
+            .map(|record| record.charging_windows(spec))
+            .collect();
+        let overlap = shared::overlap(&cycles);
+
+        let mut best: Option<(Schedule, Vec<AgentSimulationRecord>, Score)> = None;
+        if overlap > 0.0 {
+            for schedule in Schedule::candidates(agents.len()) {
+                let records = self.schedule(config, &agents, spec, &schedule)?;
+                let score = records.iter().map(score_events).fold(
+                    (0.0, 0.0, 0.0, 0.0),
+                    |total: Score, score| {
+                        (
+                            total.0 + score.0,
+                            total.1 + score.1,
+                            total.2 + score.2,
+                            total.3 + score.3,
+                        )
+                    },
+                );
+                if best.as_ref().map_or(true, |(_, _, best)| score < *best) {
+                    best = Some((schedule, records, score));
+                }
+            }
+        }
+
+        independent
+            .iter_mut()
+            .for_each(|record| record.finalise(config));
+        let en_route = |records: &[AgentSimulationRecord]| -> f32 {
+            records
+                .iter()
+                .map(|record| record.to_record().total_enroute)
+                .sum()
+        };
+        let independent_en_route = en_route(&independent);
+        let (schedule, records) = match best {
+            Some((schedule, mut records, _)) => {
+                records
+                    .iter_mut()
+                    .for_each(|record| record.finalise(config));
+                (schedule, records)
+            }
+            None => (Schedule::Independent, independent),
+        };
+        let order = match &schedule {
+            Schedule::Priority(order) => order.iter().map(|i| agents[*i].0.as_str()).collect(),
+            _ => agents
+                .iter()
+                .map(|(pid, _, _)| pid.as_str())
+                .collect::<Vec<&str>>(),
+        };
+        let record = ChargerRecord {
+            spec: spec.to_string(),
+            charger: charger.to_string(),
+            vehicles: agents.len(),
+            schedule: schedule.to_string(),
+            order: order.join("+"),
+            overlap,
+            extra_en_route: en_route(&records) - independent_en_route,
+        };
+        Ok((records, Some(record)))
+    }
+
+    // best simulations (not finalised) of agents sharing a charger using given joint schedule
+    fn schedule<'a>(
+        &'a self,
+        config: &'a Config,
+        agents: &[Agent<'a>],
+        spec: &str,
+        schedule: &Schedule,
+    ) -> Result<Vec<AgentSimulationRecord<'a>>> {
+        let simulate = |i: usize, access: &ChargerAccess| {
+            let (pid, person, agent_config) = &agents[i];
+            self.simulate(config, pid, person, agent_config, access)
+        };
+        match schedule {
+            Schedule::Independent => (0..agents.len())
+                .map(|i| simulate(i, &ChargerAccess::unrestricted()))
+                .collect(),
+            Schedule::Rotation => (0..agents.len())
+                .map(|i| simulate(i, &ChargerAccess::new(spec).with_turn(i, agents.len())))
+                .collect(),
+            Schedule::Priority(order) => {
+                let mut access = ChargerAccess::new(spec);
+                let mut records: Vec<Option<AgentSimulationRecord>> =
+                    agents.iter().map(|_| None).collect();
+                for i in order {
+                    let record = simulate(*i, &access)?;
+                    access.block(record.charging_windows(spec));
+                    records[*i] = Some(record);
+                }
+                Ok(records.into_iter().flatten().collect())
+            }
+        }
+    }
+
+    // best simulation (not finalised) of an agent with a battery
+    fn simulate<'a>(
+        &'a self,
+        config: &'a Config,
+        pid: &'a String,
+        person: &'a Person,
+        agent_config: &AgentConfig<'a>,
+        access: &ChargerAccess,
+    ) -> Result<AgentSimulationRecord<'a>> {
+        let _trigger_spec = agent_config.trigger.context(format!(
+            "agent {pid}, no trigger spec provided, agent has a battery so requires a 'trigger' specification"
+        ))?;
+        let _en_route_spec = agent_config.en_route.context(format!(
+            "agent {pid}, no en-route charging spec provided, agent has a battery so requires an 'en-route' charging specification"
+        ))?;
+        let activity_charging_planner =
+            ActivityChargingPlanner::new(agent_config.activities.clone());
+        self.config.patience.context("no patience provided")?;
+        self.config
+            .precision
+            .context("no closing precision provided")?;
+        run::run_simulations_with_access(
+            pid,
+            person,
+            agent_config,
+            activity_charging_planner,
+            config,
+            self.record_soc,
+            access,
+        )
+        .context(format!("failed find result for '{}'", pid))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use configuration::groups::{
+        activity::ActivitySpec, battery::BatterySpecBuilder, en_route::EnRouteSpec,
+        trigger::TriggerSpec,
+    };
+    use std::collections::HashMap;
+    use tracer::{Activity, Component, Link, Trace};
+
+    fn person(home_end: u32) -> Person {
+        Person {
+            attributes: HashMap::from([("hid".to_string(), "h".to_string())]),
+            trace: Trace {
+                plan: vec![
+                    Component::LinkType(Link {
+                        lid: "a".to_string(),
+                        start_time: 1,
+                        end_time: 2,
+                        distance: 1.0,
+                        node: (0.0, 0.0),
+                        rise: 0.0,
+                    }),
+                    Component::ActivityType(Activity {
+                        act: "home".to_string(),
+                        start_time: 2,
+                        end_time: home_end,
+                        node: (0.0, 0.0),
+                    }),
+                ],
+            },
+        }
+    }
+
+    #[test]
+    fn test_optimise_shared_charger() {
+        let config = Config::default();
+        let battery_spec = BatterySpecBuilder::new()
+            .capacity(3.0 / 3600.) // 3 kWs
+            .full()
+            .consumption_rate(1.0 / 3.6) // 1 kWs/m
+            .build();
+        let trigger_spec = TriggerSpec::empty();
+        let en_route_spec = EnRouteSpec::new(None, 1.0, None, None);
+        let home = ActivitySpec {
+            name: Some("home".to_string()),
+            charge_rate: 1.0,
+            shared_by: Some("hid".to_string()),
+            ..Default::default()
+        };
+        let (a, b) = ("a".to_string(), "b".to_string());
+        let people = [person(10), person(10)];
+        let agents: Vec<Agent> = [&a, &b]
+            .into_iter()
+            .zip(people.iter())
+            .map(|(pid, person)| {
+                let agent_config = AgentConfig {
+                    pid,
+                    battery: Some(&battery_spec),
+                    trigger: Some(&trigger_spec),
+                    en_route: Some(&en_route_spec),
+                    activities: vec![&home],
+                };
+                (pid, person, agent_config)
+            })
+            .collect();
+        let optimiser = OptimiseHandler::new(&config);
+        let (records, charger) = optimiser.optimise_shared(&config, agents).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].pid(), "b");
+        // both vehicles would charge on arrival, taking turns halves the number of charges
+        assert_eq!(
+            charger,
+            Some(ChargerRecord {
+                spec: "home".to_string(),
+                charger: "h".to_string(),
+                vehicles: 2,
+                schedule: "rotation".to_string(),
+                order: "a+b".to_string(),
+                overlap: 1.0 / 3600.0,
+                extra_en_route: 0.0,
+            })
+        );
+        for record in &records {
+            assert_eq!(record.slice().len(), 2);
+            assert_eq!(record.to_record().number_activity, 1);
+        }
+    }
+
+    #[test]
+    fn test_alternate_nights() {
+        let config = Config::default();
+        let battery_spec = BatterySpecBuilder::new()
+            .capacity(3.0 / 3600.) // 3 kWs
+            .full()
+            .consumption_rate(1.0 / 3.6) // 1 kWs/m
+            .build();
+        let trigger_spec = TriggerSpec::empty();
+        let en_route_spec = EnRouteSpec::new(None, 1.0, None, None);
+        let home = ActivitySpec {
+            name: Some("home".to_string()),
+            charge_rate: 1.0,
+            shared_by: Some("hid".to_string()),
+            ..Default::default()
+        };
+        let agent_config = AgentConfig {
+            pid: "a",
+            battery: Some(&battery_spec),
+            trigger: Some(&trigger_spec),
+            en_route: Some(&en_route_spec),
+            activities: vec![&home],
+        };
+        let (a, b) = ("a".to_string(), "b".to_string());
+        // each stay at home is only long enough for one vehicle to charge
+        let people = [person(4), person(4)];
+        let optimiser = OptimiseHandler::new(&config);
+
+        // a charges every other night
+        let access = ChargerAccess::new("home").with_turn(0, 2);
+        let record_a = optimiser
+            .simulate(&config, &a, &people[0], &agent_config, &access)
+            .unwrap();
+        assert_eq!(record_a.slice().len(), 2);
+        assert_eq!(record_a.to_record().number_activity, 1);
+
+        // b is only blocked on the nights that a charges
+        let mut access = ChargerAccess::new("home");
+        access.block(record_a.charging_windows("home"));
+        let record_b = optimiser
+            .simulate(&config, &b, &people[1], &agent_config, &access)
+            .unwrap();
+        assert_eq!(record_b.slice().len(), 2);
+        assert_eq!(record_b.to_record().number_activity, 1);
+        assert_eq!(record_b.to_record().number_enroute, 0);
+        assert_eq!(
+            shared::overlap(&[
+                record_a.charging_windows("home"),
+                record_b.charging_windows("home")
+            ]),
+            0.0
+        );
+    }
 }
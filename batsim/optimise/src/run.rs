@@ -1,5 +1,8 @@
 use configuration::{charge_plan::ActivityChargingPlanner, config::Config, handler::AgentConfig};
-use simulate::{record::AgentSimulationRecord, scoring::score_events, sim::simulate};
+use simulate::{
+    charger::ChargerAccess, record::AgentSimulationRecord, scoring::score_events,
+    sim::simulate_with_access,
+};
 use tracer::Person;
 
 /// For given person and battery simulate activity charging permitations and return best
@@ -9,31 +12,74 @@ pub fn run_simulations<'a>(
     agent_config: &AgentConfig,
     activity_charging_planner: ActivityChargingPlanner<'a>,
     config: &Config,
+    record_soc: bool,
+) -> Option<AgentSimulationRecord<'a>> {
+    run_simulations_with_access(
+        pid,
+        person,
+        agent_config,
+        activity_charging_planner,
+        config,
+        record_soc,
+        &ChargerAccess::unrestricted(),
+    )
+}
+
+/// For given person, battery and access to any shared charger, simulate activity charging
+/// permutations and return best
+pub fn run_simulations_with_access<'a>(
+    pid: &'a str,
+    person: &'a Person,
+    agent_config: &AgentConfig,
+    activity_charging_planner: ActivityChargingPlanner<'a>,
+    config: &Config,
+    record_soc: bool,
+    access: &ChargerAccess,
 ) -> Option<AgentSimulationRecord<'a>> {
     let mut best_result: Option<AgentSimulationRecord> = None;
-    let mut best_score: (f32, f32, f32) = (f32::MAX, f32::MAX, f32::MAX);
+    let mut best_score: (f32, f32, f32, f32) = (f32::MAX, f32::MAX, f32::MAX, f32::MAX);
 
     let charge_options = person.viable_combinations(activity_charging_planner.activities());
 
     for options in charge_options.into_iter() {
         for charge_activities in options.into_iter() {
             let charging_planner = activity_charging_planner.clone();
-            let simulation_record = simulate(
+            let mut simulation_record = simulate_with_access(
                 pid,
                 &person.trace,
-                charge_activities,
+                charge_activities.clone(),
                 agent_config,
-                charging_planner,
+                charging_planner.clone(),
                 config,
+                record_soc,
+                access,
             );
-            let score = score_events(&simulation_record);
+            let mut score = score_events(&simulation_record);
+            if simulation_record.has_discharge() {
+                // discharging must not cost the agent any en-route charging
+                let without_discharge = simulate_with_access(
+                    pid,
+                    &person.trace,
+                    charge_activities,
+                    agent_config,
+                    charging_planner,
+                    config,
+                    record_soc,
+                    &access.clone().without_discharge(),
+                );
+                let without_score = score_events(&without_discharge);
+                if (without_score.0, without_score.1) < (score.0, score.1) {
+                    simulation_record = without_discharge;
+                    score = without_score;
+                }
+            }
             if score < best_score {
                 best_score = score;
                 best_result = Some(simulation_record);
             }
         }
-        if best_score.0 == 0. {
-            // there are 0 en-route charge events - we do not need to look further
+        if best_score.0 == 0. && best_score.2 == 0. {
+            // there are 0 en-route charge events (and no fuel used) - we do not need to look further
             return best_result;
         }
     }
@@ -75,6 +121,7 @@ mod tests {
             end_time: $et,
             distance: $d as f32,
             node: ($x as f32, $y as f32),
+            rise: 0.0,
         })
     };
     () => {};
@@ -123,9 +170,11 @@ mod tests {
             &agent_config,
             charge_activity_configs,
             &config,
+            false,
         );
         let mut expected_record = AgentSimulationRecord::new("A", 1.0);
         expected_record.new_day(36000.);
+        expected_record.add_distance(3., 0., 0.);
         expected_record.add_event(Event::activity(
             "A",
             Some("home".to_string()),
@@ -135,6 +184,8 @@ mod tests {
             "home",
             (0.0, 0.0),
         ));
+        expected_record.update_soc_range(33000.);
+        expected_record.update_soc_range(36000.);
         expected_record.try_to_close(36000.0);
         assert_eq!(solution, Some(expected_record))
     }
@@ -177,6 +228,7 @@ mod tests {
             &agent_config,
             charge_activity_configs,
             &config,
+            false,
         )
         .unwrap();
         simulation_record.finalise(&Config::default());
@@ -221,6 +273,7 @@ mod tests {
             &agent_config,
             charge_activity_configs,
             &config,
+            false,
         )
         .unwrap();
         simulation_record.finalise(&Config::default());
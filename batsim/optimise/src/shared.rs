@@ -0,0 +1,218 @@
+use itertools::Itertools;
+use serde::Serialize;
+use std::{collections::HashMap, fmt};
+
+use configuration::handler::AgentConfig;
+use simulate::charger::{self, Cycle};
+use tracer::Person;
+
+/// Agent to optimise, with its assigned specs
+pub type Agent<'a> = (&'a String, &'a Person, AgentConfig<'a>);
+
+const DAY: u32 = 24 * 60 * 60;
+
+/// Households larger than this only consider priority in agent order (and reversed)
+const MAX_PERMUTED: usize = 4;
+
+/// Shared charger of an agent with a battery, the name of the first activity spec that is
+/// shared (`shared_by`) and the agent's value of the sharing attribute (such as a household id)
+pub fn charger<'a>(agent: &Agent<'a>) -> Option<(&'a str, &'a str)> {
+    let person: &'a Person = agent.1;
+    let agent_config = &agent.2;
+    agent_config.battery?;
+    agent_config.activities.iter().find_map(|spec| {
+        let attribute = spec.shared_by.as_ref()?;
+        Some((
+            spec.name.as_deref()?,
+            person.attributes.get(attribute)?.as_str(),
+        ))
+    })
+}
+
+/// Group agents sharing a charger, other agents are in groups of their own.
+/// Groups are in order of their first agent.
+pub fn group(agents: Vec<Agent>) -> Vec<Vec<Agent>> {
+    let mut groups: Vec<Vec<Agent>> = Vec::new();
+    let mut index: HashMap<(&str, &str), usize> = HashMap::new();
+    for agent in agents {
+        match charger(&agent) {
+            Some(key) => match index.get(&key) {
+                Some(i) => groups[*i].push(agent),
+                None => {
+                    index.insert(key, groups.len());
+                    groups.push(vec![agent]);
+                }
+            },
+            None => groups.push(vec![agent]),
+        }
+    }
+    groups
+}
+
+/// Joint charging schedule of agents sharing a charger
+#[derive(Debug, PartialEq, Clone)]
+pub enum Schedule {
+    /// Agents charge as if they had their own charger (used if their charging never overlaps)
+    Independent,
+    /// Agents (given by index) charge in order of priority, each using the charger when it
+    /// is not used by higher priority agents
+    Priority(Vec<usize>),
+    /// Agents take turns, each only using the charger every nth day
+    Rotation,
+}
+
+impl Schedule {
+    /// Constrained schedules to consider for given number of agents
+    pub fn candidates(agents: usize) -> Vec<Schedule> {
+        let orders: Vec<Vec<usize>> = match agents <= MAX_PERMUTED {
+            true => (0..agents).permutations(agents).collect(),
+            false => vec![(0..agents).collect(), (0..agents).rev().collect()],
+        };
+        orders
+            .into_iter()
+            .map(Schedule::Priority)
+            .chain(std::iter::once(Schedule::Rotation))
+            .collect()
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Schedule::Independent => write!(f, "independent"),
+            Schedule::Priority(_) => write!(f, "priority"),
+            Schedule::Rotation => write!(f, "rotation"),
+        }
+    }
+}
+
+/// Contention for a shared charger, as written to 'chargers.csv'
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ChargerRecord {
+    /// Name of the shared activity spec
+    pub spec: String,
+    /// Value of the sharing attribute, such as a household id
+    pub charger: String,
+    pub vehicles: usize,
+    pub schedule: String,
+    /// Agents in order of priority or turn ('+' joined)
+    pub order: String,
+    /// Daily time that vehicles would charge at once if each had their own charger
+    #[serde(rename = "overlap_(h)")]
+    pub overlap: f32,
+    /// Additional en-route charge required due to sharing the charger
+    #[serde(rename = "extra_en_route_(kWh)")]
+    pub extra_en_route: f32,
+}
+
+/// Total (pairwise) overlap of agent charging loops per day, in hours. Overlap is found over a
+/// loop of days common to all agents, starting once all agent loops have started.
+pub fn overlap(cycles: &[Cycle]) -> f32 {
+    let start_day = cycles
+        .iter()
+        .map(|cycle| cycle.start_day)
+        .max()
+        .unwrap_or(0);
+    let days = cycles.iter().map(|cycle| cycle.days).fold(1, charger::lcm);
+    let (start, end) = (start_day as u32 * DAY, (start_day + days) as u32 * DAY);
+    cycles
+        .iter()
+        .map(|cycle| cycle.windows(start, end))
+        .tuple_combinations()
+        .map(|(a, b)| charger::overlap(&a, &b))
+        .sum::<u32>() as f32
+        / days as f32
+        / 3600.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use configuration::groups::{activity::ActivitySpec, battery::BatterySpec};
+
+    fn person(household: &str) -> Person {
+        Person {
+            attributes: HashMap::from([("hid".to_string(), household.to_string())]),
+            trace: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_group_by_shared_charger() {
+        let battery = BatterySpec::unit();
+        let shared = ActivitySpec {
+            name: Some("home".to_string()),
+            shared_by: Some("hid".to_string()),
+            ..Default::default()
+        };
+        let pids: Vec<String> = ["a", "b", "c", "d"].iter().map(|p| p.to_string()).collect();
+        let people = [person("x"), person("y"), person("x"), person("x")];
+        let agents: Vec<Agent> = pids
+            .iter()
+            .zip(people.iter())
+            .map(|(pid, person)| {
+                let agent_config = AgentConfig {
+                    pid,
+                    // d has no battery
+                    battery: (pid != "d").then_some(&battery),
+                    trigger: None,
+                    en_route: None,
+                    activities: vec![&shared],
+                };
+                (pid, person, agent_config)
+            })
+            .collect();
+        let groups: Vec<Vec<&str>> = group(agents)
+            .iter()
+            .map(|members| members.iter().map(|(pid, _, _)| pid.as_str()).collect())
+            .collect();
+        assert_eq!(groups, vec![vec!["a", "c"], vec!["b"], vec!["d"]]);
+    }
+
+    #[test]
+    fn test_candidates() {
+        let candidates = Schedule::candidates(2);
+        assert_eq!(
+            candidates,
+            vec![
+                Schedule::Priority(vec![0, 1]),
+                Schedule::Priority(vec![1, 0]),
+                Schedule::Rotation
+            ]
+        );
+        assert_eq!(Schedule::candidates(6).len(), 3);
+    }
+
+    #[test]
+    fn test_overlap() {
+        let cycle = |start_day, days, windows| Cycle {
+            start_day,
+            days,
+            windows,
+        };
+        // charging each day, the window past midnight overlaps the next day
+        assert_eq!(
+            overlap(&[
+                cycle(0, 1, vec![(0, 7200)]),
+                cycle(1, 1, vec![(90000, 97200)])
+            ]),
+            1.0
+        );
+        // charging on alternate nights
+        assert_eq!(
+            overlap(&[
+                cycle(0, 2, vec![(72000, 79200)]),
+                cycle(0, 2, vec![(DAY + 72000, DAY + 79200)])
+            ]),
+            0.0
+        );
+        // charging every night and every other night
+        assert_eq!(
+            overlap(&[
+                cycle(0, 1, vec![(72000, 79200)]),
+                cycle(1, 2, vec![(72000, 79200)])
+            ]),
+            1.0
+        );
+    }
+}
@@ -1,4 +1,28 @@
-use configuration::groups::{battery::BatterySpec, trigger::TriggerSpec};
+use std::sync::Arc;
+
+use crate::energy::{energy_model, EnergyModel};
+use configuration::{
+    groups::{
+        battery::{AuxiliaryLoads, BatterySpec, TemperatureSensitivity},
+        trigger::TriggerSpec,
+    },
+    temperature::TemperatureProfile,
+};
+use tracer::Link;
+
+/// Ambient temperature (°C) by hour of day and the battery's sensitivity to it
+#[derive(Debug, Clone)]
+pub struct Climate {
+    pub hourly: [f32; 24],
+    pub sensitivity: TemperatureSensitivity,
+}
+
+impl Climate {
+    /// Temperature at given trace time (seconds), which may run past midnight
+    pub fn temperature(&self, time: u32) -> f32 {
+        self.hourly[(time / 3600) as usize % 24]
+    }
+}
 
 /// Battery state keeps track of the agent battery state during simulation.
 /// We also convert battery specification units from hours to seconds and km to metres.
@@ -9,6 +33,11 @@ pub struct BatteryState {
     pub initial: f32,
     pub trigger: f32,
     pub consumption_rate: f32,
+    // plug-in hybrid fuel consumption rate (litres/m)
+    pub fuel_consumption_rate: Option<f32>,
+    pub model: Arc<dyn EnergyModel>,
+    pub climate: Option<Climate>,
+    pub auxiliary: AuxiliaryLoads,
 }
 impl BatteryState {
     pub fn new(battery_spec: &BatterySpec, trigger_spec: &TriggerSpec) -> BatteryState {
@@ -19,22 +48,128 @@ impl BatteryState {
             initial: battery_spec.initial * 3600.0,   // convert kWh to kWs
             trigger: trigger_spec.trigger * capacity, // convert kWh to kWs
             consumption_rate: battery_spec.consumption_rate * 3.6, // convert kWh/km to kWs/m
+            fuel_consumption_rate: battery_spec.fuel_consumption_rate.map(|rate| rate / 1000.0), // convert l/km to l/m
+            model: energy_model(battery_spec),
+            climate: None,
+            auxiliary: battery_spec.auxiliary.clone().unwrap_or_default(),
         }
     }
 
+    /// Apply an ambient temperature profile, if the battery is sensitive to temperature
+    pub fn with_temperature(
+        mut self,
+        profile: Option<&TemperatureProfile>,
+        sensitivity: Option<&TemperatureSensitivity>,
+    ) -> Self {
+        self.climate = profile
+            .zip(sensitivity)
+            .map(|(profile, sensitivity)| Climate {
+                hourly: profile.hourly(),
+                sensitivity: sensitivity.clone(),
+            });
+        self
+    }
+
     /// Reduce battery state for given distance
     pub fn apply_distance(&mut self, distance: f32) {
         self.state -= distance * self.consumption_rate;
     }
 
+    /// Energy (kWs) used to drive given link, including the effect of temperature at the start
+    /// of the link and auxiliary loads while driving. Temperature only affects energy used, not
+    /// energy recovered.
+    pub fn energy(&self, link: &Link) -> f32 {
+        let energy = self.model.energy(link);
+        let energy = match &self.climate {
+            Some(climate) if energy > 0.0 => {
+                energy
+                    * climate
+                        .sensitivity
+                        .consumption_factor(climate.temperature(link.start_time))
+            }
+            _ => energy,
+        };
+        energy + self.auxiliary.driving(link.duration())
+    }
+
+    /// Reduce battery state by auxiliary loads while parked from start to end (trace seconds).
+    /// Plug-in hybrids are not drained below empty.
+    pub fn park(&mut self, start: u32, end: u32) {
+        let drain = self.auxiliary.parked(start, end);
+        self.state = match self.fuel_consumption_rate {
+            Some(_) => self.state - drain.min(self.state.max(0.0)),
+            None => self.state - drain,
+        };
+    }
+
+    /// Charge rate accepted at given time, reduced when cold
+    pub fn charge_rate(&self, charge_rate: f32, time: u32) -> f32 {
+        match &self.climate {
+            Some(climate) => {
+                charge_rate * climate.sensitivity.charge_factor(climate.temperature(time))
+            }
+            None => charge_rate,
+        }
+    }
+
+    /// Lowest charge rate accepted over given window (hourly temperatures)
+    pub fn min_charge_rate(&self, charge_rate: f32, start: u32, end: u32) -> f32 {
+        (start / 3600..(end + 3599) / 3600)
+            .map(|hour| self.charge_rate(charge_rate, (hour * 3600).max(start)))
+            .fold(charge_rate, f32::min)
+    }
+
+    /// Attempt to charge battery from start to end time at given rate, reduced by the temperature
+    /// of each hour, return achieved charge and duration
+    pub fn charge_for_window(&mut self, start: u32, end: u32, charge_rate: f32) -> (f32, u32) {
+        let (mut charge, mut duration) = (0.0, 0);
+        let mut time = start;
+        while time < end {
+            let next = ((time / 3600 + 1) * 3600).min(end);
+            let rate = self.charge_rate(charge_rate, time);
+            let (slice_charge, slice_duration) = self.charge_for_duration(next - time, rate);
+            charge += slice_charge;
+            duration += slice_duration;
+            if slice_duration < next - time {
+                break; // full
+            }
+            time = next;
+        }
+        (charge, duration)
+    }
+
+    /// Drive given link, return the distances driven using the battery and using fuel.
+    /// Plug-in hybrids use fuel once the battery is depleted, otherwise the battery state can
+    /// become negative (to be corrected by en-route charging). Recovered energy can not charge
+    /// the battery beyond capacity.
+    pub fn drive(&mut self, link: &Link) -> (f32, f32) {
+        let energy = self.energy(link);
+        match self.fuel_consumption_rate {
+            Some(_) if energy > self.state.max(0.0) => {
+                let electric = link.distance * self.state.max(0.0) / energy;
+                self.state = self.state.min(0.0);
+                (electric, link.distance - electric)
+            }
+            _ => {
+                self.state = (self.state - energy).min(self.capacity);
+                (link.distance, 0.0)
+            }
+        }
+    }
+
+    /// Fuel (litres) used to drive given distance on fuel
+    pub fn fuel(&self, distance: f32) -> f32 {
+        self.fuel_consumption_rate.unwrap_or(0.0) * distance
+    }
+
     /// Return difference between current battery state and capacity
     pub fn deficit(&self) -> f32 {
         self.capacity - self.state
     }
 
-    /// Charge desired if state is at or below trigger level
+    /// Charge desired if state is at or below trigger level, plug-in hybrids never need to charge
     pub fn must_charge(&self) -> bool {
-        self.state <= self.trigger
+        self.fuel_consumption_rate.is_none() && self.state <= self.trigger
     }
 
     /// Charge battery to full at given rate, return size of charge and duration of charge
@@ -58,6 +193,20 @@ impl BatteryState {
         (charge, duration)
     }
 
+    /// Attempt to discharge battery for given duration and rate, without falling below the floor
+    /// state, return achieved (negative) charge and duration
+    pub fn discharge_for_duration(
+        &mut self,
+        duration: u32,
+        discharge_rate: f32,
+        floor: f32,
+    ) -> (f32, u32) {
+        let available = (self.state - floor).max(0.0);
+        let discharge = (duration as f32 * discharge_rate).min(available);
+        self.state -= discharge;
+        (-discharge, (discharge / discharge_rate) as u32)
+    }
+
     /// Attempt to apply desired charge at given rate, return achieved charge and duration
     pub fn charge_to_desired(&mut self, desired_charge: f32, charge_rate: f32) -> (f32, u32) {
         if desired_charge > self.deficit() {
@@ -75,6 +224,8 @@ impl BatteryState {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::energy::{ConstantRate, Gradient};
+    use configuration::groups::battery::EnergyModelSpec;
 
     #[test]
     fn test_battery_state_apply_distance() {
@@ -99,6 +250,112 @@ mod tests {
         assert!(battery.must_charge());
     }
 
+    fn link(distance: f32) -> Link {
+        Link {
+            start_time: 0,
+            end_time: 1,
+            lid: "a".to_string(),
+            distance,
+            node: (0.0, 0.0),
+            rise: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_hybrid_drive() {
+        let mut spec = BatterySpec::unit();
+        spec.fuel_consumption_rate = Some(0.1);
+        let trigger_spec = TriggerSpec::empty();
+        let mut battery = BatteryState::new(&spec, &trigger_spec);
+        assert_eq!(battery.drive(&link(0.5)), (0.5, 0.0));
+        assert_eq!(battery.drive(&link(2.0)), (0.5, 1.5));
+        assert_eq!(battery.state, 0.0);
+        assert!(!battery.must_charge());
+        assert_eq!(battery.fuel(1000.0), 0.1);
+        assert_eq!(battery.drive(&link(1.0)), (0.0, 1.0));
+    }
+
+    #[test]
+    fn test_drive_with_energy_model() {
+        let mut spec = BatterySpec::unit();
+        spec.energy_model = Some(EnergyModelSpec::Speed {
+            curve: vec![[0.0, 2.0 / 3.6]], // 2 kWs/m
+        });
+        let trigger_spec = TriggerSpec::empty();
+        let mut battery = BatteryState::new(&spec, &trigger_spec);
+        assert_eq!(battery.drive(&link(1.0)), (1.0, 0.0));
+        assert_eq!(battery.state, -1.0);
+        assert!(battery.must_charge());
+    }
+
+    #[test]
+    fn test_temperature() {
+        let spec = BatterySpec::unit();
+        let trigger_spec = TriggerSpec::empty();
+        let profile = TemperatureProfile::Hourly {
+            hourly: (0..24).map(|hour| hour as f32).collect(),
+        };
+        let sensitivity = TemperatureSensitivity {
+            reference: 20.0,
+            heating: 0.1,
+            cooling: 0.0,
+            charging: 0.05,
+        };
+        let battery = BatteryState::new(&spec, &trigger_spec)
+            .with_temperature(Some(&profile), Some(&sensitivity));
+        // 10 °C at 10:00 and 20 °C at 20:00 on the following day
+        let at = |start_time: u32| Link {
+            start_time,
+            end_time: start_time + 1,
+            ..link(1.0)
+        };
+        assert_eq!(battery.energy(&at(36000)), 2.0);
+        assert_eq!(battery.energy(&at(86400 + 72000)), 1.0);
+        assert_eq!(battery.charge_rate(1.0, 36000), 0.5);
+        assert_eq!(battery.min_charge_rate(1.0, 36000, 72000), 0.5);
+        assert_eq!(battery.min_charge_rate(1.0, 39599, 72000), 0.5);
+        // recovered energy is unaffected
+        let mut battery = battery;
+        battery.model = Arc::new(ConstantRate {
+            rate: -1.0,
+            gradient: Gradient {
+                mass: 0.0,
+                regen_efficiency: 0.0,
+            },
+        });
+        assert_eq!(battery.energy(&at(36000)), -1.0);
+        // batteries without a sensitivity are unaffected
+        let battery =
+            BatteryState::new(&spec, &trigger_spec).with_temperature(Some(&profile), None);
+        assert_eq!(battery.charge_rate(1.0, 0), 1.0);
+    }
+
+    #[test]
+    fn test_auxiliary_loads() {
+        let mut spec = BatterySpec::unit();
+        spec.capacity = 10.0 / 3600.0;
+        spec.initial = spec.capacity;
+        spec.auxiliary = Some(AuxiliaryLoads {
+            standby: 0.01,
+            preconditioning: 1.0,
+            preconditioning_duration: 2,
+            driving: 1.0,
+            ..Default::default()
+        });
+        let trigger_spec = TriggerSpec::empty();
+        let mut battery = BatteryState::new(&spec, &trigger_spec);
+        battery.park(0, 100);
+        assert_eq!(battery.state, 7.0);
+        // 1 kWs/m over 1 m plus 1 kW for 1 s
+        assert_eq!(battery.drive(&link(1.0)), (1.0, 0.0));
+        assert_eq!(battery.state, 5.0);
+
+        spec.fuel_consumption_rate = Some(0.1);
+        let mut battery = BatteryState::new(&spec, &trigger_spec);
+        battery.park(0, 1000);
+        assert_eq!(battery.state, 0.0);
+    }
+
     #[test]
     fn test_charge_to_full_already_full() {
         let spec = BatterySpec::unit();
@@ -145,6 +402,21 @@ mod tests {
         assert_eq!(battery.deficit(), 0.5);
     }
 
+    #[test]
+    fn test_discharge_for_duration() {
+        let spec = BatterySpec::unit();
+        let trigger_spec = TriggerSpec::empty();
+        let mut battery = BatteryState::new(&spec, &trigger_spec);
+        let (charge, duration) = battery.discharge_for_duration(2, 0.2, 0.5);
+        assert!((charge + 0.4).abs() < 1e-6);
+        assert_eq!(duration, 2);
+        // discharge stops at the floor
+        let (charge, duration) = battery.discharge_for_duration(10, 0.2, 0.5);
+        assert!((charge + 0.1).abs() < 1e-6);
+        assert_eq!(duration, 0); // rounds down from 0.5
+        assert!((battery.state - 0.5).abs() < 1e-6);
+    }
+
     #[test]
     fn test_charge_for_duration() {
         let spec = BatterySpec::unit();
@@ -0,0 +1,223 @@
+const DAY: u32 = 24 * 60 * 60;
+
+/// Access of an agent to a charger shared with other agents (such as a household home charger).
+/// Activities charged using the shared spec can only charge in windows where the charger is
+/// not blocked by other agents, and (if taking turns) only on the agent's own days.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ChargerAccess {
+    /// Name of the shared activity spec
+    pub spec: Option<String>,
+    /// Charging loops of other agents, in which the charger is used
+    pub blocked: Vec<Cycle>,
+    /// Turn of the agent (index, number of agents), the charger is only available on days of
+    /// the agent's turn
+    pub turn: Option<(usize, usize)>,
+    /// Discharging (vehicle-to-grid) is disabled at all chargers
+    pub no_discharge: bool,
+}
+
+/// Charging windows of an agent's repeating loop of days (such as a record slice)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cycle {
+    /// Day on which the loop starts
+    pub start_day: usize,
+    /// Number of days in the loop
+    pub days: usize,
+    /// Windows (seconds from the start of the loop, which may run past its end)
+    pub windows: Vec<(u32, u32)>,
+}
+
+impl ChargerAccess {
+    /// Unrestricted access, no chargers are shared
+    pub fn unrestricted() -> Self {
+        Self::default()
+    }
+
+    pub fn new(spec: &str) -> Self {
+        ChargerAccess {
+            spec: Some(spec.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Block the charger for the charging loop of another agent
+    pub fn block(&mut self, cycle: Cycle) {
+        self.blocked.push(cycle);
+    }
+
+    /// Only allow charging on every `of` days, starting from day `index`
+    pub fn with_turn(mut self, index: usize, of: usize) -> Self {
+        self.turn = Some((index, of));
+        self
+    }
+
+    /// Disable discharging, so that batteries are only charged
+    pub fn without_discharge(mut self) -> Self {
+        self.no_discharge = true;
+        self
+    }
+
+    /// Number of days before access repeats
+    pub fn period(&self) -> usize {
+        self.blocked
+            .iter()
+            .map(|cycle| cycle.days)
+            .fold(self.turn.map(|(_, of)| of).unwrap_or(1), lcm)
+    }
+
+    /// Check if given spec is the shared spec
+    pub fn is_shared(&self, spec: Option<&str>) -> bool {
+        self.spec.is_some() && self.spec.as_deref() == spec
+    }
+
+    /// Windows of an activity (trace seconds) in which the charger is available on given day
+    pub fn windows(&self, day: usize, start: u32, end: u32) -> Vec<(u32, u32)> {
+        if let Some((index, of)) = self.turn {
+            if day % of != index {
+                return vec![];
+            }
+        }
+        // blocked windows are found in seconds from the start of the first day
+        let offset = day as u32 * DAY;
+        let mut windows = vec![(offset + start, offset + end)];
+        for cycle in &self.blocked {
+            for (blocked_start, blocked_end) in cycle.windows(offset + start, offset + end) {
+                windows = windows
+                    .into_iter()
+                    .flat_map(|(start, end)| {
+                        if blocked_end <= start || blocked_start >= end {
+                            return vec![(start, end)];
+                        }
+                        [(start, blocked_start), (blocked_end, end)]
+                            .into_iter()
+                            .filter(|(start, end)| start < end)
+                            .collect()
+                    })
+                    .collect();
+            }
+        }
+        windows
+            .into_iter()
+            .map(|(start, end)| (start - offset, end - offset))
+            .collect()
+    }
+}
+
+impl Cycle {
+    /// Windows (seconds from the start of the first day) overlapping the given range, repeating
+    /// the loop before and after its start, clipped to the range
+    pub fn windows(&self, start: u32, end: u32) -> Vec<(u32, u32)> {
+        if self.days == 0 {
+            return vec![];
+        }
+        let period = self.days as i64 * DAY as i64;
+        let origin = self.start_day as i64 * DAY as i64;
+        let (start, end) = (start as i64, end as i64);
+        let mut windows = Vec::new();
+        for (window_start, window_end) in &self.windows {
+            let window_start = origin + *window_start as i64;
+            let window_end = origin + *window_end as i64;
+            // repeats of the window that overlap the range
+            let first = (start - window_end).div_euclid(period) + 1;
+            let last = -(window_start - end).div_euclid(period) - 1;
+            for repeat in first..=last {
+                windows.push((
+                    (window_start + repeat * period).max(start) as u32,
+                    (window_end + repeat * period).min(end) as u32,
+                ));
+            }
+        }
+        windows.sort();
+        windows
+    }
+}
+
+/// Least common multiple of two numbers of days
+pub fn lcm(a: usize, b: usize) -> usize {
+    let (mut x, mut y) = (a, b);
+    while y != 0 {
+        (x, y) = (y, x % y);
+    }
+    a / x * b
+}
+
+/// Total overlap (seconds) between two sets of windows
+pub fn overlap(a: &[(u32, u32)], b: &[(u32, u32)]) -> u32 {
+    a.iter()
+        .flat_map(|(a_start, a_end)| {
+            b.iter()
+                .map(move |(b_start, b_end)| a_end.min(b_end).saturating_sub(*a_start.max(b_start)))
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocked_windows() {
+        let mut access = ChargerAccess::new("home");
+        // another vehicle charges from 23:00 to 01:00 every day
+        access.block(Cycle {
+            start_day: 0,
+            days: 1,
+            windows: vec![(82800, 90000)],
+        });
+        assert_eq!(
+            access.windows(0, 64800, 111600),
+            vec![(64800, 82800), (90000, 111600)]
+        );
+        assert_eq!(access.windows(3, 0, 7200), vec![(3600, 7200)]);
+        assert_eq!(access.windows(0, 36000, 43200), vec![(36000, 43200)]);
+        assert!(access.is_shared(Some("home")));
+        assert!(!access.is_shared(None));
+        assert_eq!(access.period(), 1);
+    }
+
+    #[test]
+    fn test_alternate_blocked_windows() {
+        let mut access = ChargerAccess::new("home");
+        // another vehicle charges from 22:00 to 02:00 every other night, starting on day 1
+        access.block(Cycle {
+            start_day: 1,
+            days: 2,
+            windows: vec![(79200, 93600)],
+        });
+        assert_eq!(access.period(), 2);
+        assert_eq!(
+            access.windows(1, 64800, 111600),
+            vec![(64800, 79200), (93600, 111600)]
+        );
+        assert_eq!(access.windows(2, 64800, 111600), vec![(64800, 111600)]);
+        assert_eq!(access.windows(2, 0, 10800), vec![(7200, 10800)]);
+        assert_eq!(access.windows(3, 0, 10800), vec![(0, 10800)]);
+        // the loop also repeats before its start
+        assert_eq!(access.windows(0, 0, 10800), vec![(7200, 10800)]);
+    }
+
+    #[test]
+    fn test_cycle_windows() {
+        let cycle = Cycle {
+            start_day: 0,
+            days: 1,
+            windows: vec![(82800, 90000)],
+        };
+        assert_eq!(
+            cycle.windows(0, 2 * DAY),
+            vec![(0, 3600), (82800, DAY + 3600), (DAY + 82800, 2 * DAY)]
+        );
+        assert_eq!(lcm(2, 3), 6);
+        assert_eq!(lcm(2, 4), 4);
+    }
+
+    #[test]
+    fn test_turns() {
+        let access = ChargerAccess::new("home").with_turn(1, 2);
+        assert_eq!(access.period(), 2);
+        assert!(access.windows(0, 64800, 111600).is_empty());
+        assert_eq!(access.windows(1, 64800, 111600), vec![(64800, 111600)]);
+        assert!(access.windows(2, 64800, 111600).is_empty());
+        assert_eq!(ChargerAccess::unrestricted().period(), 1);
+    }
+}
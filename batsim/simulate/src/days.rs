@@ -5,15 +5,58 @@ use crate::events::Event;
 #[derive(PartialEq, Debug, Default)]
 pub struct Day<'a> {
     pub events: Vec<Event<'a>>,
+    // battery state (kWs) at the end of each trace component, only kept if requested
+    pub soc: Vec<(u32, f32)>,
+    // lowest and highest battery states (kWs) seen during the day
+    pub soc_range: Option<(f32, f32)>,
+    // distances (m) driven using the battery and using fuel (plug-in hybrids only), and fuel used (l)
+    pub electric_distance: f32,
+    pub fuel_distance: f32,
+    pub fuel: f32,
+    // discharge available while plugged in during discharge windows (vehicle-to-grid)
+    pub flexibility: Vec<Flexibility>,
+}
+
+/// Discharge available from a plugged in battery during a discharge window, whether or not the
+/// battery is discharged
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Flexibility {
+    pub start_time: u32,
+    pub end_time: u32,
+    /// Charge above the discharge floor at the start of the window (kWs)
+    pub energy: f32,
+    /// Discharge rate (kW)
+    pub rate: f32,
 }
 
 impl<'a> Day<'a> {
     pub fn new() -> Self {
-        Self { events: Vec::new() }
+        Self {
+            events: Vec::new(),
+            soc: Vec::new(),
+            soc_range: None,
+            electric_distance: 0.0,
+            fuel_distance: 0.0,
+            fuel: 0.0,
+            flexibility: Vec::new(),
+        }
     }
     pub fn iter_events(&'a self) -> std::slice::Iter<'a, Event<'a>> {
         self.events.iter()
     }
+    /// Add distances (m) driven using the battery and using fuel, and fuel used (l)
+    pub fn add_distance(&mut self, electric: f32, fuel_distance: f32, fuel: f32) {
+        self.electric_distance += electric;
+        self.fuel_distance += fuel_distance;
+        self.fuel += fuel;
+    }
+    /// Update the days battery state range with given state
+    pub fn update_soc_range(&mut self, state: f32) {
+        self.soc_range = match self.soc_range {
+            None => Some((state, state)),
+            Some((min, max)) => Some((min.min(state), max.max(state))),
+        }
+    }
 }
 
 impl<'a> std::iter::IntoIterator for &'a Day<'a> {
@@ -53,6 +96,7 @@ mod tests {
                 Event::activity("a", None, 0., 0, (0, 1), "home", (0., 0.)),
                 Event::en_route("a", None, 0., 0, (0, 1), "a", (0., 0.)),
             ],
+            ..Default::default()
         }
     }
 
@@ -60,4 +104,14 @@ mod tests {
     fn iter_events() {
         println!("{:?}", day().iter_events().collect::<Vec<&Event>>())
     }
+
+    #[test]
+    fn test_soc_range() {
+        let mut day = day();
+        assert_eq!(day.soc_range, None);
+        day.update_soc_range(2.);
+        day.update_soc_range(1.);
+        day.update_soc_range(3.);
+        assert_eq!(day.soc_range, Some((1., 3.)));
+    }
 }
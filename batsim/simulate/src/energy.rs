@@ -0,0 +1,236 @@
+use std::{fmt::Debug, sync::Arc};
+
+use configuration::groups::battery::{BatterySpec, EnergyModelSpec};
+use tracer::Link;
+
+const GRAVITY: f32 = 9.81; // m/s2
+const AIR_DENSITY: f32 = 1.2; // kg/m3
+const DEFAULT_MASS: f32 = 1800.0; // kg
+const DEFAULT_REGEN_EFFICIENCY: f32 = 0.6;
+
+/// Model of the battery energy used to drive a link, in kWs (negative if energy is recovered)
+pub trait EnergyModel: Debug + Send + Sync {
+    fn energy(&self, link: &Link) -> f32;
+}
+
+/// Energy model given by a battery spec, defaults to the constant consumption rate.
+/// We convert units from kWh to kWs and km to metres.
+pub fn energy_model(spec: &BatterySpec) -> Arc<dyn EnergyModel> {
+    let gradient = Gradient {
+        mass: spec.mass.unwrap_or(DEFAULT_MASS),
+        regen_efficiency: DEFAULT_REGEN_EFFICIENCY,
+    };
+    match &spec.energy_model {
+        None | Some(EnergyModelSpec::Constant) => Arc::new(ConstantRate {
+            rate: spec.consumption_rate * 3.6, // convert kWh/km to kWs/m
+            gradient,
+        }),
+        Some(EnergyModelSpec::Speed { curve }) => Arc::new(SpeedCurve {
+            curve: curve
+                .iter()
+                .map(|[speed, rate]| (speed / 3.6, rate * 3.6)) // convert km/h to m/s and kWh/km to kWs/m
+                .collect(),
+            gradient,
+        }),
+        Some(EnergyModelSpec::Physics {
+            mass,
+            drag_area,
+            rolling_resistance,
+            efficiency,
+            regen_efficiency,
+        }) => Arc::new(Physics {
+            mass: *mass,
+            drag_area: *drag_area,
+            rolling_resistance: *rolling_resistance,
+            efficiency: *efficiency,
+            regen_efficiency: *regen_efficiency,
+        }),
+    }
+}
+
+/// Average link speed (m/s), links of no duration are treated as stationary
+fn speed(link: &Link) -> f32 {
+    match link.duration() {
+        0 => 0.0,
+        _ => link.speed(),
+    }
+}
+
+/// Potential energy of climbing links, added to consumption rate based models.
+/// Descending links recover part of the potential energy.
+#[derive(Debug)]
+pub struct Gradient {
+    /// Vehicle mass (kg)
+    pub mass: f32,
+    /// Proportion of potential energy recovered to the battery when descending
+    pub regen_efficiency: f32,
+}
+
+impl Gradient {
+    /// Battery energy (kWs) to climb given rise (m), negative if descending
+    pub fn energy(&self, rise: f32) -> f32 {
+        let potential = self.mass * GRAVITY * rise / 1000.0; // convert J to kWs
+        match potential >= 0.0 {
+            true => potential,
+            false => potential * self.regen_efficiency,
+        }
+    }
+}
+
+/// Constant consumption rate (kWs/m), plus the potential energy of the link gradient
+#[derive(Debug)]
+pub struct ConstantRate {
+    pub rate: f32,
+    pub gradient: Gradient,
+}
+
+impl EnergyModel for ConstantRate {
+    fn energy(&self, link: &Link) -> f32 {
+        link.distance * self.rate + self.gradient.energy(link.rise)
+    }
+}
+
+/// Consumption rate (kWs/m) by speed (m/s), linearly interpolated between points, plus the
+/// potential energy of the link gradient
+#[derive(Debug)]
+pub struct SpeedCurve {
+    pub curve: Vec<(f32, f32)>,
+    pub gradient: Gradient,
+}
+
+impl SpeedCurve {
+    pub fn rate(&self, speed: f32) -> f32 {
+        let (first, last) = (self.curve[0], self.curve[self.curve.len() - 1]);
+        if speed <= first.0 {
+            return first.1;
+        }
+        if speed >= last.0 {
+            return last.1;
+        }
+        let upper = self.curve.iter().position(|(s, _)| *s > speed).unwrap();
+        let ((s0, r0), (s1, r1)) = (self.curve[upper - 1], self.curve[upper]);
+        r0 + (r1 - r0) * (speed - s0) / (s1 - s0)
+    }
+}
+
+impl EnergyModel for SpeedCurve {
+    fn energy(&self, link: &Link) -> f32 {
+        link.distance * self.rate(speed(link)) + self.gradient.energy(link.rise)
+    }
+}
+
+/// Physics based model of tractive energy (rolling resistance, aerodynamic drag and climbing).
+/// Descending links can recover energy (less losses) if potential energy exceeds resistance.
+#[derive(Debug)]
+pub struct Physics {
+    /// Vehicle mass (kg)
+    pub mass: f32,
+    /// Drag coefficient times frontal area (m2)
+    pub drag_area: f32,
+    pub rolling_resistance: f32,
+    /// Proportion of battery energy delivered as tractive energy
+    pub efficiency: f32,
+    /// Proportion of negative tractive energy recovered to the battery
+    pub regen_efficiency: f32,
+}
+
+impl Physics {
+    /// Battery energy (kWs) to drive a distance (m) at given speed (m/s) and rise (m)
+    pub fn battery_energy(&self, distance: f32, speed: f32, rise: f32) -> f32 {
+        let tractive = (self.mass * GRAVITY * (self.rolling_resistance * distance + rise)
+            + 0.5 * AIR_DENSITY * self.drag_area * speed.powi(2) * distance)
+            / 1000.0; // convert J to kWs
+        match tractive >= 0.0 {
+            true => tractive / self.efficiency,
+            false => tractive * self.regen_efficiency,
+        }
+    }
+}
+
+impl EnergyModel for Physics {
+    fn energy(&self, link: &Link) -> f32 {
+        self.battery_energy(link.distance, speed(link), link.rise)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use configuration::groups::battery::BatterySpecBuilder;
+
+    fn link(distance: f32, duration: u32) -> Link {
+        Link {
+            start_time: 0,
+            end_time: duration,
+            lid: "a".to_string(),
+            distance,
+            node: (0.0, 0.0),
+            rise: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_constant_rate() {
+        let model = energy_model(&BatterySpecBuilder::new().consumption_rate(0.1).build());
+        assert!((model.energy(&link(1000.0, 100)) - 360.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_constant_rate_gradient() {
+        let model = energy_model(&BatterySpecBuilder::new().consumption_rate(0.1).build());
+        // climbing 10 m adds 1800 * 9.81 * 10 J of potential energy
+        let mut climb = link(1000.0, 100);
+        climb.rise = 10.0;
+        assert!((model.energy(&climb) - (360.0 + 176.58)).abs() < 1e-3);
+        // descending 10 m recovers part of the potential energy
+        climb.rise = -10.0;
+        assert!((model.energy(&climb) - (360.0 - 176.58 * 0.6)).abs() < 1e-3);
+        // using the battery mass
+        let model = energy_model(
+            &BatterySpecBuilder::new()
+                .consumption_rate(0.1)
+                .mass(1000.0)
+                .build(),
+        );
+        climb.rise = 10.0;
+        assert!((model.energy(&climb) - (360.0 + 98.1)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_speed_curve() {
+        let spec = BatterySpecBuilder::new()
+            .energy_model(EnergyModelSpec::Speed {
+                curve: vec![[36.0, 0.2], [72.0, 0.1]],
+            })
+            .build();
+        let model = energy_model(&spec);
+        let energy = |distance, duration| model.energy(&link(distance, duration));
+        // 10 m/s, 15 m/s and 20 m/s
+        assert!((energy(1000.0, 100) - 720.0).abs() < 1e-3);
+        assert!((energy(1500.0, 100) - 810.0).abs() < 1e-3);
+        assert!((energy(2000.0, 100) - 720.0).abs() < 1e-3);
+        // stationary uses the rate of the lowest speed
+        assert!((energy(10.0, 0) - 7.2).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_physics() {
+        let physics = Physics {
+            mass: 1000.0,
+            drag_area: 0.5,
+            rolling_resistance: 0.01,
+            efficiency: 0.5,
+            regen_efficiency: 0.5,
+        };
+        // rolling 98.1 N, drag 0.5 * 1.2 * 0.5 * 100 = 30 N, over 1 km
+        let energy = physics.energy(&link(1000.0, 100));
+        assert!((energy - 256.2).abs() < 1e-3);
+        // descending 20 m over 1 km, half of the net 98.1 kWs is recovered
+        let energy = physics.battery_energy(1000.0, 0.0, -20.0);
+        assert!((energy - (98.1 - 196.2) * 0.5).abs() < 1e-3);
+        // climbing 10 m adds 98.1 kWs of potential energy (less losses)
+        let mut climb = link(1000.0, 100);
+        climb.rise = 10.0;
+        assert!((physics.energy(&climb) - 452.4).abs() < 1e-3);
+    }
+}
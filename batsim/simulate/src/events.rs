@@ -1,10 +1,12 @@
 use serde::Serialize;
 
-#[derive(Serialize, PartialEq, Debug, Default)]
+#[derive(Serialize, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug, Default)]
 pub enum ChargeType {
     EnRoute,
     #[default]
     Activity,
+    /// Discharging a parked battery (vehicle-to-grid), with negative charge
+    Discharge,
 }
 
 /// Charge event
@@ -13,19 +15,22 @@ pub enum ChargeType {
 #[derive(Serialize, Default, Debug, PartialEq)]
 pub struct Event<'a> {
     pub charge_type: ChargeType,
-    pid: &'a str,
+    pub pid: &'a str,
     pub spec: Option<String>,
     #[serde(rename = "charge_(kWs)")]
     pub charge: f32,
     pub day: u32,
     #[serde(rename = "start_time_(s)")]
-    start_time: u32,
+    pub start_time: u32,
     #[serde(rename = "end_time_(s)")]
-    end_time: u32,
+    pub end_time: u32,
     pub activity: Option<&'a str>,
-    link_id: Option<&'a str>,
-    x: f32,
-    y: f32,
+    pub link_id: Option<&'a str>,
+    pub x: f32,
+    pub y: f32,
+    pub zone: Option<String>,
+    /// Person driving (or parking) the vehicle, if vehicles are shared
+    pub driver: Option<String>,
 }
 
 impl<'a> Event<'a> {
@@ -50,6 +55,8 @@ impl<'a> Event<'a> {
             link_id: Some(link_id),
             x: loc.0,
             y: loc.1,
+            zone: None,
+            driver: None,
         }
     }
     pub fn activity(
@@ -73,6 +80,22 @@ impl<'a> Event<'a> {
             link_id: None,
             x: loc.0,
             y: loc.1,
+            zone: None,
+            driver: None,
+        }
+    }
+    pub fn discharge(
+        pid: &'a str,
+        spec: Option<String>,
+        charge: f32,
+        day: u32,
+        time: (u32, u32),
+        activity: &'a str,
+        loc: (f32, f32),
+    ) -> Self {
+        Event {
+            charge_type: ChargeType::Discharge,
+            ..Event::activity(pid, spec, charge, day, time, activity, loc)
         }
     }
     pub fn normalise(&mut self, days: usize, start_day: usize) {
@@ -97,6 +120,13 @@ mod tests {
         assert_eq!(event.charge_type, ChargeType::EnRoute)
     }
 
+    #[test]
+    fn test_init_discharge() {
+        let event = Event::discharge("a", None, -1., 0, (0, 1), "home", (0., 0.));
+        assert_eq!(event.charge_type, ChargeType::Discharge);
+        assert_eq!(event.activity, Some("home"));
+    }
+
     #[test]
     fn test_init_default() {
         let event = Event::default();
@@ -0,0 +1,211 @@
+use core::fmt;
+use indicatif::HumanCount;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    record::{AgentSimulationRecord, EventsRecord},
+    results::HumanEnergyCount,
+    scoring::score_events,
+};
+use configuration::config::Config;
+use tracer::population::PersonAttributes;
+
+/// Results for agents sharing a person attribute value, energy in kWh.
+/// Agents without the attribute are reported without a value.
+#[derive(Serialize, Default, Debug, PartialEq)]
+pub struct GroupRecord<'a> {
+    pub attribute: &'a str,
+    pub value: Option<&'a str>,
+    pub agents: f32,
+    pub evs: f32,
+    #[serde(rename = "total_charge_(kWh)")]
+    pub total_charge: f32,
+    #[serde(rename = "total_enroute_(kWh)")]
+    pub total_enroute: f32,
+    #[serde(rename = "total_activity_(kWh)")]
+    pub total_activity: f32,
+    pub number_enroute: f32,
+    pub enroute_per_ev: f32,
+    /// Mean optimiser cost (en-route charge per day) of each EV
+    #[serde(rename = "cost_per_ev_(kWh/day)")]
+    pub cost_per_ev: f32,
+    #[serde(rename = "leak_(kWh)")]
+    pub leak: f32,
+}
+
+/// Aggregates agent results by the values of given person attributes
+#[derive(Debug)]
+pub struct GroupHandler<'a> {
+    config: &'a Config,
+    attributes: &'a [String],
+    // attribute values and ev ownership of each agent
+    agents: HashMap<&'a str, (Vec<Option<&'a str>>, bool)>,
+    groups: BTreeMap<(usize, Option<&'a str>), GroupRecord<'a>>,
+}
+
+impl<'a> GroupHandler<'a> {
+    pub fn new(config: &'a Config, attributes: &'a [String]) -> Self {
+        Self {
+            config,
+            attributes,
+            agents: HashMap::new(),
+            groups: BTreeMap::new(),
+        }
+    }
+
+    /// Add an agent's attributes and ev ownership, expected before the agent's simulation is added
+    pub fn add_agent(&mut self, pid: &'a str, attributes: &'a PersonAttributes, ev: bool) {
+        let values: Vec<Option<&'a str>> = self
+            .attributes
+            .iter()
+            .map(|key| attributes.get(key).map(String::as_str))
+            .collect();
+        for (i, value) in values.iter().enumerate() {
+            let group = self.group(i, *value);
+            group.agents += 1.0;
+            if ev {
+                group.evs += 1.0;
+            }
+        }
+        self.agents.insert(pid, (values, ev));
+    }
+
+    pub fn add(&mut self, sim: &AgentSimulationRecord) {
+        let record = sim.to_record();
+        let Some((values, ev)) = self.agents.get(record.pid).cloned() else {
+            return;
+        };
+        // cost is undefined for agents without a battery (no simulated days)
+        let cost = match ev && !sim.slice().is_empty() {
+            true => score_events(sim).1 / 3600.0,
+            false => 0.0,
+        };
+        for (i, value) in values.into_iter().enumerate() {
+            let group = self.group(i, value);
+            group.total_charge += record.total_charge;
+            group.total_enroute += record.total_enroute;
+            group.total_activity += record.total_activity;
+            group.number_enroute += record.number_enroute as f32;
+            group.cost_per_ev += cost;
+            group.leak += record.leak.unwrap_or(0.0);
+        }
+    }
+
+    fn group(&mut self, i: usize, value: Option<&'a str>) -> &mut GroupRecord<'a> {
+        let attribute = self.attributes[i].as_str();
+        self.groups
+            .entry((i, value))
+            .or_insert_with(|| GroupRecord {
+                attribute,
+                value,
+                ..Default::default()
+            })
+    }
+
+    /// Apply scale to counts and convert cost and en-route totals into means per EV
+    pub fn finalise(&mut self) {
+        let scale = self.config.scale.unwrap();
+        for group in self.groups.values_mut() {
+            if group.evs > 0.0 {
+                group.enroute_per_ev = group.number_enroute / group.evs;
+                group.cost_per_ev /= group.evs;
+            }
+            group.agents *= scale;
+            group.evs *= scale;
+            group.number_enroute *= scale;
+        }
+    }
+
+    /// Serialisable group records, ordered by attribute (as given) then value
+    pub fn records(&self) -> impl Iterator<Item = &GroupRecord<'a>> {
+        self.groups.values()
+    }
+}
+
+impl fmt::Display for GroupHandler<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut attribute = None;
+        for group in self.records() {
+            if attribute != Some(group.attribute) {
+                write!(f, "\n\n[Charging by {}]", group.attribute)?;
+                attribute = Some(group.attribute);
+            }
+            write!(
+                f,
+                "\n{}: {} agents ({} EVs), {} en-route and {} activity charge, {:.2} en-route events per EV",
+                group.value.unwrap_or("(missing)"),
+                HumanCount(group.agents as u64),
+                HumanCount(group.evs as u64),
+                HumanEnergyCount(group.total_enroute * 3600.0),
+                HumanEnergyCount(group.total_activity * 3600.0),
+                group.enroute_per_ev,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::Event;
+
+    #[test]
+    fn test_group_records() {
+        let config = Config::default();
+        let attributes = vec!["income".to_string()];
+        let mut handler = GroupHandler::new(&config, &attributes);
+        let high = PersonAttributes::from([("income".to_string(), "high".to_string())]);
+        let none = PersonAttributes::new();
+        handler.add_agent("a", &high, true);
+        handler.add_agent("b", &high, false);
+        handler.add_agent("c", &none, true);
+
+        let mut sim_a = AgentSimulationRecord::new("a", 0.1);
+        sim_a.new_day(10.0);
+        sim_a.add_event(Event::en_route("a", None, 7200., 1, (0, 1), "a", (0., 0.)));
+        sim_a.add_event(Event::activity(
+            "a",
+            None,
+            3600.,
+            1,
+            (2, 3),
+            "home",
+            (0., 0.),
+        ));
+        sim_a.new_day(10.0);
+        sim_a.error = Some(0.0);
+        handler.add(&sim_a);
+        handler.add(&AgentSimulationRecord::empty("b"));
+        handler.add(&AgentSimulationRecord::empty("c"));
+        handler.finalise();
+
+        let records = handler.records().collect::<Vec<&GroupRecord>>();
+        assert_eq!(
+            records,
+            vec![
+                &GroupRecord {
+                    attribute: "income",
+                    value: None,
+                    agents: 1.,
+                    evs: 1.,
+                    ..Default::default()
+                },
+                &GroupRecord {
+                    attribute: "income",
+                    value: Some("high"),
+                    agents: 2.,
+                    evs: 1.,
+                    total_charge: 3.,
+                    total_enroute: 2.,
+                    total_activity: 1.,
+                    number_enroute: 1.,
+                    enroute_per_ev: 1.,
+                    cost_per_ev: 1.,
+                    leak: 0.,
+                },
+            ]
+        );
+    }
+}
@@ -1,7 +1,13 @@
 pub mod battery;
+pub mod charger;
 pub mod days;
+pub mod energy;
 pub mod events;
+pub mod groups;
+pub mod profiles;
 pub mod record;
 pub mod results;
 pub mod scoring;
 pub mod sim;
+pub mod smart;
+pub mod zones;
@@ -0,0 +1,327 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::{
+    days::Flexibility,
+    events::{ChargeType, Event},
+};
+
+const DAY: u32 = 24 * 60 * 60;
+
+/// Number of time bins of given size (seconds) in a day, the last bin may be shorter
+fn bins(bin_size: u32) -> usize {
+    ((DAY + bin_size - 1) / bin_size) as usize
+}
+
+/// Location used to spatially split power profiles
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProfileLocation {
+    Link,
+    Zone,
+}
+
+// charge type, spec, activity, location
+type ProfileKey<'a> = (
+    ChargeType,
+    Option<&'a str>,
+    Option<&'a str>,
+    Option<&'a str>,
+);
+
+/// Aggregates charge events into average power profiles.
+/// Each event's energy is spread evenly across its duration and binned by time of day,
+/// times beyond 24 hours (for example from wrapped activities) are wrapped back into the day.
+#[derive(Debug)]
+pub struct ProfileHandler<'a> {
+    bin_size: u32,
+    location: Option<ProfileLocation>,
+    // energy (kWs) per time bin, for each profile key
+    profiles: BTreeMap<ProfileKey<'a>, Vec<f32>>,
+}
+
+/// Average power for a single profile time bin
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ProfileRecord<'a> {
+    pub charge_type: ChargeType,
+    pub spec: Option<&'a str>,
+    pub activity: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<Option<&'a str>>,
+    #[serde(rename = "start_time_(s)")]
+    pub start_time: u32,
+    #[serde(rename = "end_time_(s)")]
+    pub end_time: u32,
+    #[serde(rename = "energy_(kWh)")]
+    pub energy: f32,
+    #[serde(rename = "power_(kW)")]
+    pub power: f32,
+}
+
+/// Aggregates the discharge available from plugged in batteries (vehicle-to-grid flexibility) by
+/// time of day bin. In each bin a battery offers its charge above the discharge floor, limited by
+/// its discharge rate for the time plugged in during the bin.
+#[derive(Debug)]
+pub struct FlexibilityHandler {
+    bin_size: u32,
+    // available energy (kWs) per time bin
+    bins: Vec<f32>,
+}
+
+/// Vehicle-to-grid flexibility (discharge available) for a single time bin
+#[derive(Serialize, Debug, PartialEq)]
+pub struct FlexibilityRecord {
+    #[serde(rename = "start_time_(s)")]
+    pub start_time: u32,
+    #[serde(rename = "end_time_(s)")]
+    pub end_time: u32,
+    #[serde(rename = "energy_(kWh)")]
+    pub energy: f32,
+    #[serde(rename = "power_(kW)")]
+    pub power: f32,
+}
+
+impl<'a> ProfileHandler<'a> {
+    pub fn new(bin_size: u32, location: Option<ProfileLocation>) -> Self {
+        assert!(bin_size > 0, "profile bin size must be greater than zero");
+        Self {
+            bin_size,
+            location,
+            profiles: BTreeMap::new(),
+        }
+    }
+
+    fn bins(&self) -> usize {
+        bins(self.bin_size)
+    }
+
+    fn key(&self, event: &'a Event) -> ProfileKey<'a> {
+        let location = match self.location {
+            None => None,
+            Some(ProfileLocation::Link) => event.link_id,
+            Some(ProfileLocation::Zone) => event.zone.as_deref(),
+        };
+        (
+            event.charge_type,
+            event.spec.as_deref(),
+            event.activity,
+            location,
+        )
+    }
+
+    pub fn add(&mut self, event: &'a Event) {
+        let bins = self.bins();
+        let bin_size = self.bin_size;
+        let key = self.key(event);
+        let profile = self.profiles.entry(key).or_insert_with(|| vec![0.0; bins]);
+
+        if event.end_time <= event.start_time {
+            // instantaneous charge, place all energy in start bin
+            let bin = ((event.start_time % DAY) / bin_size) as usize;
+            profile[bin] += event.charge;
+            return;
+        }
+        let rate = event.charge / (event.end_time - event.start_time) as f32;
+        let mut time = event.start_time;
+        while time < event.end_time {
+            let day_start = time - (time % DAY);
+            let bin = ((time % DAY) / bin_size) as usize;
+            let bin_end = (day_start + (bin as u32 + 1) * bin_size).min(day_start + DAY);
+            let next = bin_end.min(event.end_time);
+            profile[bin] += (next - time) as f32 * rate;
+            time = next;
+        }
+    }
+
+    /// Serialisable profile records, only non-zero time bins are included
+    pub fn records(&self) -> impl Iterator<Item = ProfileRecord<'_>> {
+        let bin_size = self.bin_size;
+        let with_location = self.location.is_some();
+        self.profiles
+            .iter()
+            .flat_map(move |((charge_type, spec, activity, location), bins)| {
+                bins.iter()
+                    .enumerate()
+                    .filter(|(_, energy)| **energy != 0.0)
+                    .map(move |(i, energy)| {
+                        let start_time = i as u32 * bin_size;
+                        let end_time = (start_time + bin_size).min(DAY);
+                        ProfileRecord {
+                            charge_type: *charge_type,
+                            spec: *spec,
+                            activity: *activity,
+                            location: with_location.then_some(*location),
+                            start_time,
+                            end_time,
+                            energy: energy / 3600.0,
+                            power: energy / (end_time - start_time) as f32,
+                        }
+                    })
+            })
+    }
+}
+
+impl FlexibilityHandler {
+    pub fn new(bin_size: u32) -> Self {
+        assert!(
+            bin_size > 0,
+            "flexibility bin size must be greater than zero"
+        );
+        Self {
+            bin_size,
+            bins: vec![0.0; bins(bin_size)],
+        }
+    }
+
+    pub fn add(&mut self, flexibility: &Flexibility) {
+        let mut time = flexibility.start_time;
+        while time < flexibility.end_time {
+            let day_start = time - (time % DAY);
+            let bin = ((time % DAY) / self.bin_size) as usize;
+            let bin_end = (day_start + (bin as u32 + 1) * self.bin_size).min(day_start + DAY);
+            let next = bin_end.min(flexibility.end_time);
+            self.bins[bin] += flexibility
+                .energy
+                .min((next - time) as f32 * flexibility.rate);
+            time = next;
+        }
+    }
+
+    /// Serialisable flexibility records, only non-zero time bins are included
+    pub fn records(&self) -> impl Iterator<Item = FlexibilityRecord> + '_ {
+        self.bins
+            .iter()
+            .enumerate()
+            .filter(|(_, energy)| **energy != 0.0)
+            .map(|(i, energy)| {
+                let start_time = i as u32 * self.bin_size;
+                let end_time = (start_time + self.bin_size).min(DAY);
+                FlexibilityRecord {
+                    start_time,
+                    end_time,
+                    energy: energy / 3600.0,
+                    power: energy / (end_time - start_time) as f32,
+                }
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spread_event() {
+        let mut handler = ProfileHandler::new(10, None);
+        let event = Event::activity("a", None, 20., 1, (5, 25), "home", (0., 0.));
+        handler.add(&event);
+        let records = handler.records().collect::<Vec<ProfileRecord>>();
+        assert_eq!(records.len(), 3);
+        assert_eq!(
+            records.iter().map(|r| r.start_time).collect::<Vec<u32>>(),
+            vec![0, 10, 20]
+        );
+        assert_eq!(
+            records.iter().map(|r| r.power).collect::<Vec<f32>>(),
+            vec![0.5, 1.0, 0.5]
+        );
+    }
+
+    #[test]
+    fn test_instant_event() {
+        let mut handler = ProfileHandler::new(10, None);
+        let event = Event::en_route("a", None, 20., 1, (15, 15), "a", (0., 0.));
+        handler.add(&event);
+        let records = handler.records().collect::<Vec<ProfileRecord>>();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].start_time, 10);
+        assert_eq!(records[0].power, 2.0);
+    }
+
+    #[test]
+    fn test_wrap_event() {
+        let mut handler = ProfileHandler::new(3600, None);
+        let event = Event::activity(
+            "a",
+            None,
+            7200.,
+            1,
+            (DAY - 3600, DAY + 3600),
+            "home",
+            (0., 0.),
+        );
+        handler.add(&event);
+        let records = handler.records().collect::<Vec<ProfileRecord>>();
+        assert_eq!(
+            records
+                .iter()
+                .map(|r| (r.start_time, r.power))
+                .collect::<Vec<(u32, f32)>>(),
+            vec![(0, 1.0), (DAY - 3600, 1.0)]
+        );
+    }
+
+    #[test]
+    fn test_split_profiles() {
+        let mut handler = ProfileHandler::new(3600, Some(ProfileLocation::Link));
+        let event_a = Event::en_route("a", None, 3600., 1, (0, 10), "a", (0., 0.));
+        let event_b = Event::en_route("b", None, 3600., 1, (0, 10), "b", (0., 0.));
+        let event_c = Event::activity("c", None, 3600., 1, (0, 10), "home", (0., 0.));
+        handler.add(&event_a);
+        handler.add(&event_b);
+        handler.add(&event_c);
+        let records = handler.records().collect::<Vec<ProfileRecord>>();
+        assert_eq!(
+            records
+                .iter()
+                .map(|r| (r.charge_type, r.location, r.energy))
+                .collect::<Vec<(ChargeType, Option<Option<&str>>, f32)>>(),
+            vec![
+                (ChargeType::EnRoute, Some(Some("a")), 1.0),
+                (ChargeType::EnRoute, Some(Some("b")), 1.0),
+                (ChargeType::Activity, Some(None), 1.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_flexibility() {
+        let mut handler = FlexibilityHandler::new(3600);
+        // limited by the discharge rate
+        handler.add(&Flexibility {
+            start_time: 1800,
+            end_time: 9000,
+            energy: 5400.,
+            rate: 1.,
+        });
+        // limited by the charge above the floor
+        handler.add(&Flexibility {
+            start_time: 0,
+            end_time: 3600,
+            energy: 720.,
+            rate: 1.,
+        });
+        assert_eq!(
+            handler.records().collect::<Vec<FlexibilityRecord>>(),
+            vec![
+                FlexibilityRecord {
+                    start_time: 0,
+                    end_time: 3600,
+                    energy: 0.7,
+                    power: 0.7,
+                },
+                FlexibilityRecord {
+                    start_time: 3600,
+                    end_time: 7200,
+                    energy: 1.0,
+                    power: 1.0,
+                },
+                FlexibilityRecord {
+                    start_time: 7200,
+                    end_time: 10800,
+                    energy: 0.5,
+                    power: 0.5,
+                },
+            ]
+        );
+    }
+}
@@ -1,11 +1,14 @@
 use serde::Serialize;
 
 use crate::{
-    days::Day,
+    charger::Cycle,
+    days::{Day, Flexibility},
     events::{ChargeType, Event},
 };
 use configuration::config::Config;
 
+const DAY: u32 = 24 * 60 * 60;
+
 #[derive(Serialize)]
 pub struct PlanRecord<'a> {
     pub pid: &'a str,
@@ -21,6 +24,30 @@ pub struct PlanRecord<'a> {
     pub total_activity: f32,
     #[serde(rename = "leak_(kWh)")]
     pub leak: Option<f32>,
+    #[serde(rename = "min_soc_(kWh)")]
+    pub min_soc: Option<f32>,
+    #[serde(rename = "max_soc_(kWh)")]
+    pub max_soc: Option<f32>,
+    #[serde(rename = "electric_(km)")]
+    pub electric_distance: f32,
+    #[serde(rename = "fuel_(km)")]
+    pub fuel_distance: f32,
+    #[serde(rename = "fuel_(l)")]
+    pub fuel: f32,
+    pub number_discharge: usize,
+    #[serde(rename = "total_discharge_(kWh)")]
+    pub total_discharge: f32,
+}
+
+/// Battery state of charge at the end of a trace component
+#[derive(Serialize, Debug, PartialEq)]
+pub struct SocRecord<'a> {
+    pub pid: &'a str,
+    pub day: usize,
+    #[serde(rename = "time_(s)")]
+    pub time: u32,
+    #[serde(rename = "soc_(kWh)")]
+    pub soc: f32,
 }
 
 pub trait EventsRecord<'a> {
@@ -48,6 +75,10 @@ pub struct AgentSimulationRecord<'a> {
     slice_end: Option<usize>,
     close_precision: f32,
     pub error: Option<f32>,
+    // keep full state of charge timeline (min and max are always kept)
+    record_soc: bool,
+    // loops must be a multiple of this number of days
+    period: usize,
 }
 
 impl<'a> EventsRecord<'a> for AgentSimulationRecord<'a> {
@@ -58,6 +89,16 @@ impl<'a> EventsRecord<'a> for AgentSimulationRecord<'a> {
         let start_day = self.slice_start;
         let end_day = self.slice_end.unwrap_or(self.len());
         for i in start_day..end_day {
+            // distances are averaged over the slice
+            let day = &mut self.days[i];
+            let factor = config.scale.unwrap() / slice_length as f32;
+            day.electric_distance *= factor;
+            day.fuel_distance *= factor;
+            day.fuel *= factor;
+            for flexibility in day.flexibility.iter_mut() {
+                flexibility.energy *= factor;
+                flexibility.rate *= factor;
+            }
             // only normalise within the slice
             for event in self.days[i].events.iter_mut() {
                 if slice_length > 1 {
@@ -83,6 +124,23 @@ impl<'a> EventsRecord<'a> for AgentSimulationRecord<'a> {
             total_enroute: self.get_total_charge_en_route(),
             total_activity: self.get_total_charge_activity(),
             leak: self.get_error(),
+            min_soc: self.get_min_soc(),
+            max_soc: self.get_max_soc(),
+            electric_distance: self
+                .slice()
+                .iter()
+                .map(|day| day.electric_distance)
+                .sum::<f32>()
+                / 1000.0,
+            fuel_distance: self
+                .slice()
+                .iter()
+                .map(|day| day.fuel_distance)
+                .sum::<f32>()
+                / 1000.0,
+            fuel: self.slice().iter().map(|day| day.fuel).sum(),
+            number_discharge: self.get_count_discharge(),
+            total_discharge: self.get_total_discharge(),
         }
     }
 }
@@ -97,8 +155,24 @@ impl<'a> AgentSimulationRecord<'a> {
             slice_end: None,
             close_precision,
             error: None,
+            record_soc: false,
+            period: 1,
         }
     }
+    /// Keep the full state of charge timeline of the simulation
+    pub fn with_soc(mut self, record_soc: bool) -> Self {
+        self.record_soc = record_soc;
+        self
+    }
+    /// Only close loops of a multiple of given number of days, for example if charging
+    /// availability repeats over several days
+    pub fn with_period(mut self, period: usize) -> Self {
+        self.period = period.max(1);
+        self
+    }
+    pub fn pid(&self) -> &'a str {
+        self.pid
+    }
     pub fn empty(pid: &'a str) -> Self {
         AgentSimulationRecord {
             pid,
@@ -108,6 +182,8 @@ impl<'a> AgentSimulationRecord<'a> {
             slice_end: None,
             close_precision: 0.0,
             error: Some(0.0),
+            record_soc: false,
+            period: 1,
         }
     }
 
@@ -120,11 +196,93 @@ impl<'a> AgentSimulationRecord<'a> {
         self.days.last_mut().unwrap().push(event);
     }
 
+    /// Record discharge available while plugged in during a discharge window
+    pub fn add_flexibility(&mut self, flexibility: Flexibility) {
+        self.days.last_mut().unwrap().flexibility.push(flexibility);
+    }
+
+    /// Record battery state at the end of a trace component
+    pub fn add_soc(&mut self, time: u32, state: f32) {
+        let day = self.days.last_mut().unwrap();
+        day.update_soc_range(state);
+        if self.record_soc {
+            day.soc.push((time, state));
+        }
+    }
+
+    /// Record distances (m) driven using the battery and using fuel, and fuel used (l)
+    pub fn add_distance(&mut self, electric: f32, fuel_distance: f32, fuel: f32) {
+        self.days
+            .last_mut()
+            .unwrap()
+            .add_distance(electric, fuel_distance, fuel);
+    }
+
+    /// Record battery state without adding it to the timeline, for example prior to an en-route charge
+    pub fn update_soc_range(&mut self, state: f32) {
+        self.days.last_mut().unwrap().update_soc_range(state);
+    }
+
+    /// Serialisable state of charge timeline for the record slice, converted from kWs to kWh
+    pub fn soc_records(&'a self) -> impl Iterator<Item = SocRecord<'a>> {
+        self.slice().iter().enumerate().flat_map(move |(i, day)| {
+            day.soc.iter().map(move |(time, state)| SocRecord {
+                pid: self.pid,
+                day: i + 1,
+                time: *time,
+                soc: state / 3600.0,
+            })
+        })
+    }
+
+    /// Assign a zone to every event using given lookup from event location (x, y)
+    pub fn assign_zones(&mut self, zone: impl Fn(f32, f32) -> Option<String>) {
+        for event in self.days.iter_mut().flat_map(|day| day.events.iter_mut()) {
+            event.zone = zone(event.x, event.y);
+        }
+    }
+
+    /// Assign a driver to every event using given lookup from event start time and link or activity,
+    /// events may start after the start of their link or activity
+    pub fn assign_drivers(&mut self, driver: impl Fn(u32, &str) -> Option<String>) {
+        for event in self.days.iter_mut().flat_map(|day| day.events.iter_mut()) {
+            event.driver = event
+                .link_id
+                .or(event.activity)
+                .and_then(|component| driver(event.start_time, component));
+        }
+    }
+
+    /// Time windows of activity charge (and discharge) events using given spec in the record
+    /// slice, as a loop of days repeated before and after the slice
+    pub fn charging_windows(&self, spec: &str) -> Cycle {
+        let windows = self
+            .slice()
+            .iter()
+            .enumerate()
+            .flat_map(|(i, day)| {
+                let offset = i as u32 * DAY;
+                day.iter()
+                    .filter(|event| {
+                        event.charge_type != ChargeType::EnRoute
+                            && event.spec.as_deref() == Some(spec)
+                    })
+                    .map(move |event| (offset + event.start_time, offset + event.end_time))
+            })
+            .collect();
+        Cycle {
+            start_day: self.slice_start,
+            days: self.slice().len(),
+            windows,
+        }
+    }
+
     /// Check if state is in history
     /// Update state
     pub fn try_to_close(&mut self, state: f32) -> bool {
+        let days = self.history.len();
         for (k, v) in self.history.iter().enumerate() {
-            if (state - v).abs() < self.close_precision {
+            if (days - k) % self.period == 0 && (state - v).abs() < self.close_precision {
                 self.slice_start = k;
                 self.error = Some(self.error(state));
                 return true;
@@ -137,7 +295,7 @@ impl<'a> AgentSimulationRecord<'a> {
     pub fn force_close(&mut self) {
         let mut best_score: (f32, usize) = (f32::MAX, usize::MAX);
         for i in 0..(self.history.len() - 1) {
-            for j in (i + 1)..self.history.len() {
+            for j in ((i + self.period)..self.history.len()).step_by(self.period) {
                 let leak: f32 = self.history[i] - self.history[j];
                 let score = (leak.abs(), j - i);
                 if score < best_score {
@@ -147,7 +305,9 @@ impl<'a> AgentSimulationRecord<'a> {
                 }
             }
         }
-        self.error = Some(self.history[self.slice_end.unwrap()] - self.history[self.slice_start]);
+        // no loop of a multiple of the period, use the whole record
+        let end = *self.slice_end.get_or_insert(self.history.len() - 1);
+        self.error = Some(self.history[end] - self.history[self.slice_start]);
     }
 
     /// Get error (gap between state and start of record slice)
@@ -171,16 +331,33 @@ impl<'a> AgentSimulationRecord<'a> {
         self.days.len()
     }
 
-    /// Retrieve total charge from plan, convert from kWs to kWh
+    /// Retrieve total charge (excluding discharge) from plan, convert from kWs to kWh
     pub fn get_total_charge(&self) -> f32 {
         self.slice()
             .iter()
             .flatten()
+            .filter(|event| event.charge_type != ChargeType::Discharge)
+            .map(|event| event.charge)
+            .sum::<f32>()
+            / 3600.0
+    }
+
+    /// Retrieve total (negative) discharge from plan, convert from kWs to kWh
+    fn get_total_discharge(&self) -> f32 {
+        self.slice()
+            .iter()
+            .flatten()
+            .filter(|event| event.charge_type == ChargeType::Discharge)
             .map(|event| event.charge)
             .sum::<f32>()
             / 3600.0
     }
 
+    /// Check if the plan discharges the battery
+    pub fn has_discharge(&self) -> bool {
+        self.get_count_discharge() > 0
+    }
+
     /// Retrieve total en_route charge from plan, convert from kWs to kWh
     fn get_total_charge_en_route(&self) -> f32 {
         self.slice()
@@ -207,7 +384,18 @@ impl<'a> AgentSimulationRecord<'a> {
             / 3600.0
     }
     fn get_count(&self) -> usize {
-        self.slice().iter().flatten().count()
+        self.slice()
+            .iter()
+            .flatten()
+            .filter(|event| event.charge_type != ChargeType::Discharge)
+            .count()
+    }
+    fn get_count_discharge(&self) -> usize {
+        self.slice()
+            .iter()
+            .flatten()
+            .filter(|event| event.charge_type == ChargeType::Discharge)
+            .count()
     }
     fn get_count_en_route(&self) -> usize {
         self.slice()
@@ -227,6 +415,22 @@ impl<'a> AgentSimulationRecord<'a> {
     fn get_error(&self) -> Option<f32> {
         self.error.map(|v| v / 3600.0)
     }
+    /// Retrieve lowest battery state from plan, convert from kWs to kWh
+    fn get_min_soc(&self) -> Option<f32> {
+        self.slice()
+            .iter()
+            .filter_map(|day| day.soc_range)
+            .map(|(min, _)| min / 3600.0)
+            .reduce(f32::min)
+    }
+    /// Retrieve highest battery state from plan, convert from kWs to kWh
+    fn get_max_soc(&self) -> Option<f32> {
+        self.slice()
+            .iter()
+            .filter_map(|day| day.soc_range)
+            .map(|(_, max)| max / 3600.0)
+            .reduce(f32::max)
+    }
 }
 
 #[cfg(test)]
@@ -277,6 +481,17 @@ mod tests {
         record
     }
 
+    #[test]
+    fn test_assign_zones() {
+        let mut record = record();
+        record.assign_zones(|x, _| (x < 1.).then(|| "a".to_string()));
+        assert!(record
+            .days
+            .iter()
+            .flatten()
+            .all(|event| event.zone.as_deref() == Some("a")));
+    }
+
     #[test]
     fn test_not_close() {
         let mut record = record();
@@ -289,6 +504,16 @@ mod tests {
         assert_eq!(record.slice().len(), 3)
     }
 
+    #[test]
+    fn test_close_with_period() {
+        let mut record = record().with_period(2);
+        // one day loop to day 2 is not a multiple of the period
+        assert!(!record.try_to_close(8. * 3600.));
+        assert!(record.try_to_close(4. * 3600.));
+        assert_eq!(record.slice_start, 1);
+        assert_eq!(record.slice().len(), 2);
+    }
+
     #[test]
     fn test_close_on_first() {
         let mut record = record();
@@ -429,6 +654,53 @@ mod tests {
         assert_eq!(record.get_total_charge(), 0.)
     }
 
+    #[test]
+    fn test_soc() {
+        let mut record = AgentSimulationRecord::new("a", 0.1 * 3600.).with_soc(true);
+        record.new_day(10. * 3600.);
+        record.add_soc(1, 8. * 3600.);
+        record.update_soc_range(2. * 3600.);
+        record.add_soc(2, 10. * 3600.);
+        record.new_day(10. * 3600.);
+        record.add_soc(1, 9. * 3600.);
+        assert!(record.try_to_close(10. * 3600.));
+        assert_eq!(record.get_min_soc(), Some(2.));
+        assert_eq!(record.get_max_soc(), Some(10.));
+        assert_eq!(
+            record.soc_records().collect::<Vec<SocRecord>>(),
+            vec![
+                SocRecord {
+                    pid: "a",
+                    day: 1,
+                    time: 1,
+                    soc: 8.
+                },
+                SocRecord {
+                    pid: "a",
+                    day: 1,
+                    time: 2,
+                    soc: 10.
+                },
+                SocRecord {
+                    pid: "a",
+                    day: 2,
+                    time: 1,
+                    soc: 9.
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_soc_not_recorded() {
+        let mut record = AgentSimulationRecord::new("a", 0.1 * 3600.);
+        record.new_day(10. * 3600.);
+        record.add_soc(1, 8. * 3600.);
+        assert!(record.try_to_close(10. * 3600.));
+        assert_eq!(record.get_min_soc(), Some(8.));
+        assert_eq!(record.soc_records().count(), 0);
+    }
+
     #[test]
     fn test_totals() {
         let record = record();
@@ -1,10 +1,45 @@
 use core::fmt;
 use indicatif::HumanCount;
+use serde::Serialize;
 use std::collections::HashMap;
 
 use crate::events::{ChargeType, Event};
 use configuration::config::Config;
 
+/// Serialisable run summary totals, energy converted from kWs to kWh
+#[derive(Serialize, Debug, PartialEq)]
+pub struct SummaryRecord {
+    #[serde(rename = "total_charge_(kWh)")]
+    pub total_charge: f32,
+    pub number_charges: f32,
+    #[serde(rename = "leak_(kWh)")]
+    pub leak: f32,
+    #[serde(rename = "total_enroute_(kWh)")]
+    pub total_enroute: f32,
+    pub number_enroute: f32,
+    #[serde(rename = "total_activity_(kWh)")]
+    pub total_activity: f32,
+    pub number_activity: f32,
+    #[serde(rename = "electric_(km)")]
+    pub electric_distance: f32,
+    #[serde(rename = "fuel_(km)")]
+    pub fuel_distance: f32,
+    #[serde(rename = "fuel_(l)")]
+    pub fuel: f32,
+    #[serde(rename = "total_discharge_(kWh)")]
+    pub total_discharge: f32,
+    pub number_discharge: f32,
+}
+
+/// Serialisable run summary of charging at a single activity type
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ActivitySummaryRecord<'a> {
+    pub activity: &'a str,
+    #[serde(rename = "total_charge_(kWh)")]
+    pub total_charge: f32,
+    pub number_charges: f32,
+}
+
 #[derive(Debug)]
 pub struct SummaryHandler<'a> {
     config: &'a Config,
@@ -16,6 +51,13 @@ pub struct SummaryHandler<'a> {
     activity_events_map: HashMap<&'a str, f32>,
     // energy leak from unclosed plans
     leak: f32,
+    // distances (km) driven using batteries and using fuel (plug-in hybrids), and fuel used (l)
+    electric_distance: f32,
+    fuel_distance: f32,
+    fuel: f32,
+    // (negative) discharge sum and events count
+    discharge: f32,
+    discharge_events: f32,
 }
 
 impl<'a> SummaryHandler<'a> {
@@ -27,6 +69,11 @@ impl<'a> SummaryHandler<'a> {
             en_route_events: 0.0,
             activity_events_map: HashMap::new(),
             leak: 0.0,
+            electric_distance: 0.0,
+            fuel_distance: 0.0,
+            fuel: 0.0,
+            discharge: 0.0,
+            discharge_events: 0.0,
         }
     }
 
@@ -46,6 +93,10 @@ impl<'a> SummaryHandler<'a> {
                     .and_modify(|count| *count += 1.0)
                     .or_insert(1.0);
             }
+            ChargeType::Discharge => {
+                self.discharge += event.charge;
+                self.discharge_events += 1.0;
+            }
         }
     }
 
@@ -53,15 +104,58 @@ impl<'a> SummaryHandler<'a> {
         self.leak += leak
     }
 
+    /// Add an agent's (scaled) distances (km) using battery and fuel, and fuel used (l)
+    pub fn add_distance(&mut self, electric: f32, fuel_distance: f32, fuel: f32) {
+        self.electric_distance += electric;
+        self.fuel_distance += fuel_distance;
+        self.fuel += fuel;
+    }
+
     pub fn finalise(&mut self) {
         self.leak *= self.config.scale.unwrap();
         self.en_route_events *= self.config.scale.unwrap();
+        self.discharge_events *= self.config.scale.unwrap();
         self.activity_events_map = self
             .activity_events_map
             .iter()
             .map(|(k, v)| (*k, v * self.config.scale.unwrap()))
             .collect();
     }
+
+    /// Serialisable summary totals, expected to be used after finalise
+    pub fn to_record(&self) -> SummaryRecord {
+        let total_activity = self.activity_charge_map.values().sum::<f32>();
+        let number_activity = self.activity_events_map.values().sum::<f32>();
+        SummaryRecord {
+            total_charge: (self.en_route_charge + total_activity) / 3600.0,
+            number_charges: self.en_route_events + number_activity,
+            leak: self.leak,
+            total_enroute: self.en_route_charge / 3600.0,
+            number_enroute: self.en_route_events,
+            total_activity: total_activity / 3600.0,
+            number_activity,
+            electric_distance: self.electric_distance,
+            fuel_distance: self.fuel_distance,
+            fuel: self.fuel,
+            total_discharge: self.discharge / 3600.0,
+            number_discharge: self.discharge_events,
+        }
+    }
+
+    /// Serialisable summaries for each activity type, ordered by activity
+    pub fn activity_records(&self) -> Vec<ActivitySummaryRecord<'a>> {
+        let mut records: Vec<ActivitySummaryRecord> = self
+            .activity_charge_map
+            .iter()
+            .map(|(activity, charge)| ActivitySummaryRecord {
+                activity,
+                total_charge: charge / 3600.0,
+                number_charges: *self.activity_events_map.get(activity).unwrap_or(&0.0),
+            })
+            .collect();
+        records.sort_by_key(|record| record.activity);
+        records
+    }
 }
 
 impl fmt::Display for SummaryHandler<'_> {
@@ -95,6 +189,33 @@ impl fmt::Display for SummaryHandler<'_> {
             "\nTotal Activity Charge Events: {}",
             HumanCount(activity_events as u64)
         )?;
+        if self.fuel_distance > 0.0 {
+            write!(f, "\n\n[Plug-in Hybrids]")?;
+            write!(
+                f,
+                "\nElectric Distance: {} km",
+                HumanCount(self.electric_distance as u64)
+            )?;
+            write!(
+                f,
+                "\nFuel Distance: {} km",
+                HumanCount(self.fuel_distance as u64)
+            )?;
+            write!(f, "\nFuel: {} l", HumanCount(self.fuel as u64))?;
+        }
+        if self.discharge_events > 0.0 {
+            write!(f, "\n\n[Vehicle-to-Grid]")?;
+            write!(
+                f,
+                "\nTotal Discharge: {}",
+                HumanEnergyCount(-self.discharge)
+            )?;
+            write!(
+                f,
+                "\nTotal Discharge Events: {}",
+                HumanCount(self.discharge_events as u64)
+            )?;
+        }
         write!(f, "\n\n[Charging by activity]")?;
         for (k, v) in self.activity_charge_map.iter() {
             write!(
@@ -144,6 +265,53 @@ impl fmt::Display for HumanEnergyCount {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_summary_records() {
+        let config = Config::default();
+        let mut summary = SummaryHandler::new(&config);
+        let event_a = Event::en_route("a", None, 3600., 1, (0, 1), "a", (0., 0.));
+        let event_b = Event::activity("a", None, 7200., 1, (0, 1), "work", (0., 0.));
+        let event_c = Event::activity("a", None, 3600., 1, (0, 1), "home", (0., 0.));
+        summary.add(&event_a);
+        summary.add(&event_b);
+        summary.add(&event_c);
+        summary.add_leak(1.);
+        summary.add_distance(10., 5., 0.3);
+        summary.finalise();
+        assert_eq!(
+            summary.to_record(),
+            SummaryRecord {
+                total_charge: 4.,
+                number_charges: 3.,
+                leak: 1.,
+                total_enroute: 1.,
+                number_enroute: 1.,
+                total_activity: 3.,
+                number_activity: 2.,
+                electric_distance: 10.,
+                fuel_distance: 5.,
+                fuel: 0.3,
+                total_discharge: 0.,
+                number_discharge: 0.,
+            }
+        );
+        assert_eq!(
+            summary.activity_records(),
+            vec![
+                ActivitySummaryRecord {
+                    activity: "home",
+                    total_charge: 1.,
+                    number_charges: 1.,
+                },
+                ActivitySummaryRecord {
+                    activity: "work",
+                    total_charge: 2.,
+                    number_charges: 1.,
+                },
+            ]
+        );
+    }
+
     #[test]
     fn test_human_energy_count_fmt() {
         assert_eq!("1000 kWs", format!("{}", HumanEnergyCount(1000.0)));
@@ -1,8 +1,9 @@
 use crate::{events::ChargeType, record::AgentSimulationRecord};
 
-/// Score charge events
-pub fn score_events(record: &AgentSimulationRecord) -> (f32, f32, f32) {
+/// Score charge events (and fuel use of plug-in hybrids)
+pub fn score_events(record: &AgentSimulationRecord) -> (f32, f32, f32, f32) {
     let days = record.slice().len() as f32;
+    let fuel_distance: f32 = record.slice().iter().map(|day| day.fuel_distance).sum();
     let mut en_route: u32 = 0;
     let mut cost: f32 = 0.;
     let mut activity: u32 = 0;
@@ -16,12 +17,14 @@ pub fn score_events(record: &AgentSimulationRecord) -> (f32, f32, f32) {
                     en_route += 1;
                     cost += event.charge;
                 }
+                ChargeType::Discharge => (),
             }
         }
     }
     (
         en_route as f32 / days, // number of en-route charge events per day
         cost / days,            // average en-route charge total per day
+        fuel_distance / days,   // average distance driven using fuel per day
         activity as f32 / days, // number of activity charge events per day
     )
 }
@@ -46,7 +49,7 @@ mod tests {
         ));
         record.new_day(9.0);
         record.add_event(Event::en_route("A", None, 1., 2, (4, 7), "a", (0.0, 0.0)));
-        assert_eq!(score_events(&record), (0.5, 0.5, 0.5));
+        assert_eq!(score_events(&record), (0.5, 0.5, 0., 0.5));
         record.new_day(8.0);
         record.new_day(7.0);
         record.add_event(Event::en_route("A", None, 3., 4, (4, 7), "a", (0.0, 0.0)));
@@ -59,6 +62,8 @@ mod tests {
             "home",
             (0.0, 0.0),
         ));
-        assert_eq!(score_events(&record), (0.5, 1., 0.5));
+        assert_eq!(score_events(&record), (0.5, 1., 0., 0.5));
+        record.add_distance(0., 2000., 0.1);
+        assert_eq!(score_events(&record), (0.5, 1., 500., 0.5));
     }
 }
@@ -1,7 +1,12 @@
-use crate::{battery::BatteryState, events::Event, record::AgentSimulationRecord};
+use crate::{
+    battery::BatteryState, charger::ChargerAccess, days::Flexibility, events::Event,
+    record::AgentSimulationRecord, smart::schedule,
+};
 use configuration::{charge_plan::ActivityChargingPlanner, config::Config, handler::AgentConfig};
 use tracer::{Component, Trace};
 
+const DAY: u32 = 24 * 60 * 60;
+
 /// Run a simulation for given trace, using given battery and viable charge events (as trace indices).
 // todo: reduce args, perhaps pass config ref
 pub fn simulate<'a>(
@@ -11,14 +16,44 @@ pub fn simulate<'a>(
     agent_config: &AgentConfig,
     activity_charging_planner: ActivityChargingPlanner,
     config: &Config,
+    record_soc: bool,
+) -> AgentSimulationRecord<'a> {
+    simulate_with_access(
+        pid,
+        trace,
+        charge_activities,
+        agent_config,
+        activity_charging_planner,
+        config,
+        record_soc,
+        &ChargerAccess::unrestricted(),
+    )
+}
+
+/// Run a simulation (see `simulate`) where a charger may be shared with other agents
+#[allow(clippy::too_many_arguments)]
+pub fn simulate_with_access<'a>(
+    pid: &'a str,
+    trace: &'a Trace,
+    charge_activities: Vec<usize>,
+    agent_config: &AgentConfig,
+    activity_charging_planner: ActivityChargingPlanner,
+    config: &Config,
+    record_soc: bool,
+    access: &ChargerAccess,
 ) -> AgentSimulationRecord<'a> {
     let close_precision = config.precision.unwrap();
     let max_days = config.patience.unwrap();
     let battery_spec = agent_config.battery.unwrap();
     let trigger_spec = agent_config.trigger.unwrap();
     let en_route_spec = agent_config.en_route.unwrap();
-    let mut battery = BatteryState::new(battery_spec, trigger_spec);
-    let mut simulation_record = AgentSimulationRecord::new(pid, close_precision);
+    let mut battery = BatteryState::new(battery_spec, trigger_spec).with_temperature(
+        config.temperature.as_ref(),
+        battery_spec.temperature_sensitivity.as_ref(),
+    );
+    let mut simulation_record = AgentSimulationRecord::new(pid, close_precision)
+        .with_soc(record_soc)
+        .with_period(access.period());
 
     for day in 0..max_days {
         simulation_record.new_day(battery.state);
@@ -26,36 +61,109 @@ pub fn simulate<'a>(
         for (i, component) in trace.plan.iter().enumerate() {
             match component {
                 Component::ActivityType(activity) if charge_activities.contains(&i) => {
+                    battery.park(activity.start_time, activity.end_time);
                     let charge_spec = activity_charging_planner.get(&activity.act).unwrap();
-                    let (charge, charge_duration) =
-                        battery.charge_for_duration(activity.duration(), charge_spec.charge_rate);
-                    if charge > 0.0 {
-                        simulation_record.add_event(Event::activity(
-                            pid,
-                            charge_spec.name,
-                            charge,
-                            day as u32 + 1,
-                            (activity.start_time, activity.start_time + charge_duration),
-                            &activity.act,
-                            activity.node,
-                        ))
+                    let windows = match access.is_shared(charge_spec.name.as_deref()) {
+                        true => access.windows(day, activity.start_time, activity.end_time),
+                        false => vec![(activity.start_time, activity.end_time)],
+                    };
+                    let discharge = charge_spec
+                        .discharge
+                        .as_ref()
+                        .filter(|_| !access.no_discharge);
+                    let windows =
+                        windows
+                            .into_iter()
+                            .flat_map(|(start_time, end_time)| match discharge {
+                                Some(discharge) => {
+                                    split_discharge(start_time, end_time, &discharge.windows)
+                                }
+                                None => vec![(start_time, end_time, false)],
+                            });
+                    for (start_time, end_time, discharging) in windows {
+                        if let Some(discharge) = discharge.filter(|_| discharging) {
+                            // keep enough charge to reach the next charging activity
+                            let floor = (discharge.floor * battery.capacity).max(
+                                battery.trigger
+                                    + plan_ahead(trace, &charge_activities, i + 1, &battery),
+                            );
+                            simulation_record.add_flexibility(Flexibility {
+                                start_time,
+                                end_time,
+                                energy: (battery.state - floor).max(0.0),
+                                rate: discharge.rate,
+                            });
+                            let (charge, discharge_duration) = battery.discharge_for_duration(
+                                end_time - start_time,
+                                discharge.rate,
+                                floor,
+                            );
+                            if charge < 0.0 {
+                                simulation_record.add_event(Event::discharge(
+                                    pid,
+                                    charge_spec.name.clone(),
+                                    charge,
+                                    day as u32 + 1,
+                                    (start_time, start_time + discharge_duration),
+                                    &activity.act,
+                                    activity.node,
+                                ))
+                            }
+                            continue;
+                        }
+                        let sessions = match &charge_spec.smart {
+                            Some(smart) => {
+                                // scheduled at the lowest hourly rate of the window, so that
+                                // sessions deliver the deficit whichever hours are chosen
+                                let charge_rate = battery.min_charge_rate(
+                                    charge_spec.charge_rate,
+                                    start_time,
+                                    end_time,
+                                );
+                                let required = (battery.deficit() / charge_rate).ceil() as u32;
+                                schedule(smart, start_time, end_time, required)
+                            }
+                            None => vec![(start_time, end_time)],
+                        };
+                        for (start_time, end_time) in sessions {
+                            let (charge, charge_duration) = battery.charge_for_window(
+                                start_time,
+                                end_time,
+                                charge_spec.charge_rate,
+                            );
+                            if charge > 0.0 {
+                                simulation_record.add_event(Event::activity(
+                                    pid,
+                                    charge_spec.name.clone(),
+                                    charge,
+                                    day as u32 + 1,
+                                    (start_time, start_time + charge_duration),
+                                    &activity.act,
+                                    activity.node,
+                                ))
+                            }
+                        }
                     }
+                    simulation_record.add_soc(activity.end_time, battery.state);
                 }
                 Component::LinkType(link) => {
-                    battery.apply_distance(link.distance);
+                    let (electric, fuel_distance) = battery.drive(link);
+                    simulation_record.add_distance(
+                        electric,
+                        fuel_distance,
+                        battery.fuel(fuel_distance),
+                    );
                     if battery.must_charge() {
+                        simulation_record.update_soc_range(battery.state);
                         // check for en-route charge
+                        let charge_rate =
+                            battery.charge_rate(en_route_spec.charge_rate, link.start_time);
                         let (charge, duration) = match charge_activities.len() {
-                            0 => battery.charge_to_full(en_route_spec.charge_rate), // no valid activities for charging so just charge to full
+                            0 => battery.charge_to_full(charge_rate), // no valid activities for charging so just charge to full
                             _ => {
                                 // plan ahead to minimise en-route charge
-                                let charge = plan_ahead(
-                                    trace,
-                                    &charge_activities,
-                                    i,
-                                    battery.consumption_rate,
-                                );
-                                battery.charge_to_desired(charge, en_route_spec.charge_rate)
+                                let charge = plan_ahead(trace, &charge_activities, i, &battery);
+                                battery.charge_to_desired(charge, charge_rate)
                             }
                         };
                         simulation_record.add_event(Event::en_route(
@@ -68,8 +176,12 @@ pub fn simulate<'a>(
                             link.node,
                         ))
                     }
+                    simulation_record.add_soc(link.end_time, battery.state);
+                }
+                Component::ActivityType(activity) => {
+                    battery.park(activity.start_time, activity.end_time);
+                    simulation_record.add_soc(activity.end_time, battery.state)
                 }
-                _ => (),
             }
         }
         if simulation_record.try_to_close(battery.state) {
@@ -81,17 +193,59 @@ pub fn simulate<'a>(
     simulation_record
 }
 
-/// Plan ahead from index i looking for next available activity charge, return required additional charge to get there
-fn plan_ahead(trace: &Trace, charge_activities: &[usize], start: usize, efficiency: f32) -> f32 {
+/// Split a charging window (trace seconds) into time ordered pieces, flagged if they fall in
+/// any of the discharge windows (time of day seconds, repeated each day)
+fn split_discharge(start: u32, end: u32, discharge: &[[u32; 2]]) -> Vec<(u32, u32, bool)> {
+    let mut windows = (start / DAY..=end / DAY)
+        .flat_map(|day| {
+            discharge.iter().map(move |[window_start, window_end]| {
+                (
+                    (window_start + day * DAY).max(start),
+                    (window_end + day * DAY).min(end),
+                )
+            })
+        })
+        .filter(|(start, end)| start < end)
+        .collect::<Vec<(u32, u32)>>();
+    windows.sort();
+    let mut pieces = Vec::new();
+    let mut time = start;
+    for (window_start, window_end) in windows {
+        if window_start > time {
+            pieces.push((time, window_start, false));
+        }
+        if window_end > time {
+            pieces.push((time.max(window_start), window_end, true));
+            time = window_end;
+        }
+    }
+    if time < end {
+        pieces.push((time, end, false));
+    }
+    pieces
+}
+
+/// Plan ahead from index i looking for next available activity charge, return required additional charge to get there,
+/// including auxiliary loads while parked at activities on the way
+fn plan_ahead(
+    trace: &Trace,
+    charge_activities: &[usize],
+    start: usize,
+    battery: &BatteryState,
+) -> f32 {
     let mut required_charge = 0.0;
     for (i, component) in trace.plan.iter().enumerate().skip(start) {
         // this includes current link (again)
         match component {
             Component::ActivityType(_) if charge_activities.contains(&i) => return required_charge,
             Component::LinkType(ref link) => {
-                required_charge += link.distance * efficiency;
+                required_charge += battery.energy(link);
+            }
+            Component::ActivityType(activity) => {
+                required_charge += battery
+                    .auxiliary
+                    .parked(activity.start_time, activity.end_time);
             }
-            Component::ActivityType(_) => (),
         }
     }
     for (i, component) in trace.plan.iter().enumerate().take(start) {
@@ -100,9 +254,13 @@ fn plan_ahead(trace: &Trace, charge_activities: &[usize], start: usize, efficien
                 return required_charge
             }
             Component::LinkType(link) => {
-                required_charge += link.distance * efficiency;
+                required_charge += battery.energy(link);
+            }
+            Component::ActivityType(activity) => {
+                required_charge += battery
+                    .auxiliary
+                    .parked(activity.start_time, activity.end_time);
             }
-            Component::ActivityType(_) => (),
         };
     }
     required_charge
@@ -112,13 +270,15 @@ fn plan_ahead(trace: &Trace, charge_activities: &[usize], start: usize, efficien
 mod tests {
 
     use super::*;
-    use crate::record::EventsRecord;
+    use crate::{charger::Cycle, record::EventsRecord};
     use configuration::groups::{
-        activity::ActivitySpec,
-        battery::{BatterySpec, BatterySpecBuilder},
+        activity::{ActivitySpec, DischargeSpec, SmartChargingSpec},
+        battery::{AuxiliaryLoads, BatterySpec, BatterySpecBuilder, TemperatureSensitivity},
         en_route::EnRouteSpec,
         trigger::TriggerSpec,
     };
+    use configuration::temperature::TemperatureProfile;
+    use std::collections::{BTreeMap, HashMap};
     use tracer::{Activity, Component, Link, Trace};
 
     macro_rules! quick_trace {
@@ -142,6 +302,7 @@ mod tests {
             end_time: $et,
             distance: $d as f32,
             node: ($x as f32, $y as f32),
+            rise: 0.0,
         })
     };
     () => {};
@@ -188,6 +349,7 @@ mod tests {
             &agent_config,
             charge_activity_configs,
             &config,
+            false,
         );
         let simulation_record = binding.days().flatten().collect::<Vec<&Event>>();
         assert_eq!(simulation_record, expected);
@@ -242,6 +404,7 @@ mod tests {
                 &agent_config,
                 charge_activity_configs,
                 &config,
+                false,
             )
             .days()
             .flatten()
@@ -313,6 +476,7 @@ mod tests {
                 &agent_config,
                 charge_activity_configs,
                 &config,
+                false,
             )
             .days()
             .flatten()
@@ -380,6 +544,7 @@ mod tests {
                 &agent_config,
                 charge_activity_configs,
                 &config,
+                false,
             )
             .days()
             .flatten()
@@ -387,4 +552,508 @@ mod tests {
             expected
         )
     }
+
+    #[test]
+    fn test_sim_shared_charger() {
+        let config = Config::default();
+        let trace = quick_trace!([(L, "a", 1, 2, 1., 0, 0), (A, "home", 2, 10, 0, 0)]);
+        let battery_spec = BatterySpecBuilder::new()
+            .capacity(3.0 / 3600.) // 3 kWs
+            .full()
+            .consumption_rate(1.0 / 3.6)
+            .build();
+        let trigger_spec = TriggerSpec::empty();
+        let charge_act = ActivitySpec::new(
+            Some("home".to_string()),
+            vec!["home".to_string()],
+            1.0,
+            None,
+            None,
+        );
+        let en_route_spec = EnRouteSpec::new(None, 1.0, None, None);
+        let agent_config: AgentConfig = AgentConfig {
+            pid: "a",
+            battery: Some(&battery_spec),
+            trigger: Some(&trigger_spec),
+            en_route: Some(&en_route_spec),
+            activities: vec![&charge_act],
+        };
+        // the charger is used by another vehicle on arrival
+        let mut access = ChargerAccess::new("home");
+        access.block(Cycle {
+            start_day: 0,
+            days: 1,
+            windows: vec![(0, 5)],
+        });
+        let event = Event::activity(
+            "A",
+            Some("home".to_string()),
+            1.,
+            1,
+            (5, 6),
+            "home",
+            (0.0, 0.0),
+        );
+        assert_eq!(
+            simulate_with_access(
+                "A",
+                &trace,
+                vec![1],
+                &agent_config,
+                ActivityChargingPlanner::new(vec![&charge_act]),
+                &config,
+                false,
+                &access,
+            )
+            .days()
+            .flatten()
+            .collect::<Vec<&Event>>(),
+            vec![&event]
+        );
+    }
+
+    #[test]
+    fn test_sim_hybrid_uses_fuel() {
+        let config = Config::default();
+        let trace = quick_trace!([(L, "a", 1, 2, 3., 0, 0), (A, "home", 2, 10, 0, 0)]);
+        let battery_spec = BatterySpecBuilder::new()
+            .capacity(2.0 / 3600.) // 2 kWs
+            .full()
+            .consumption_rate(1.0 / 3.6) // 1 kWs/m
+            .fuel_consumption_rate(0.1) // 0.0001 l/m
+            .build();
+        let trigger_spec = TriggerSpec::empty();
+        let charge_act = ActivitySpec::new(None, vec!["home".to_string()], 1.0, None, None);
+        let en_route_spec = EnRouteSpec::new(None, 1.0, None, None);
+        let agent_config: AgentConfig = AgentConfig {
+            pid: "a",
+            battery: Some(&battery_spec),
+            trigger: Some(&trigger_spec),
+            en_route: Some(&en_route_spec),
+            activities: vec![&charge_act],
+        };
+        let record = simulate(
+            "A",
+            &trace,
+            vec![1],
+            &agent_config,
+            ActivityChargingPlanner::new(vec![&charge_act]),
+            &config,
+            false,
+        );
+        // battery is depleted after 2m, then uses fuel, and is recharged at home (no en-route)
+        let plan_record = record.to_record();
+        assert_eq!(plan_record.number_enroute, 0);
+        assert_eq!(plan_record.number_activity, 1);
+        assert_eq!(plan_record.electric_distance, 0.002);
+        assert_eq!(plan_record.fuel_distance, 0.001);
+        assert!((plan_record.fuel - 0.0001).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_sim_soc() {
+        let config = Config::default();
+        let trace = quick_trace!([(L, "a", 1, 2, 1., 0, 0), (A, "home", 2, 10, 0, 0)]);
+        let battery_spec = BatterySpecBuilder::new()
+            .capacity(3.0 / 3600.) // 3 kWs
+            .full()
+            .consumption_rate(1.0 / 3.6)
+            .build();
+        let trigger_spec = TriggerSpec::empty();
+        let charge_act = ActivitySpec::new(None, vec!["home".to_string()], 1.0, None, None);
+        let en_route_spec = EnRouteSpec::new(None, 1.0, None, None);
+        let agent_config: AgentConfig = AgentConfig {
+            pid: "a",
+            battery: Some(&battery_spec),
+            trigger: Some(&trigger_spec),
+            en_route: Some(&en_route_spec),
+            activities: vec![&charge_act],
+        };
+        let record = simulate(
+            "A",
+            &trace,
+            vec![1],
+            &agent_config,
+            ActivityChargingPlanner::new(vec![&charge_act]),
+            &config,
+            true,
+        );
+        let soc = record
+            .soc_records()
+            .map(|r| (r.day, r.time, r.soc * 3600.))
+            .collect::<Vec<(usize, u32, f32)>>();
+        assert_eq!(soc, vec![(1, 2, 2.), (1, 10, 3.)]);
+        let plan_record = record.to_record();
+        assert_eq!(plan_record.min_soc, Some(2. / 3600.));
+        assert_eq!(plan_record.max_soc, Some(3. / 3600.));
+    }
+
+    #[test]
+    fn test_split_discharge() {
+        assert_eq!(
+            split_discharge(0, 10, &[[2, 4], [3, 6]]),
+            vec![(0, 2, false), (2, 4, true), (4, 6, true), (6, 10, false)]
+        );
+        assert_eq!(
+            split_discharge(DAY - 10, DAY + 10, &[[0, 5]]),
+            vec![
+                (DAY - 10, DAY, false),
+                (DAY, DAY + 5, true),
+                (DAY + 5, DAY + 10, false)
+            ]
+        );
+        assert_eq!(split_discharge(0, 10, &[]), vec![(0, 10, false)]);
+    }
+
+    #[test]
+    fn test_sim_discharge() {
+        let config = Config::default();
+        let trace = quick_trace!([(L, "a", 1, 2, 1., 0, 0), (A, "home", 2, 10, 0, 0)]);
+        let battery_spec = BatterySpecBuilder::new()
+            .capacity(3.0 / 3600.) // 3 kWs
+            .full()
+            .consumption_rate(1.0 / 3.6)
+            .build();
+        let trigger_spec = TriggerSpec::empty();
+        let mut charge_act = ActivitySpec::new(
+            Some("home".to_string()),
+            vec!["home".to_string()],
+            1.0,
+            None,
+            None,
+        );
+        charge_act.discharge = Some(DischargeSpec {
+            rate: 1.0,
+            floor: 0.0,
+            windows: vec![[5, 8]],
+        });
+        let en_route_spec = EnRouteSpec::new(None, 1.0, None, None);
+        let agent_config: AgentConfig = AgentConfig {
+            pid: "a",
+            battery: Some(&battery_spec),
+            trigger: Some(&trigger_spec),
+            en_route: Some(&en_route_spec),
+            activities: vec![&charge_act],
+        };
+        let simulate = |access: &ChargerAccess| {
+            simulate_with_access(
+                "A",
+                &trace,
+                vec![1],
+                &agent_config,
+                ActivityChargingPlanner::new(vec![&charge_act]),
+                &config,
+                false,
+                access,
+            )
+        };
+        let home = Some("home".to_string());
+        // charge to full, discharge keeping enough to drive link a, then recharge
+        let event_a = Event::activity("A", home.clone(), 1., 1, (2, 3), "home", (0.0, 0.0));
+        let event_b = Event::discharge("A", home.clone(), -2., 1, (5, 7), "home", (0.0, 0.0));
+        let event_c = Event::activity("A", home.clone(), 2., 1, (8, 10), "home", (0.0, 0.0));
+        let record = simulate(&ChargerAccess::unrestricted());
+        assert_eq!(
+            record.days().flatten().collect::<Vec<&Event>>(),
+            vec![&event_a, &event_b, &event_c]
+        );
+        assert!(record.has_discharge());
+        // flexibility is the charge above the floor when plugged in during the window
+        assert_eq!(
+            record
+                .days()
+                .flat_map(|day| day.flexibility.iter())
+                .collect::<Vec<&Flexibility>>(),
+            vec![&Flexibility {
+                start_time: 5,
+                end_time: 8,
+                energy: 2.,
+                rate: 1.,
+            }]
+        );
+        let record = simulate(&ChargerAccess::unrestricted().without_discharge());
+        assert_eq!(
+            record.days().flatten().collect::<Vec<&Event>>(),
+            vec![&event_a]
+        );
+        assert!(record.days().all(|day| day.flexibility.is_empty()));
+    }
+
+    #[test]
+    fn test_sim_smart_charging() {
+        let config = Config::default();
+        let trace = quick_trace!([(L, "a", 1, 2, 2., 0, 0), (A, "home", 2, 10, 0, 0)]);
+        let battery_spec = BatterySpecBuilder::new()
+            .capacity(3.0 / 3600.) // 3 kWs
+            .full()
+            .consumption_rate(1.0 / 3.6)
+            .build();
+        let trigger_spec = TriggerSpec::empty();
+        let mut charge_act = ActivitySpec::new(
+            Some("home".to_string()),
+            vec!["home".to_string()],
+            1.0,
+            None,
+            None,
+        );
+        charge_act.smart = Some(SmartChargingSpec::Delayed { start: 6 });
+        let en_route_spec = EnRouteSpec::new(None, 1.0, None, None);
+        let agent_config: AgentConfig = AgentConfig {
+            pid: "a",
+            battery: Some(&battery_spec),
+            trigger: Some(&trigger_spec),
+            en_route: Some(&en_route_spec),
+            activities: vec![&charge_act],
+        };
+        // charge is delayed until 6 seconds after midnight
+        let event = Event::activity(
+            "A",
+            Some("home".to_string()),
+            2.,
+            1,
+            (6, 8),
+            "home",
+            (0.0, 0.0),
+        );
+        assert_eq!(
+            simulate(
+                "A",
+                &trace,
+                vec![1],
+                &agent_config,
+                ActivityChargingPlanner::new(vec![&charge_act]),
+                &config,
+                false,
+            )
+            .days()
+            .flatten()
+            .collect::<Vec<&Event>>(),
+            vec![&event]
+        );
+    }
+
+    #[test]
+    fn test_sim_charging_through_temperature_change() {
+        // warm until 01:00, then cold
+        let config = Config {
+            temperature: Some(TemperatureProfile::Hourly {
+                hourly: (0..24)
+                    .map(|hour| if hour == 0 { 20.0 } else { 0.0 })
+                    .collect(),
+            }),
+            ..Default::default()
+        };
+        let trace = quick_trace!([(L, "a", 1, 3599, 2., 0, 0), (A, "home", 3599, 7200, 0, 0)]);
+        let mut battery_spec = BatterySpecBuilder::new()
+            .capacity(3.0 / 3600.) // 3 kWs
+            .full()
+            .consumption_rate(1.0 / 3.6)
+            .build();
+        battery_spec.temperature_sensitivity = Some(TemperatureSensitivity {
+            reference: 20.0,
+            heating: 0.0,
+            cooling: 0.0,
+            charging: 0.025,
+        });
+        let trigger_spec = TriggerSpec::empty();
+        let charge_act = ActivitySpec::new(
+            Some("home".to_string()),
+            vec!["home".to_string()],
+            1.0,
+            None,
+            None,
+        );
+        let en_route_spec = EnRouteSpec::new(None, 1.0, None, None);
+        let agent_config: AgentConfig = AgentConfig {
+            pid: "a",
+            battery: Some(&battery_spec),
+            trigger: Some(&trigger_spec),
+            en_route: Some(&en_route_spec),
+            activities: vec![&charge_act],
+        };
+        let record = simulate(
+            "A",
+            &trace,
+            vec![1],
+            &agent_config,
+            ActivityChargingPlanner::new(vec![&charge_act]),
+            &config,
+            false,
+        );
+        // 1 kWs at full rate before 01:00, then 1 kWs at half rate
+        let event = Event::activity(
+            "A",
+            Some("home".to_string()),
+            2.,
+            1,
+            (3599, 3602),
+            "home",
+            (0.0, 0.0),
+        );
+        assert_eq!(
+            record.days().flatten().collect::<Vec<&Event>>(),
+            vec![&event]
+        );
+    }
+
+    #[test]
+    fn test_sim_smart_charging_when_cold() {
+        // warm until 01:00, then cold
+        let config = Config {
+            temperature: Some(TemperatureProfile::Hourly {
+                hourly: (0..24)
+                    .map(|hour| if hour == 0 { 20.0 } else { 0.0 })
+                    .collect(),
+            }),
+            ..Default::default()
+        };
+        let trace = quick_trace!([(L, "a", 1, 2, 2., 0, 0), (A, "home", 2, 7200, 0, 0)]);
+        let mut battery_spec = BatterySpecBuilder::new()
+            .capacity(3.0 / 3600.) // 3 kWs
+            .full()
+            .consumption_rate(1.0 / 3.6)
+            .build();
+        battery_spec.temperature_sensitivity = Some(TemperatureSensitivity {
+            reference: 20.0,
+            heating: 0.0,
+            cooling: 0.0,
+            charging: 0.025,
+        });
+        let trigger_spec = TriggerSpec::empty();
+        let mut charge_act = ActivitySpec::new(
+            Some("home".to_string()),
+            vec!["home".to_string()],
+            1.0,
+            None,
+            None,
+        );
+        charge_act.smart = Some(SmartChargingSpec::Delayed { start: 3600 });
+        let en_route_spec = EnRouteSpec::new(None, 1.0, None, None);
+        let agent_config: AgentConfig = AgentConfig {
+            pid: "a",
+            battery: Some(&battery_spec),
+            trigger: Some(&trigger_spec),
+            en_route: Some(&en_route_spec),
+            activities: vec![&charge_act],
+        };
+        let record = simulate(
+            "A",
+            &trace,
+            vec![1],
+            &agent_config,
+            ActivityChargingPlanner::new(vec![&charge_act]),
+            &config,
+            false,
+        );
+        // the delayed session charges at half rate, still delivering the full deficit
+        let event = Event::activity(
+            "A",
+            Some("home".to_string()),
+            2.,
+            1,
+            (3600, 3604),
+            "home",
+            (0.0, 0.0),
+        );
+        assert_eq!(
+            record.days().flatten().collect::<Vec<&Event>>(),
+            vec![&event]
+        );
+    }
+
+    #[test]
+    fn test_sim_vehicle_drivers_with_smart_charging() {
+        let config = Config::default();
+        let person = |plan: Trace| tracer::Person {
+            attributes: HashMap::from([("hid".to_string(), "h".to_string())]),
+            trace: plan,
+        };
+        // a drives home, then b drives to the shop, both charge the vehicle on arrival
+        let population = tracer::Population {
+            people: BTreeMap::from([
+                (
+                    "a".to_string(),
+                    person(quick_trace!([
+                        (L, "a1", 100, 200, 1., 0, 0),
+                        (A, "home", 200, 86400, 0, 0)
+                    ])),
+                ),
+                (
+                    "b".to_string(),
+                    person(quick_trace!([
+                        (A, "home", 0, 1200, 0, 0),
+                        (L, "b1", 1200, 1300, 1., 0, 0),
+                        (A, "shop", 1300, 86400, 0, 0)
+                    ])),
+                ),
+            ]),
+        };
+        let (vehicles, drivers) = population.into_vehicles("hid").unwrap();
+        let battery_spec = BatterySpecBuilder::new()
+            .capacity(3.0 / 3600.) // 3 kWs
+            .full()
+            .consumption_rate(1.0 / 3.6)
+            .build();
+        let trigger_spec = TriggerSpec::empty();
+        let mut charge_act = ActivitySpec::new(
+            None,
+            vec!["home".to_string(), "shop".to_string()],
+            1.0,
+            None,
+            None,
+        );
+        charge_act.smart = Some(SmartChargingSpec::Delayed { start: 3600 });
+        let en_route_spec = EnRouteSpec::new(None, 1.0, None, None);
+        let agent_config: AgentConfig = AgentConfig {
+            pid: "h",
+            battery: Some(&battery_spec),
+            trigger: Some(&trigger_spec),
+            en_route: Some(&en_route_spec),
+            activities: vec![&charge_act],
+        };
+        let mut record = simulate(
+            "h",
+            &vehicles.people["h"].trace,
+            vec![1, 3],
+            &agent_config,
+            ActivityChargingPlanner::new(vec![&charge_act]),
+            &config,
+            false,
+        );
+        record.assign_drivers(|start_time, component| {
+            drivers.driver("h", start_time, component).map(String::from)
+        });
+        // charging is delayed (or brought forward to finish before departure) within each stay
+        assert_eq!(
+            record
+                .days()
+                .flatten()
+                .map(|event| (event.start_time, event.driver.as_deref()))
+                .collect::<Vec<(u32, Option<&str>)>>(),
+            vec![(1199, Some("a")), (3600, Some("b"))]
+        );
+    }
+
+    #[test]
+    fn test_plan_ahead_includes_parked_loads() {
+        let trace = quick_trace!([
+            (L, "a", 1, 2, 5., 0, 0),
+            (A, "shop", 2, 12, 0, 0),
+            (L, "b", 12, 13, 1., 0, 0),
+            (A, "home", 13, 20, 0, 0)
+        ]);
+        let mut battery_spec = BatterySpecBuilder::new()
+            .capacity(10.0 / 3600.) // 10 kWs
+            .consumption_rate(1.0 / 3.6)
+            .build();
+        battery_spec.auxiliary = Some(AuxiliaryLoads {
+            standby: 0.1,
+            ..Default::default()
+        });
+        let battery = BatteryState::new(&battery_spec, &TriggerSpec::empty());
+        // links a and b and 1 kWs of standby drain at the shop
+        assert!((plan_ahead(&trace, &[3], 0, &battery) - 7.0).abs() < 1e-5);
+        // wrapping around from home
+        assert!((plan_ahead(&trace, &[3], 4, &battery) - 7.0).abs() < 1e-5);
+    }
 }
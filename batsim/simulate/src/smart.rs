@@ -0,0 +1,105 @@
+use configuration::groups::activity::SmartChargingSpec;
+
+const DAY: u32 = 24 * 60 * 60;
+
+/// Schedule charging of given duration (seconds) within a charging window (trace seconds),
+/// returning time ordered charging sessions. Charging is completed within the window where
+/// possible, otherwise the whole window is used.
+pub fn schedule(spec: &SmartChargingSpec, start: u32, end: u32, duration: u32) -> Vec<(u32, u32)> {
+    let duration = duration.min(end - start);
+    if duration == 0 {
+        return vec![];
+    }
+    match spec {
+        SmartChargingSpec::Delayed { start: delay } => {
+            let mut delayed = start - start % DAY + delay;
+            if delayed < start {
+                delayed += DAY;
+            }
+            // start late enough to finish before departure
+            let session_start = delayed.min(end - duration);
+            vec![(session_start, session_start + duration)]
+        }
+        SmartChargingSpec::Cheapest { prices } => cheapest(prices, start, end, duration),
+    }
+}
+
+/// Fill the cheapest price slots first (earliest first for equal prices), then merge adjacent
+/// slots into sessions
+fn cheapest(prices: &[f32], start: u32, end: u32, duration: u32) -> Vec<(u32, u32)> {
+    let slot = DAY / prices.len() as u32;
+    let mut slots = Vec::new();
+    let mut time = start;
+    while time < end {
+        let next = ((time / slot + 1) * slot).min(end);
+        slots.push((prices[((time % DAY) / slot) as usize], time, next));
+        time = next;
+    }
+    slots.sort_by(|(a, a_start, _), (b, b_start, _)| a.total_cmp(b).then(a_start.cmp(b_start)));
+
+    let mut remaining = duration;
+    let mut chosen = Vec::new();
+    for (_, slot_start, slot_end) in slots {
+        if remaining == 0 {
+            break;
+        }
+        let used = remaining.min(slot_end - slot_start);
+        chosen.push((slot_start, slot_start + used));
+        remaining -= used;
+    }
+    chosen.sort();
+
+    let mut sessions: Vec<(u32, u32)> = Vec::new();
+    for (slot_start, slot_end) in chosen {
+        match sessions.last_mut() {
+            Some((_, end)) if *end == slot_start => *end = slot_end,
+            _ => sessions.push((slot_start, slot_end)),
+        }
+    }
+    sessions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delayed() {
+        let spec = SmartChargingSpec::Delayed { start: 3600 };
+        // evening arrival, charging delayed until 01:00 the next day
+        assert_eq!(
+            schedule(&spec, 64800, DAY + 28800, 7200),
+            vec![(DAY + 3600, DAY + 10800)]
+        );
+        // brought forward to finish before departure
+        assert_eq!(
+            schedule(&spec, 64800, DAY + 7200, 7200),
+            vec![(DAY, DAY + 7200)]
+        );
+        // the whole stay is used if it is too short
+        assert_eq!(schedule(&spec, 0, 100, 200), vec![(0, 100)]);
+        assert!(schedule(&spec, 0, 100, 0).is_empty());
+    }
+
+    #[test]
+    fn test_cheapest() {
+        // expensive 00:00-06:00, cheap 06:00-12:00, expensive afternoon, mid evening
+        let spec = SmartChargingSpec::Cheapest {
+            prices: vec![3.0, 1.0, 3.0, 2.0],
+        };
+        assert_eq!(schedule(&spec, 0, DAY, 3600), vec![(21600, 25200)]);
+        // cheap slot is filled, then the evening slot, in two sessions
+        assert_eq!(
+            schedule(&spec, 18000, DAY, 25200),
+            vec![(21600, 43200), (64800, 68400)]
+        );
+        // adjacent slots are merged, equal prices fill the earliest first
+        let spec = SmartChargingSpec::Cheapest {
+            prices: vec![1.0, 1.0],
+        };
+        assert_eq!(
+            schedule(&spec, 36000, DAY + 3600, 54000),
+            vec![(36000, 90000)]
+        );
+    }
+}
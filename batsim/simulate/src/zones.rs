@@ -0,0 +1,113 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::events::{ChargeType, Event};
+
+/// Charge totals for a single zone, events outside of all zones are reported without a zone
+#[derive(Serialize, Default, Debug, PartialEq)]
+pub struct ZoneRecord<'a> {
+    pub zone: Option<&'a str>,
+    pub number_enroute: usize,
+    pub number_activity: usize,
+    pub number_charges: usize,
+    #[serde(rename = "total_charge_(kWh)")]
+    pub total_charge: f32,
+    #[serde(rename = "total_enroute_(kWh)")]
+    pub total_enroute: f32,
+    #[serde(rename = "total_activity_(kWh)")]
+    pub total_activity: f32,
+    pub number_discharge: usize,
+    #[serde(rename = "total_discharge_(kWh)")]
+    pub total_discharge: f32,
+}
+
+/// Aggregates charge events into totals for each zone
+#[derive(Default, Debug)]
+pub struct ZoneHandler<'a> {
+    zones: BTreeMap<Option<&'a str>, ZoneRecord<'a>>,
+}
+
+impl<'a> ZoneHandler<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, event: &'a Event) {
+        let zone = event.zone.as_deref();
+        let record = self.zones.entry(zone).or_insert_with(|| ZoneRecord {
+            zone,
+            ..Default::default()
+        });
+        // convert from kWs to kWh
+        let charge = event.charge / 3600.0;
+        match event.charge_type {
+            ChargeType::EnRoute => {
+                record.number_enroute += 1;
+                record.total_enroute += charge;
+            }
+            ChargeType::Activity => {
+                record.number_activity += 1;
+                record.total_activity += charge;
+            }
+            ChargeType::Discharge => {
+                record.number_discharge += 1;
+                record.total_discharge += charge;
+                return;
+            }
+        }
+        record.number_charges += 1;
+        record.total_charge += charge;
+    }
+
+    /// Serialisable zone records, ordered by zone id
+    pub fn records(&self) -> impl Iterator<Item = &ZoneRecord<'a>> {
+        self.zones.values()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zone_totals() {
+        let mut handler = ZoneHandler::new();
+        let mut event_a = Event::en_route("a", None, 3600., 1, (0, 10), "a", (0., 0.));
+        event_a.zone = Some("x".to_string());
+        let mut event_b = Event::activity("b", None, 7200., 1, (0, 10), "home", (0., 0.));
+        event_b.zone = Some("x".to_string());
+        let event_c = Event::activity("c", None, 3600., 1, (0, 10), "home", (0., 0.));
+        let event_d = Event::discharge("c", None, -1800., 1, (10, 20), "home", (0., 0.));
+        handler.add(&event_a);
+        handler.add(&event_b);
+        handler.add(&event_c);
+        handler.add(&event_d);
+        let records = handler.records().collect::<Vec<&ZoneRecord>>();
+        assert_eq!(
+            records,
+            vec![
+                &ZoneRecord {
+                    zone: None,
+                    number_enroute: 0,
+                    number_activity: 1,
+                    number_charges: 1,
+                    total_charge: 1.,
+                    total_enroute: 0.,
+                    total_activity: 1.,
+                    number_discharge: 1,
+                    total_discharge: -0.5,
+                },
+                &ZoneRecord {
+                    zone: Some("x"),
+                    number_enroute: 1,
+                    number_activity: 1,
+                    number_charges: 2,
+                    total_charge: 3.,
+                    total_enroute: 1.,
+                    total_activity: 2.,
+                    ..Default::default()
+                },
+            ]
+        );
+    }
+}
@@ -87,7 +87,7 @@ impl<'a> TraceHandler<'a> {
                         Some(lid) => lid,
                         None => from_utf8(&e.lid)?.to_string(),
                     };
-                    let (_, node) = network
+                    let link = network
                         .links
                         .get(&lid)
                         .context(format!("failed to find link '{}' in network", &lid))?;
@@ -95,7 +95,7 @@ impl<'a> TraceHandler<'a> {
                         start_time,
                         end_time,
                         act: act_type,
-                        node: *node,
+                        node: link.to,
                     }))
                 }
                 Ok(())
@@ -113,7 +113,7 @@ impl<'a> TraceHandler<'a> {
                     if let Some(start_time) = self.link_entries.remove(&pid) {
                         let end_time = e.time;
                         let lid = from_utf8(&e.link)?.to_string();
-                        let (distance, node) = network
+                        let link = network
                             .links
                             .get(&lid)
                             .context(format!("failed to find link '{}' in network", &lid))?;
@@ -121,8 +121,9 @@ impl<'a> TraceHandler<'a> {
                             start_time,
                             end_time,
                             lid,
-                            distance: *distance,
-                            node: *node,
+                            distance: link.length,
+                            node: link.to,
+                            rise: link.rise,
                         }))
                     }
                 }
@@ -135,7 +136,7 @@ impl<'a> TraceHandler<'a> {
                     if let Some(start_time) = self.link_entries.remove(&pid) {
                         let end_time = e.time;
                         let lid = from_utf8(&e.link)?.to_string();
-                        let (distance, node) = network
+                        let link = network
                             .links
                             .get(&lid)
                             .context(format!("failed to find link '{}' in network", &lid))?;
@@ -143,8 +144,9 @@ impl<'a> TraceHandler<'a> {
                             start_time,
                             end_time,
                             lid,
-                            distance: *distance * 0.5,
-                            node: *node,
+                            distance: link.length * 0.5,
+                            node: link.to,
+                            rise: link.rise * 0.5,
                         }))
                     }
                 }
@@ -167,12 +169,12 @@ impl<'a> TraceHandler<'a> {
                     Some(lid) => lid,
                     None => panic!("Failed to find activity link when finalising activity: person {}, {} at {}", pid, act_type, start_time),
                 };
-                let (_, node) = network.links.get(lid).unwrap();
+                let link = network.links.get(lid).unwrap();
                 person.trace.add(Component::ActivityType(Activity {
                     start_time: *start_time,
                     end_time: 24 * 60 * 60,
                     act: act_type,
-                    node: *node,
+                    node: link.to,
                 }))
             }
         }
@@ -296,6 +298,9 @@ pub struct Link {
     pub lid: String,
     pub distance: f32,
     pub node: Node,
+    /// Change in elevation (m) over the distance travelled, json traces without it are flat
+    #[serde(default)]
+    pub rise: f32,
 }
 impl Link {
     pub fn duration(&self) -> u32 {
@@ -312,7 +317,7 @@ mod tests {
 
     use crate::{
         events::{ActEnd, ActStart, EnteredLink, LeftLink, VehicleLeavesTraffic},
-        Person,
+        NetworkLink, Person,
     };
 
     use super::*;
@@ -320,8 +325,24 @@ mod tests {
     fn network() -> Network {
         Network {
             links: HashMap::from_iter([
-                ("a".to_string(), (1.0, (0.0, 0.0))),
-                ("b".to_string(), (1.0, (0.0, 0.0))),
+                (
+                    "a".to_string(),
+                    NetworkLink {
+                        length: 1.0,
+                        from: (0.0, 0.0),
+                        to: (0.0, 0.0),
+                        rise: 0.0,
+                    },
+                ),
+                (
+                    "b".to_string(),
+                    NetworkLink {
+                        length: 1.0,
+                        from: (0.0, 0.0),
+                        to: (0.0, 0.0),
+                        rise: 0.0,
+                    },
+                ),
             ]),
         }
     }
@@ -464,7 +485,8 @@ mod tests {
                 end_time: 2,
                 lid: "a".to_string(),
                 distance: 1.0,
-                node: (0.0, 0.0)
+                node: (0.0, 0.0),
+                rise: 0.0,
             })
         );
         assert_eq!(
@@ -474,7 +496,8 @@ mod tests {
                 end_time: 3,
                 lid: "b".to_string(),
                 distance: 0.5,
-                node: (0.0, 0.0)
+                node: (0.0, 0.0),
+                rise: 0.0,
             })
         );
         assert_eq!(
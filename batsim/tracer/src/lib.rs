@@ -2,8 +2,10 @@ pub mod events;
 pub mod handler;
 pub mod network;
 pub mod population;
+pub mod vehicles;
 
 pub use events::{MATSimEvent, MATSimEventsReader};
 pub use handler::{Activity, Component, Link, Trace, TraceHandler};
-pub use network::{Network, Node};
+pub use network::{Elevations, Network, NetworkLink, Node};
 pub use population::{Person, Population};
+pub use vehicles::Drivers;
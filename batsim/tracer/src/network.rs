@@ -1,10 +1,20 @@
 use anyhow::{Context, Result};
 use quick_xml::{events::Event, Reader};
-use std::{collections::HashMap, io::BufRead, str};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, Read},
+    path::Path,
+    str,
+};
 use thiserror::Error;
 
 pub type Node = (f32, f32);
 
+/// Node elevations (m) keyed by node id
+pub type Elevations = HashMap<String, f32>;
+
 /// TracerError enumerates all possible errors.
 #[derive(Error, Debug)]
 pub enum TracerError {
@@ -13,9 +23,43 @@ pub enum TracerError {
     NetworkXMLError(quick_xml::Error),
 }
 
-/// A network stuct containing map of all link lengths (generally assumed in m)
+/// Network link length (generally assumed in m) and from/to node locations
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NetworkLink {
+    pub length: f32,
+    pub from: Node,
+    pub to: Node,
+    /// Change in elevation (m) from the from node to the to node, zero if either is unknown
+    pub rise: f32,
+}
+
+#[derive(Deserialize)]
+struct ElevationRecord {
+    id: String,
+    z: f32,
+}
+
+/// Load node elevations from a csv with node `id` and elevation `z` (m) columns
+pub fn load_elevations(path: &Path) -> Result<Elevations> {
+    let file = File::open(path).context(format!("unable to open '{}'", path.display()))?;
+    read_elevations(file).context(format!(
+        "failed to read node elevations '{}'",
+        path.display()
+    ))
+}
+
+pub fn read_elevations<R: Read>(reader: R) -> Result<Elevations> {
+    let mut elevations = Elevations::new();
+    for record in csv::Reader::from_reader(reader).deserialize() {
+        let record: ElevationRecord = record?;
+        elevations.insert(record.id, record.z);
+    }
+    Ok(elevations)
+}
+
+/// A network stuct containing map of all links
 pub struct Network {
-    pub links: HashMap<String, (f32, Node)>, // len, x, y
+    pub links: HashMap<String, NetworkLink>,
 }
 
 impl Network {
@@ -26,8 +70,19 @@ impl Network {
     /// * `path` - Path to MATSim network xml
     ///
     pub fn from_xml(reader: &mut Reader<Box<dyn BufRead>>) -> Result<Self> {
-        let mut links = HashMap::<String, (f32, Node)>::new();
+        Self::from_xml_with_elevations(reader, &Elevations::new())
+    }
+
+    /// Return a network (see `from_xml`) with link rise from node elevations. Elevations are
+    /// read from node 'z' attributes, given elevations (such as from `load_elevations`) take
+    /// precedence.
+    pub fn from_xml_with_elevations(
+        reader: &mut Reader<Box<dyn BufRead>>,
+        elevations: &Elevations,
+    ) -> Result<Self> {
+        let mut links = HashMap::<String, NetworkLink>::new();
         let mut nodes: HashMap<String, Node> = HashMap::<String, Node>::new();
+        let mut heights: Elevations = Elevations::new();
         let mut buf = Vec::new();
 
         // The `Reader` does not implement `Iterator` because it outputs borrowed data (`Cow`s)
@@ -50,6 +105,7 @@ impl Network {
                     let mut nid = String::new();
                     let mut x: f32 = f32::NAN;
                     let mut y: f32 = f32::NAN;
+                    let mut z: Option<f32> = None;
 
                     let mut attributes = e.attributes();
                     attributes.with_checks(false);
@@ -91,16 +147,35 @@ impl Network {
                                         reader.buffer_position()
                                     ))?;
                             }
+                            b"z" => {
+                                z = Some(
+                                    str::from_utf8(attribute.value.as_ref())
+                                        .context(format!(
+                                            "error reading node 'z' as str at position {}",
+                                            reader.buffer_position()
+                                        ))?
+                                        .to_string()
+                                        .parse::<f32>()
+                                        .context(format!(
+                                            "error parsing node 'z' as float at position {}",
+                                            reader.buffer_position()
+                                        ))?,
+                                );
+                            }
                             _ => (),
                         }
                     }
                     // add to map
+                    if let Some(z) = z {
+                        heights.insert(nid.clone(), z);
+                    }
                     nodes.insert(nid, (x, y));
                 }
 
                 Ok(Event::Start(ref e)) if e.name().into_inner() == b"link" => {
                     let mut lid = String::new();
                     let mut length: f32 = f32::NAN;
+                    let mut from = String::new();
                     let mut to = String::new();
 
                     let mut attributes = e.attributes();
@@ -130,6 +205,14 @@ impl Network {
                                         reader.buffer_position()
                                     ))?;
                             }
+                            b"from" => {
+                                from = str::from_utf8(attribute.value.as_ref())
+                                    .context(format!(
+                                        "error reading link 'from' as str at position {}",
+                                        reader.buffer_position()
+                                    ))?
+                                    .to_string();
+                            }
                             b"to" => {
                                 to = str::from_utf8(attribute.value.as_ref())
                                     .context(format!(
@@ -142,10 +225,26 @@ impl Network {
                         }
                     }
                     // add to map
-                    let node: Node = *nodes
+                    let elevation = |nid: &str| elevations.get(nid).or(heights.get(nid)).copied();
+                    let rise = match (elevation(&from), elevation(&to)) {
+                        (Some(from), Some(to)) => to - from,
+                        _ => 0.0,
+                    };
+                    let from: Node = *nodes
+                        .get(&from)
+                        .context(format!("error finding node id '{}'", from))?;
+                    let to: Node = *nodes
                         .get(&to)
                         .context(format!("error finding node id '{}'", to))?;
-                    links.insert(lid, (length, node));
+                    links.insert(
+                        lid,
+                        NetworkLink {
+                            length,
+                            from,
+                            to,
+                            rise,
+                        },
+                    );
                 }
                 _ => (),
             }
@@ -169,15 +268,105 @@ mod tests {
         let mut network_reader = xml::reader(&path).unwrap();
         let network = Network::from_xml(&mut network_reader);
         let expected_links = HashMap::from([
-            (std::string::String::from("1-2"), (1000.0, (100.0, 0.0))),
-            (std::string::String::from("1-5"), (20000.0, (0.0, 10000.0))),
-            (std::string::String::from("2-1"), (1000.0, (0.0, 0.0))),
-            (std::string::String::from("2-3"), (20000.0, (10000.0, 0.0))),
-            (std::string::String::from("3-2"), (20000.0, (100.0, 0.0))),
-            (std::string::String::from("3-4"), (1000.0, (10100.0, 0.0))),
-            (std::string::String::from("4-3"), (1000.0, (10000.0, 0.0))),
-            (std::string::String::from("5-1"), (20000.0, (0.0, 0.0))),
+            (
+                std::string::String::from("1-2"),
+                NetworkLink {
+                    length: 1000.0,
+                    from: (0.0, 0.0),
+                    to: (100.0, 0.0),
+                    rise: 0.0,
+                },
+            ),
+            (
+                std::string::String::from("1-5"),
+                NetworkLink {
+                    length: 20000.0,
+                    from: (0.0, 0.0),
+                    to: (0.0, 10000.0),
+                    rise: 0.0,
+                },
+            ),
+            (
+                std::string::String::from("2-1"),
+                NetworkLink {
+                    length: 1000.0,
+                    from: (100.0, 0.0),
+                    to: (0.0, 0.0),
+                    rise: 0.0,
+                },
+            ),
+            (
+                std::string::String::from("2-3"),
+                NetworkLink {
+                    length: 20000.0,
+                    from: (100.0, 0.0),
+                    to: (10000.0, 0.0),
+                    rise: 0.0,
+                },
+            ),
+            (
+                std::string::String::from("3-2"),
+                NetworkLink {
+                    length: 20000.0,
+                    from: (10000.0, 0.0),
+                    to: (100.0, 0.0),
+                    rise: 0.0,
+                },
+            ),
+            (
+                std::string::String::from("3-4"),
+                NetworkLink {
+                    length: 1000.0,
+                    from: (10000.0, 0.0),
+                    to: (10100.0, 0.0),
+                    rise: 0.0,
+                },
+            ),
+            (
+                std::string::String::from("4-3"),
+                NetworkLink {
+                    length: 1000.0,
+                    from: (10100.0, 0.0),
+                    to: (10000.0, 0.0),
+                    rise: 0.0,
+                },
+            ),
+            (
+                std::string::String::from("5-1"),
+                NetworkLink {
+                    length: 20000.0,
+                    from: (0.0, 10000.0),
+                    to: (0.0, 0.0),
+                    rise: 0.0,
+                },
+            ),
         ]);
         assert_eq!(network.unwrap().links, expected_links);
     }
+
+    #[test]
+    fn network_reads_elevations() {
+        let xml = r#"<network>
+<nodes>
+<node id="1" x="0.0" y="0.0" z="10.0"></node>
+<node id="2" x="100.0" y="0.0" z="30.0"></node>
+<node id="3" x="200.0" y="0.0"></node>
+</nodes>
+<links>
+<link id="1-2" from="1" to="2" length="100.0"></link>
+<link id="2-3" from="2" to="3" length="100.0"></link>
+</links>
+</network>"#;
+        let reader =
+            || -> Reader<Box<dyn BufRead>> { Reader::from_reader(Box::new(xml.as_bytes())) };
+        let network = Network::from_xml(&mut reader()).unwrap();
+        assert_eq!(network.links["1-2"].rise, 20.0);
+        // node 3 has no elevation
+        assert_eq!(network.links["2-3"].rise, 0.0);
+
+        let elevations = read_elevations("id,z\n2,0.0\n3,-10.0\n".as_bytes()).unwrap();
+        let network = Network::from_xml_with_elevations(&mut reader(), &elevations).unwrap();
+        assert_eq!(network.links["1-2"].rise, -10.0);
+        assert_eq!(network.links["2-3"].rise, -10.0);
+    }
 }
@@ -1,11 +1,11 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use itertools::Itertools;
 use quick_xml::{events::Event, Reader};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::{BTreeMap, HashMap},
     fs::File,
-    io::{BufRead, BufReader, BufWriter},
+    io::{BufRead, BufReader, BufWriter, Read, Write},
     ops::Deref,
     str::from_utf8,
 };
@@ -15,6 +15,10 @@ use super::{Component, Trace};
 
 pub type PersonAttributes = HashMap<String, String>;
 
+// header of binary traces, the version must be bumped whenever the trace structs change
+const TRACE_MAGIC: &[u8; 8] = b"BATSIMTR";
+const TRACE_VERSION: u32 = 1;
+
 ///Person struct to hold agent info
 #[derive(Default, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Person {
@@ -102,23 +106,33 @@ impl Population {
     pub fn len(&self) -> usize {
         self.people.len()
     }
+    /// Write traces as json or binary, binary traces start with a format header
     pub fn serialise(&self, out_file: File, json: bool) -> Result<()> {
-        let writer = BufWriter::new(out_file);
+        let mut writer = BufWriter::new(out_file);
         if json {
             // human readable json
             serde_json::to_writer(writer, self)
                 .context("failed to serialise json format traces")?;
         } else {
+            writer.write_all(TRACE_MAGIC)?;
+            writer.write_all(&TRACE_VERSION.to_le_bytes())?;
             bincode::serialize_into(writer, self)
                 .context("failed to serialise binary format traces")?;
         }
         Ok(())
     }
-    pub fn deserialise(reader: BufReader<File>, json: bool) -> Result<Self> {
+    pub fn deserialise(mut reader: BufReader<File>, json: bool) -> Result<Self> {
         if json {
             serde_json::from_reader(reader)
                 .context("unable to json deserialise traces (check files are json)")
         } else {
+            let mut header = [0; 12];
+            if reader.read_exact(&mut header).is_err()
+                || &header[..8] != TRACE_MAGIC
+                || header[8..] != TRACE_VERSION.to_le_bytes()
+            {
+                bail!("binary traces are not in the current format (check files are binary), re-run `batsim trace` to rebuild them")
+            }
             bincode::deserialize_from(reader)
                 .context("unable to deserialise binary traces (check files are binary)")
         }
@@ -558,6 +572,7 @@ mod tests {
                             lid: "a".to_string(),
                             distance: 1.0,
                             node: (0.0, 0.0),
+                            rise: 0.0,
                         }),
                         Component::LinkType(Link {
                             start_time: 2,
@@ -565,6 +580,7 @@ mod tests {
                             lid: "b".to_string(),
                             distance: 0.5,
                             node: (0.0, 0.0),
+                            rise: 0.0,
                         }),
                         Component::ActivityType(Activity {
                             start_time: 3,
@@ -608,4 +624,20 @@ mod tests {
 
         assert_eq!(population, new_pop)
     }
+
+    #[test]
+    fn test_deserialise_binary_without_header() {
+        let population = test_pop();
+
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("tmp.txt");
+        let out_file = File::create(&file_path).unwrap();
+        // binary traces written by previous versions have no header
+        bincode::serialize_into(out_file, &population).unwrap();
+
+        let in_file = File::open(&file_path).unwrap();
+        let reader = BufReader::new(in_file);
+        let error = Population::deserialise(reader, false).unwrap_err();
+        assert!(error.to_string().contains("re-run `batsim trace`"));
+    }
 }
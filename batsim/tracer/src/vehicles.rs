@@ -0,0 +1,311 @@
+use anyhow::{bail, Result};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{Activity, Component, Link, Person, Population, Trace};
+
+/// Driver of a vehicle trace component, keyed by component time window and link or activity
+#[derive(Debug, PartialEq)]
+struct Driver {
+    start_time: u32,
+    end_time: u32,
+    component: String,
+    pid: String,
+}
+
+impl Driver {
+    /// Check if given time is within the component, components of no duration contain their start
+    fn contains(&self, time: u32) -> bool {
+        time == self.start_time || (self.start_time..self.end_time).contains(&time)
+    }
+}
+
+/// Persons driving (or parking) each vehicle, used to attribute vehicle charge events to persons
+#[derive(Debug, Default)]
+pub struct Drivers {
+    // drivers of vehicles of a single person
+    single: HashMap<String, String>,
+    // drivers of each component of shared vehicles
+    shared: HashMap<String, Vec<Driver>>,
+    /// Number of vehicles with overlapping trips (by different drivers)
+    pub conflicts: usize,
+}
+
+impl Drivers {
+    /// Driver of the vehicle component (link or activity) at given time, such as the start time
+    /// of a charge event, which may start after the component starts (for example delayed charging)
+    pub fn driver(&self, vehicle: &str, time: u32, component: &str) -> Option<&str> {
+        match self.shared.get(vehicle) {
+            Some(drivers) => drivers
+                .iter()
+                .find(|driver| driver.component == component && driver.contains(time))
+                .map(|driver| driver.pid.as_str()),
+            None => self.single.get(vehicle).map(String::as_str),
+        }
+    }
+}
+
+/// A trip (consecutive links) of a person, and the activity at which the vehicle is then parked
+struct Trip {
+    pid: String,
+    links: Vec<Link>,
+    parked: Activity,
+}
+
+impl Trip {
+    fn start_time(&self) -> u32 {
+        self.links[0].start_time
+    }
+    fn end_time(&self) -> u32 {
+        self.links[self.links.len() - 1].end_time
+    }
+}
+
+impl Population {
+    /// Merge the traces of persons sharing a vehicle, given by a person attribute (such as a
+    /// household or vehicle id), into a population of vehicles keyed by attribute value. Persons
+    /// without the attribute keep their own vehicle (keyed by pid).
+    ///
+    /// Vehicle traces are the trips (links) of all members in time order, each followed by the
+    /// activity of its driver, at which the vehicle is parked until the next trip. Vehicle
+    /// attributes are the attributes shared (with the same value) by all members. Vehicles of a
+    /// single person keep the person's trace unchanged.
+    pub fn into_vehicles(self, attribute: &str) -> Result<(Population, Drivers)> {
+        let mut members: BTreeMap<String, Vec<(String, Person)>> = BTreeMap::new();
+        for (pid, person) in self.people {
+            let vehicle = match person.attributes.get(attribute) {
+                Some(value) => value.clone(),
+                None => pid.clone(),
+            };
+            members.entry(vehicle).or_default().push((pid, person));
+        }
+        let mut people = BTreeMap::new();
+        let mut drivers = Drivers::default();
+        for (vehicle, mut members) in members {
+            if members.len() == 1 {
+                let (pid, person) = members.pop().unwrap();
+                drivers.single.insert(vehicle.clone(), pid);
+                people.insert(vehicle, person);
+                continue;
+            }
+            if members
+                .iter()
+                .any(|(pid, person)| *pid == vehicle && !person.attributes.contains_key(attribute))
+            {
+                bail!("vehicle id '{}' is also a person id", vehicle)
+            }
+            let attributes = members[0]
+                .1
+                .attributes
+                .iter()
+                .filter(|(key, value)| {
+                    members
+                        .iter()
+                        .all(|(_, person)| person.attributes.get(*key) == Some(*value))
+                })
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect();
+
+            let mut trips: Vec<Trip> = members
+                .iter()
+                .flat_map(|(pid, person)| trips(pid, &person.trace))
+                .collect();
+            let trace = match trips.is_empty() {
+                // no member drives, keep the first member's activities
+                true => members.swap_remove(0).1.trace,
+                false => {
+                    trips.sort_by_key(|trip| trip.start_time());
+                    if trips
+                        .windows(2)
+                        .any(|pair| pair[1].start_time() < pair[0].end_time())
+                    {
+                        drivers.conflicts += 1;
+                    }
+                    merge(trips, drivers.shared.entry(vehicle.clone()).or_default())
+                }
+            };
+            people.insert(vehicle, Person { attributes, trace });
+        }
+        Ok((Population { people }, drivers))
+    }
+}
+
+// split a trace into trips, each followed by the next activity (wrapping to the start of the trace)
+fn trips(pid: &str, trace: &Trace) -> Vec<Trip> {
+    let mut trips = Vec::new();
+    let mut links: Vec<Link> = Vec::new();
+    let n = trace.plan.len();
+    if n == 0 {
+        return trips;
+    }
+    let start = trace
+        .plan
+        .iter()
+        .position(|component| matches!(component, Component::ActivityType(_)))
+        .unwrap_or(0);
+    // iterate from the first activity (and back to it) so that trips wrapping the day are complete
+    for i in (start..=start + n).map(|i| i % n) {
+        match &trace.plan[i] {
+            Component::LinkType(link) => links.push(link.clone()),
+            Component::ActivityType(activity) if !links.is_empty() => trips.push(Trip {
+                pid: pid.to_string(),
+                links: std::mem::take(&mut links),
+                parked: activity.clone(),
+            }),
+            Component::ActivityType(_) => (),
+        }
+    }
+    trips
+}
+
+// merge time ordered trips into a vehicle trace, parked activities end at the start of the next
+// trip (or the first trip of the next day)
+fn merge(trips: Vec<Trip>, drivers: &mut Vec<Driver>) -> Trace {
+    let mut plan = Vec::new();
+    let next_starts: Vec<u32> = trips
+        .iter()
+        .skip(1)
+        .map(Trip::start_time)
+        .chain(std::iter::once(trips[0].start_time() + 24 * 60 * 60))
+        .collect();
+    for (trip, next_start) in trips.into_iter().zip(next_starts) {
+        let start_time = trip.end_time();
+        for link in trip.links {
+            drivers.push(Driver {
+                start_time: link.start_time,
+                end_time: link.end_time,
+                component: link.lid.clone(),
+                pid: trip.pid.clone(),
+            });
+            plan.push(Component::LinkType(link));
+        }
+        // overlapping trips are parked for no time
+        let end_time = next_start.max(start_time);
+        drivers.push(Driver {
+            start_time,
+            end_time,
+            component: trip.parked.act.clone(),
+            pid: trip.pid,
+        });
+        plan.push(Component::ActivityType(Activity {
+            start_time,
+            end_time,
+            ..trip.parked
+        }));
+    }
+    Trace { plan }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn activity(act: &str, start_time: u32, end_time: u32) -> Component {
+        Component::ActivityType(Activity {
+            start_time,
+            end_time,
+            act: act.to_string(),
+            node: (0.0, 0.0),
+        })
+    }
+
+    fn link(lid: &str, start_time: u32, end_time: u32) -> Component {
+        Component::LinkType(Link {
+            start_time,
+            end_time,
+            lid: lid.to_string(),
+            distance: 1000.0,
+            node: (0.0, 0.0),
+            rise: 0.0,
+        })
+    }
+
+    fn person(household: Option<&str>, plan: Vec<Component>) -> Person {
+        let mut attributes = HashMap::from([("income".to_string(), "high".to_string())]);
+        if let Some(household) = household {
+            attributes.insert("hid".to_string(), household.to_string());
+        }
+        Person {
+            attributes,
+            trace: Trace { plan },
+        }
+    }
+
+    #[test]
+    fn test_merge_household_vehicle() {
+        // a drives to work and back, b drives to shop and back in between
+        let a = person(
+            Some("h"),
+            vec![
+                link("a1", 100, 200),
+                activity("work", 200, 1000),
+                link("a2", 1000, 1100),
+                activity("home", 1100, 86500),
+            ],
+        );
+        let b = person(
+            Some("h"),
+            vec![
+                activity("home", 0, 1200),
+                link("b1", 1200, 1300),
+                activity("shop", 1300, 1400),
+                link("b2", 1400, 1500),
+                activity("home", 1500, 86400),
+            ],
+        );
+        let c = person(None, vec![activity("home", 0, 86400)]);
+        let population = Population {
+            people: BTreeMap::from([
+                ("a".to_string(), a),
+                ("b".to_string(), b),
+                ("c".to_string(), c),
+            ]),
+        };
+        let (vehicles, drivers) = population.into_vehicles("hid").unwrap();
+        assert_eq!(vehicles.len(), 2);
+        assert_eq!(drivers.conflicts, 0);
+        let vehicle = &vehicles.people["h"];
+        assert_eq!(vehicle.attributes.get("income").unwrap(), "high");
+        assert_eq!(
+            vehicle.trace.plan,
+            vec![
+                link("a1", 100, 200),
+                activity("work", 200, 1000),
+                link("a2", 1000, 1100),
+                activity("home", 1100, 1200),
+                link("b1", 1200, 1300),
+                activity("shop", 1300, 1400),
+                link("b2", 1400, 1500),
+                activity("home", 1500, 86500),
+            ]
+        );
+        assert_eq!(drivers.driver("h", 1100, "home"), Some("a"));
+        // events can start within a component
+        assert_eq!(drivers.driver("h", 1150, "home"), Some("a"));
+        assert_eq!(drivers.driver("h", 86000, "home"), Some("b"));
+        assert_eq!(drivers.driver("h", 1200, "home"), None);
+        assert_eq!(drivers.driver("h", 1400, "b2"), Some("b"));
+        assert_eq!(drivers.driver("c", 0, "home"), Some("c"));
+        assert_eq!(vehicles.people["c"].trace.plan.len(), 1);
+    }
+
+    #[test]
+    fn test_overlapping_trips_conflict() {
+        let a = person(
+            Some("h"),
+            vec![link("a1", 100, 200), activity("work", 200, 86500)],
+        );
+        let b = person(
+            Some("h"),
+            vec![link("b1", 150, 250), activity("shop", 250, 86550)],
+        );
+        let population = Population {
+            people: BTreeMap::from([("a".to_string(), a), ("b".to_string(), b)]),
+        };
+        let (vehicles, drivers) = population.into_vehicles("hid").unwrap();
+        assert_eq!(drivers.conflicts, 1);
+        assert_eq!(
+            vehicles.people["h"].trace.plan[1],
+            activity("work", 200, 200)
+        );
+    }
+}
@@ -1,11 +1,11 @@
 use anyhow::{Context, Result};
-use flate2::bufread::GzDecoder;
-use quick_xml::{events::BytesStart, Reader};
+use flate2::{bufread::GzDecoder, write::GzEncoder, Compression};
+use quick_xml::{events::BytesStart, Reader, Writer};
 use std::{
     borrow::Cow,
     fmt::Error,
     fs::File,
-    io::{BufRead, BufReader},
+    io::{BufRead, BufReader, BufWriter, Write},
     path::Path,
     str,
 };
@@ -50,6 +50,66 @@ pub fn reader(path: impl AsRef<Path>) -> Result<Reader<Box<dyn BufRead>>> {
     }
 }
 
+/// Underlying output of an xml writer, optionally gzip compressed
+pub enum XmlOutput {
+    Plain(BufWriter<File>),
+    Gz(GzEncoder<BufWriter<File>>),
+}
+
+impl XmlOutput {
+    /// Complete the file, writing the gzip trailer (if compressed) and flushing to disk
+    pub fn finish(self) -> std::io::Result<()> {
+        match self {
+            XmlOutput::Plain(mut writer) => writer.flush(),
+            XmlOutput::Gz(gz_encoder) => gz_encoder.finish()?.flush(),
+        }
+    }
+}
+
+impl Write for XmlOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            XmlOutput::Plain(writer) => writer.write(buf),
+            XmlOutput::Gz(gz_encoder) => gz_encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            XmlOutput::Plain(writer) => writer.flush(),
+            XmlOutput::Gz(gz_encoder) => gz_encoder.flush(),
+        }
+    }
+}
+
+/// Create an xml writer for given path, gzip compressed if the path has a 'gz' extension.
+/// Note that the underlying output must be finished (`XmlOutput::finish`) to complete the file.
+pub fn writer(path: impl AsRef<Path>) -> Result<Writer<XmlOutput>> {
+    let path = path.as_ref();
+    let extension = path
+        .extension()
+        .ok_or(BatsimXmlError::NoFileExtension)
+        .context(format!(
+            "'{}' has no file extension, expecting either 'xml' or 'xml.gz'",
+            path.display()
+        ))?;
+
+    if extension == "xml" {
+        let file = File::create(path).context(format!("unable to create '{}'", path.display()))?;
+        let writer = XmlOutput::Plain(BufWriter::new(file));
+        Ok(Writer::new_with_indent(writer, b'\t', 1))
+    } else if extension == "gz" {
+        let file = File::create(path).context(format!("unable to create '{}'", path.display()))?;
+        let gz_encoder = GzEncoder::new(BufWriter::new(file), Compression::default());
+        Ok(Writer::new_with_indent(XmlOutput::Gz(gz_encoder), b'\t', 1))
+    } else {
+        Err(BatsimXmlError::UnknownFileExtension).context(format!(
+            "unknown file extension '{}', expecting either 'xml' or 'xml.gz'",
+            path.display()
+        ))
+    }
+}
+
 /// Retrieve the value associated with a specific key on an XML element.
 pub fn get_attribute<'b>(key: &[u8], event: &'b BytesStart) -> Result<Cow<'b, [u8]>, Error> {
     let mut attributes = event.attributes();
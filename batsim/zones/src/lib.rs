@@ -0,0 +1,210 @@
+use anyhow::{Context, Result};
+use geo::{BoundingRect, Geometry, Intersects, MultiPolygon, Point};
+use geojson::GeoJson;
+use rstar::{
+    primitives::{GeomWithData, Rectangle},
+    RTree,
+};
+use std::{fs::File, io::BufReader, io::Read, path::Path, str::FromStr};
+use thiserror::Error;
+use wkt::Wkt;
+
+#[derive(Error, Debug)]
+pub enum ZonesError {
+    #[error("file missing extension")]
+    NoFileExtension,
+
+    #[error("unknown extension")]
+    UnknownFileExtension,
+
+    #[error("zone '{0}' is missing field '{1}'")]
+    MissingField(usize, String),
+
+    #[error("zone '{0}' geometry is not a polygon or multipolygon")]
+    NotPolygon(String),
+}
+
+type ZoneBounds = GeomWithData<Rectangle<[f64; 2]>, usize>;
+
+/// Collection of named polygon zones, spatially indexed by their bounding boxes
+pub struct Zones {
+    ids: Vec<String>,
+    polygons: Vec<MultiPolygon<f64>>,
+    index: RTree<ZoneBounds>,
+}
+
+impl Zones {
+    /// Load zones from either a GeoJSON ('geojson' or 'json') or WKT CSV ('csv') file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path to zones file
+    /// * `field` - Name of the property (GeoJSON) or column (CSV) holding the zone id
+    ///
+    pub fn load(path: impl AsRef<Path>, field: &str) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).context(format!("unable to open '{}'", path.display()))?;
+        let reader = BufReader::new(file);
+        let extension = path
+            .extension()
+            .ok_or(ZonesError::NoFileExtension)
+            .context(format!(
+                "'{}' has no file extension, expecting either 'geojson', 'json' or 'csv'",
+                path.display()
+            ))?;
+
+        if extension == "geojson" || extension == "json" {
+            Zones::from_geojson(reader, field)
+                .context(format!("failed to read zones from '{}'", path.display()))
+        } else if extension == "csv" {
+            Zones::from_wkt_csv(reader, field)
+                .context(format!("failed to read zones from '{}'", path.display()))
+        } else {
+            Err(ZonesError::UnknownFileExtension).context(format!(
+                "unknown file extension '{}', expecting either 'geojson', 'json' or 'csv'",
+                path.display()
+            ))
+        }
+    }
+
+    /// Read zones from a GeoJSON feature collection, zone ids are taken from the given property,
+    /// falling back to the feature id
+    pub fn from_geojson(reader: impl Read, field: &str) -> Result<Self> {
+        let geojson = GeoJson::from_reader(reader).context("failed to parse geojson")?;
+        let collection = geojson::FeatureCollection::try_from(geojson)
+            .context("expected a geojson feature collection")?;
+        let mut zones = Vec::new();
+        for (i, feature) in collection.features.into_iter().enumerate() {
+            let id = match (feature.property(field), &feature.id) {
+                (Some(serde_json::Value::String(id)), _) => id.clone(),
+                (Some(value), _) => value.to_string(),
+                (None, Some(geojson::feature::Id::String(id))) => id.clone(),
+                (None, Some(geojson::feature::Id::Number(id))) => id.to_string(),
+                (None, None) => return Err(ZonesError::MissingField(i, field.to_string()).into()),
+            };
+            let geometry = feature
+                .geometry
+                .context(format!("zone '{}' has no geometry", id))?;
+            let geometry = Geometry::<f64>::try_from(geometry)
+                .context(format!("failed to read zone '{}' geometry", id))?;
+            zones.push((id, geometry));
+        }
+        Zones::build(zones)
+    }
+
+    /// Read zones from a CSV with an id column and a 'geometry' column of WKT polygons
+    pub fn from_wkt_csv(reader: impl Read, field: &str) -> Result<Self> {
+        let mut reader = csv::Reader::from_reader(reader);
+        let headers = reader.headers()?.clone();
+        let id_column = headers
+            .iter()
+            .position(|h| h == field)
+            .context(format!("zones csv is missing id column '{}'", field))?;
+        let geometry_column = headers
+            .iter()
+            .position(|h| h == "geometry")
+            .context("zones csv is missing 'geometry' column")?;
+        let mut zones = Vec::new();
+        for row in reader.records() {
+            let row = row?;
+            let id = row[id_column].to_string();
+            let geometry = Wkt::<f64>::from_str(&row[geometry_column])
+                .map_err(anyhow::Error::msg)
+                .context(format!("failed to parse zone '{}' wkt", id))?;
+            let geometry = Geometry::<f64>::try_from(geometry)
+                .map_err(|e| anyhow::anyhow!("{:?}", e))
+                .context(format!("failed to read zone '{}' geometry", id))?;
+            zones.push((id, geometry));
+        }
+        Zones::build(zones)
+    }
+
+    fn build(zones: Vec<(String, Geometry<f64>)>) -> Result<Self> {
+        let mut ids = Vec::with_capacity(zones.len());
+        let mut polygons = Vec::with_capacity(zones.len());
+        let mut bounds = Vec::with_capacity(zones.len());
+        for (i, (id, geometry)) in zones.into_iter().enumerate() {
+            let polygon = match geometry {
+                Geometry::Polygon(polygon) => MultiPolygon::new(vec![polygon]),
+                Geometry::MultiPolygon(polygon) => polygon,
+                _ => return Err(ZonesError::NotPolygon(id).into()),
+            };
+            if let Some(rect) = polygon.bounding_rect() {
+                bounds.push(GeomWithData::new(
+                    Rectangle::from_corners(rect.min().x_y().into(), rect.max().x_y().into()),
+                    i,
+                ));
+            }
+            ids.push(id);
+            polygons.push(polygon);
+        }
+        Ok(Zones {
+            ids,
+            polygons,
+            index: RTree::bulk_load(bounds),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Find the id of the zone containing the given point.
+    /// Points on a shared boundary are assigned to the first zone read.
+    pub fn find(&self, x: f64, y: f64) -> Option<&str> {
+        let point = Point::new(x, y);
+        self.index
+            .locate_all_at_point(&[x, y])
+            .map(|bounds| bounds.data)
+            .filter(|i| self.polygons[*i].intersects(&point))
+            .min()
+            .map(|i| self.ids[i].as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn data_path(name: &str) -> PathBuf {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("tests/data");
+        path.push(name);
+        path
+    }
+
+    #[test]
+    fn zones_load_from_geojson() {
+        let zones = Zones::load(data_path("zones.geojson"), "id").unwrap();
+        assert_eq!(zones.len(), 2);
+        assert_eq!(zones.find(100., 100.), Some("west"));
+        assert_eq!(zones.find(10000., 100.), Some("east"));
+        assert_eq!(zones.find(-100., 100.), None);
+    }
+
+    #[test]
+    fn zones_load_from_wkt_csv() {
+        let zones = Zones::load(data_path("zones.csv"), "id").unwrap();
+        assert_eq!(zones.len(), 2);
+        assert_eq!(zones.find(100., 100.), Some("west"));
+        assert_eq!(zones.find(10000., 100.), Some("east"));
+        assert_eq!(zones.find(100., 20000.), None);
+    }
+
+    #[test]
+    fn shared_boundary_uses_first_zone() {
+        let zones = Zones::load(data_path("zones.csv"), "id").unwrap();
+        assert_eq!(zones.find(5000., 100.), Some("west"));
+    }
+
+    #[test]
+    fn missing_id_field_errors() {
+        assert!(Zones::load(data_path("zones.csv"), "name").is_err());
+        assert!(Zones::load(data_path("zones.geojson"), "name").is_err());
+    }
+}